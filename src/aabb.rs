@@ -2,8 +2,8 @@ use crate::common::{ff_max, ff_min, Interval};
 use crate::ray::Ray;
 use crate::vec3::Point;
 
-#[derive(Default)]
-struct AABB {
+#[derive(Default, Copy, Clone)]
+pub struct AABB {
     x: Interval,
     y: Interval,
     z: Interval
@@ -29,6 +29,34 @@ impl AABB {
         }
     }
 
+    pub fn new_with_box(box0: &AABB, box1: &AABB) -> Self {
+        // 合并两个包围盒，得到同时包住两者的最小包围盒
+        AABB {
+            x: Interval::new_with_interval(&box0.x, &box1.x),
+            y: Interval::new_with_interval(&box0.y, &box1.y),
+            z: Interval::new_with_interval(&box0.z, &box1.z)
+        }
+    }
+
+    pub fn corners(&self) -> [Point; 8] {
+        let mut corners = [Point::default(); 8];
+        for (n, corner) in corners.iter_mut().enumerate() {
+            let x = if n & 1 == 0 { self.x.min } else { self.x.max };
+            let y = if n & 2 == 0 { self.y.min } else { self.y.max };
+            let z = if n & 4 == 0 { self.z.min } else { self.z.max };
+            *corner = Point::new(x, y, z);
+        }
+        corners
+    }
+
+    // 包围球的球心与半径
+    pub fn bounding_sphere(&self) -> (Point, f64) {
+        let min = Point::new(self.x.min, self.y.min, self.z.min);
+        let max = Point::new(self.x.max, self.y.max, self.z.max);
+        let center = (min + max) * 0.5;
+        (center, (max - center).length())
+    }
+
     pub fn axis(&self, n: i32) -> &Interval {
         if n == 1 { return &self.y }
         if n == 2 { return &self.z }
@@ -52,16 +80,14 @@ impl AABB {
 
     pub fn hit(&self, r: &Ray, ray_t:& mut Interval) -> bool {
         for a in 0..3 {
-            let invD = 1.0 / r.direction().get(a as usize).unwrap();
+            let inv_d = 1.0 / r.direction().get(a as usize).unwrap();
             let orig = r.original().get(a as usize).unwrap();
 
-            let mut t0 = (self.axis(a).min - orig) * invD;
-            let mut t1 = (self.axis(a).max - orig) * invD;
+            let mut t0 = (self.axis(a).min - orig) * inv_d;
+            let mut t1 = (self.axis(a).max - orig) * inv_d;
 
-            if invD < 0.0 {
-                let tmp = t0;
-                t0 = t1;
-                t1 = tmp;
+            if inv_d < 0.0 {
+                std::mem::swap(&mut t0, &mut t1);
             }
 
             if t0 > ray_t.min { ray_t.min = t0; }
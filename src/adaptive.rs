@@ -0,0 +1,180 @@
+use std::fs::File;
+use std::io::{self, Write};
+use crate::vec3::Color;
+
+/*
+自适应采样参数：每个像素至少渲染min_samples个样本，之后当像素均值的标准误差
+(在gamma校正后的显示空间亮度上度量)不超过threshold时停止，最多max_samples个样本
+ */
+#[derive(Debug, Clone)]
+pub struct AdaptiveSampling {
+    pub min_samples: i32,
+    pub max_samples: i32,
+    // 显示空间中可接受的标准误差，0.01约为±2.5/255
+    pub threshold: f64,
+    // 输出每像素样本数的调试图像路径
+    pub sample_map: Option<String>
+}
+
+impl Default for AdaptiveSampling {
+    fn default() -> Self {
+        AdaptiveSampling {
+            min_samples: 16,
+            max_samples: 400,
+            threshold: 0.01,
+            sample_map: None
+        }
+    }
+}
+
+impl AdaptiveSampling {
+    pub fn converged(&self, stats: &PixelStats) -> bool {
+        if stats.get_count() < self.min_samples {
+            return false;
+        }
+        stats.standard_error() <= self.threshold
+    }
+}
+
+/*
+像素样本统计：颜色之和，以及显示空间亮度的滑动均值与方差(Welford算法)
+ */
+pub struct PixelStats {
+    count: i32,
+    sum: Color,
+    mean: f64,
+    m2: f64
+}
+
+impl Default for PixelStats {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl PixelStats {
+    pub fn new() -> PixelStats {
+        PixelStats {
+            count: 0,
+            sum: Color::default(),
+            mean: 0.0,
+            m2: 0.0
+        }
+    }
+
+    pub fn add(&mut self, color: Color) {
+        self.count += 1;
+        self.sum += color;
+        // 与write_color一致的gamma校正与截断
+        let value = color.luminance().max(0.0).sqrt().min(1.0);
+        let delta = value - self.mean;
+        self.mean += delta / f64::from(self.count);
+        self.m2 += delta * (value - self.mean);
+    }
+
+    pub fn get_count(&self) -> i32 {
+        self.count
+    }
+
+    pub fn get_sum(&self) -> Color {
+        self.sum
+    }
+
+    // 像素均值的标准误差
+    pub fn standard_error(&self) -> f64 {
+        if self.count < 2 {
+            return f64::INFINITY;
+        }
+        let n = f64::from(self.count);
+        (self.m2 / (n - 1.0) / n).sqrt()
+    }
+}
+
+/*
+将每像素样本数写为灰度PPM，白色为max_samples
+ */
+pub fn write_sample_map(path: &str, counts: &[i32], width: i32, height: i32, max_samples: i32) -> io::Result<()> {
+    let mut file = File::create(path)?;
+    file.write_all(format!("P3\n{} {}\n255\n", width, height).as_bytes())?;
+    for &count in counts {
+        let value = (255.0 * f64::from(count) / f64::from(max_samples.max(1))).round().min(255.0) as i32;
+        file.write_all(format!("{} {} {}\n", value, value, value).as_bytes())?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::rc::Rc;
+    use super::*;
+    use crate::camera::Camera;
+    use crate::material::Lambertian;
+    use crate::scene::Scene;
+    use crate::sphere::{HittableList, Sphere};
+    use crate::vec3::{Point, Vec3};
+
+    #[test]
+    fn stats_track_display_space_variance() {
+        let mut stats = PixelStats::new();
+        assert_eq!(stats.standard_error(), f64::INFINITY);
+        let values = [0.04, 0.16, 0.36, 0.64, 4.0];
+        for v in values {
+            stats.add(Color::new(v, v, v));
+        }
+        assert_eq!(stats.get_count(), 5);
+        assert!((stats.get_sum().x() - 5.2).abs() < 1e-12);
+        // 显示空间的值为开方后截断到1：0.2, 0.4, 0.6, 0.8, 1.0
+        let display = [0.2, 0.4, 0.6, 0.8, 1.0];
+        let mean = display.iter().sum::<f64>() / 5.0;
+        let variance = display.iter().map(|d| (d - mean) * (d - mean)).sum::<f64>() / 4.0;
+        assert!((stats.standard_error() - (variance / 5.0).sqrt()).abs() < 1e-12);
+    }
+
+    #[test]
+    fn convergence_waits_for_min_samples() {
+        let adaptive = AdaptiveSampling {
+            min_samples: 4,
+            ..AdaptiveSampling::default()
+        };
+        let mut stats = PixelStats::new();
+        for _ in 0..3 {
+            stats.add(Color::new(0.5, 0.5, 0.5));
+        }
+        assert!(!adaptive.converged(&stats));
+        stats.add(Color::new(0.5, 0.5, 0.5));
+        assert!(adaptive.converged(&stats));
+        stats.add(Color::new(1.0, 1.0, 1.0));
+        assert!(!adaptive.converged(&stats));
+    }
+
+    #[test]
+    fn noisy_pixels_get_more_samples() {
+        // 渐变天空下地面上的灰色球：天空像素几乎没有噪声，球与地面之间的间接光照有噪声
+        let mut world = HittableList::new();
+        let grey = Rc::new(Lambertian::new(Color::new(0.5, 0.5, 0.5)));
+        world.add(Box::new(Sphere::new(Point::new(0.0, -1000.0, 0.0), 1000.0, grey.clone())));
+        world.add(Box::new(Sphere::new(Point::new(0.0, 1.0, 0.0), 1.0, grey)));
+        let scene = Scene::new(Box::new(world));
+
+        let mut camera = Camera::new(8, 60.0, 1.0, 100, Point::new(0.0, 1.0, 4.0), Point::new(0.0, 1.0, 0.0),
+                                     Vec3::new(0.0, 1.0, 0.0), 4.0, 0.0);
+        let path = std::env::temp_dir().join("rayce_sample_map.ppm");
+        camera.set_adaptive(Some(AdaptiveSampling {
+            min_samples: 8,
+            max_samples: 128,
+            threshold: 0.01,
+            sample_map: Some(path.to_str().unwrap().to_string())
+        }));
+        let film = camera.render_film(&scene, &[]);
+
+        assert_eq!(film.get_sample_count(0, 0), 8);
+        assert!(film.get_sample_count(4, 6) > 8, "{}", film.get_sample_count(4, 6));
+        let map = fs::read_to_string(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        let lines: Vec<&str> = map.lines().collect();
+        assert_eq!(&lines[..3], ["P3", "8 8", "255"]);
+        // 8/128的样本数写为16
+        assert_eq!(lines[3], "16 16 16");
+    }
+}
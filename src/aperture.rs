@@ -0,0 +1,255 @@
+use std::io;
+use crate::common::PI;
+use crate::distribution::Distribution1D;
+use crate::imageio::load_ppm;
+use crate::vec3::{cross, Vec3};
+
+/*
+光圈形状，决定散景(bokeh)的形状。
+采样点与判定点都位于归一化的镜头平面上，光圈外接于单位圆
+ */
+pub trait Aperture {
+    // 由[0,1)^2的随机数得到光圈内的均匀(或按透光率加权)采样点
+    fn sample(&self, u: (f64, f64)) -> Vec3;
+
+    // 镜头平面上的点是否透光
+    fn contains(&self, p: Vec3) -> bool;
+
+    // 光圈在归一化镜头平面上的面积，采样不均匀(按透光率加权)的光圈为None
+    fn area(&self) -> Option<f64> {
+        None
+    }
+}
+
+/*
+命令行选择的光圈形状
+ */
+#[derive(Debug, Clone, PartialEq)]
+pub enum ApertureShape {
+    Circle,
+    // 叶片数与旋转角(角度制)
+    Polygon(usize, f64),
+    // 遮罩图像(.ppm/.pgm)路径
+    Image(String)
+}
+
+impl ApertureShape {
+    // "circle"、"<叶片数>[,<旋转角>]"或遮罩图像路径
+    pub fn from_name(name: &str) -> Option<ApertureShape> {
+        if name == "circle" || name == "circular" {
+            return Some(ApertureShape::Circle);
+        }
+        let (blades, rotation) = name.split_once(',').unwrap_or((name, "0"));
+        if let Ok(blades) = blades.trim().parse::<usize>() {
+            let rotation = rotation.trim().parse().ok()?;
+            return if blades >= 3 { Some(ApertureShape::Polygon(blades, rotation)) } else { None };
+        }
+        let lower = name.to_lowercase();
+        if lower.ends_with(".ppm") || lower.ends_with(".pgm") {
+            return Some(ApertureShape::Image(name.to_string()));
+        }
+        None
+    }
+
+    pub fn create(&self) -> io::Result<Box<dyn Aperture>> {
+        Ok(match self {
+            ApertureShape::Circle => Box::new(CircularAperture::new()),
+            ApertureShape::Polygon(blades, rotation) => Box::new(PolygonAperture::new(*blades, *rotation)),
+            ApertureShape::Image(path) => Box::new(ImageAperture::load(path)?)
+        })
+    }
+}
+
+/*
+理想圆形光圈
+ */
+pub struct CircularAperture;
+
+impl Default for CircularAperture {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl CircularAperture {
+    pub fn new() -> CircularAperture {
+        CircularAperture
+    }
+}
+
+impl Aperture for CircularAperture {
+    fn sample(&self, u: (f64, f64)) -> Vec3 {
+        Vec3::sample_unit_disk(u)
+    }
+
+    fn contains(&self, p: Vec3) -> bool {
+        p.x() * p.x() + p.y() * p.y() <= 1.0
+    }
+
+    fn area(&self) -> Option<f64> {
+        Some(PI)
+    }
+}
+
+/*
+n片光圈叶片组成的正多边形光圈
+ */
+pub struct PolygonAperture {
+    vertices: Vec<Vec3>
+}
+
+impl PolygonAperture {
+    // rotation为角度制
+    pub fn new(blades: usize, rotation: f64) -> PolygonAperture {
+        let blades = blades.max(3);
+        let rotation = rotation.to_radians();
+        let vertices = (0..blades).map(|k| {
+            let angle = rotation + 2.0 * PI * k as f64 / blades as f64;
+            Vec3::new(angle.cos(), angle.sin(), 0.0)
+        }).collect();
+        PolygonAperture {
+            vertices
+        }
+    }
+}
+
+impl Aperture for PolygonAperture {
+    fn sample(&self, u: (f64, f64)) -> Vec3 {
+        // 先按面积均匀选择一个三角形(中心与相邻两个顶点)，再在三角形内均匀采样
+        let n = self.vertices.len();
+        let scaled = u.0 * n as f64;
+        let k = (scaled as usize).min(n - 1);
+        let s = (scaled - k as f64).sqrt();
+        self.vertices[k] * (s * (1.0 - u.1)) + self.vertices[(k + 1) % n] * (s * u.1)
+    }
+
+    fn contains(&self, p: Vec3) -> bool {
+        let n = self.vertices.len();
+        (0..n).all(|k| {
+            let edge = self.vertices[(k + 1) % n] - self.vertices[k];
+            cross(edge, p - self.vertices[k]).z() >= 0.0
+        })
+    }
+
+    fn area(&self) -> Option<f64> {
+        // 各顶点在单位圆上，由n个以中心为顶点的等腰三角形组成
+        let n = self.vertices.len() as f64;
+        Some(n / 2.0 * (2.0 * PI / n).sin())
+    }
+}
+
+/*
+由图像定义的光圈遮罩，像素亮度即透光率，图像铺满[-1,1]^2
+ */
+pub struct ImageAperture {
+    width: usize,
+    height: usize,
+    weights: Vec<f64>,
+    distribution: Distribution1D
+}
+
+impl ImageAperture {
+    pub fn load(path: &str) -> io::Result<ImageAperture> {
+        let image = load_ppm(path)?;
+        let (width, height) = (image.get_width(), image.get_height());
+        let weights: Vec<f64> = (0..height)
+            .flat_map(|y| (0..width).map(move |x| (x, y)))
+            .map(|(x, y)| image.get_pixel(x, y).luminance())
+            .collect();
+        if weights.iter().all(|&w| w <= 0.0) {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "Aperture mask is completely black"));
+        }
+        Ok(ImageAperture {
+            width,
+            height,
+            distribution: Distribution1D::new(weights.clone()),
+            weights
+        })
+    }
+}
+
+impl Aperture for ImageAperture {
+    fn sample(&self, u: (f64, f64)) -> Vec3 {
+        // 连续采样的区间内偏移作为像素内的水平抖动
+        let (x, _, index) = self.distribution.sample_continuous(u.0);
+        let du = x * self.distribution.count() as f64 - index as f64;
+        let px = (index % self.width) as f64 + du;
+        let py = (index / self.width) as f64 + u.1;
+        Vec3::new(px / self.width as f64 * 2.0 - 1.0, 1.0 - py / self.height as f64 * 2.0, 0.0)
+    }
+
+    fn contains(&self, p: Vec3) -> bool {
+        let x = ((p.x() + 1.0) / 2.0 * self.width as f64).floor();
+        let y = ((1.0 - p.y()) / 2.0 * self.height as f64).floor();
+        if x < 0.0 || y < 0.0 || x >= self.width as f64 || y >= self.height as f64 {
+            return false;
+        }
+        self.weights[y as usize * self.width + x as usize] >= 0.5
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    // 0..n×0..n网格上的均匀样本
+    fn grid(n: usize) -> Vec<(f64, f64)> {
+        (0..n * n).map(|k| (((k % n) as f64 + 0.5) / n as f64, ((k / n) as f64 + 0.5) / n as f64)).collect()
+    }
+
+    #[test]
+    fn aperture_shape_from_name() {
+        assert_eq!(ApertureShape::from_name("circle"), Some(ApertureShape::Circle));
+        assert_eq!(ApertureShape::from_name("6"), Some(ApertureShape::Polygon(6, 0.0)));
+        assert_eq!(ApertureShape::from_name("5,18"), Some(ApertureShape::Polygon(5, 18.0)));
+        assert_eq!(ApertureShape::from_name("mask.PGM"), Some(ApertureShape::Image("mask.PGM".to_string())));
+        assert_eq!(ApertureShape::from_name("2"), None);
+        assert_eq!(ApertureShape::from_name("hexagon"), None);
+    }
+
+    #[test]
+    fn polygon_samples_stay_inside() {
+        let aperture = PolygonAperture::new(6, 15.0);
+        assert!(grid(32).into_iter().all(|u| aperture.contains(aperture.sample(u) * 0.999)));
+        // 正六边形面积 3√3/2
+        assert!((aperture.area().unwrap() - 1.5 * 3f64.sqrt()).abs() < 1e-12);
+    }
+
+    #[test]
+    fn polygon_area_matches_containment() {
+        // 在[-1,1]^2上数落入多边形的格点，估计面积
+        let aperture = PolygonAperture::new(5, 0.0);
+        let inside = grid(400).into_iter()
+            .filter(|&(x, y)| aperture.contains(Vec3::new(2.0 * x - 1.0, 2.0 * y - 1.0, 0.0)))
+            .count();
+        let estimate = 4.0 * inside as f64 / (400.0 * 400.0);
+        assert!((estimate - aperture.area().unwrap()).abs() < 0.01);
+    }
+
+    #[test]
+    fn image_aperture_samples_transparent_texels() {
+        // 2×2遮罩只有右上角透光
+        let path = std::env::temp_dir().join("rayce_aperture_mask.pgm");
+        fs::write(&path, "P2 2 2 255\n0 255\n0 0\n").unwrap();
+        let aperture = ImageAperture::load(path.to_str().unwrap()).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        for u in grid(16) {
+            let p = aperture.sample(u);
+            assert!(p.x() >= 0.0 && p.y() >= 0.0, "sample {:?} outside the open quadrant", (p.x(), p.y()));
+            assert!(aperture.contains(p));
+        }
+        assert!(!aperture.contains(Vec3::new(-0.5, 0.5, 0.0)));
+        assert!(aperture.area().is_none());
+    }
+
+    #[test]
+    fn black_image_aperture_is_rejected() {
+        let path = std::env::temp_dir().join("rayce_aperture_black.pgm");
+        fs::write(&path, "P2 1 1 255\n0\n").unwrap();
+        let result = ImageAperture::load(path.to_str().unwrap());
+        fs::remove_file(&path).unwrap();
+        assert!(result.is_err());
+    }
+}
@@ -0,0 +1,629 @@
+use crate::camera::Camera;
+use crate::common::{INFINITY, PI};
+use crate::film::SampleAovs;
+use crate::integrator::{default_background, Integrator, record_first_hit};
+use crate::ray::Ray;
+use crate::sampler::Sampler;
+use crate::scene::Scene;
+use crate::sphere::HitRecord;
+use crate::vec3::{Color, dot, Point, Vec3};
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+enum VertexType {
+    Camera,
+    // 光源上的点；相机路径逃逸出场景时也形成一个位于无穷远处的光源顶点
+    Light,
+    Surface
+}
+
+/*
+子路径上的顶点
+ */
+struct Vertex {
+    kind: VertexType,
+    p: Point,
+    // 表面法向量(朝向到达光线一侧)，非表面顶点为0
+    n: Vec3,
+    // 到达该顶点的光线，材质求值时使用
+    r_in: Ray,
+    rec: Option<HitRecord>,
+    // 光源顶点对应的光源序号，相机路径逃逸形成的顶点为None
+    light: Option<usize>,
+    // 无穷远光源，位置的面积密度以垂直于光线的发射圆盘度量
+    infinite: bool,
+    // 光源位置或方向为delta分布(点光源、聚光灯、平行光)
+    delta_light: bool,
+    // 从路径起点到该顶点的贡献(路径通量除以概率密度)
+    beta: Color,
+    // 在该顶点发生了镜面散射，不能与其他顶点连接
+    delta: bool,
+    // 以面积度量的概率密度：pdf_fwd为沿路径生成方向采样到该顶点，pdf_rev为从反方向采样到该顶点
+    pdf_fwd: f64,
+    pdf_rev: f64
+}
+
+impl Vertex {
+    fn new(kind: VertexType, p: Point, r_in: Ray, beta: Color) -> Vertex {
+        Vertex {
+            kind,
+            p,
+            n: Vec3::default(),
+            r_in,
+            rec: None,
+            light: None,
+            infinite: false,
+            delta_light: false,
+            beta,
+            delta: false,
+            pdf_fwd: 0.0,
+            pdf_rev: 0.0
+        }
+    }
+
+    fn surface(rec: HitRecord, r_in: &Ray, beta: Color) -> Vertex {
+        let mut vertex = Vertex::new(VertexType::Surface, rec.get_p(), copy_ray(r_in), beta);
+        vertex.n = rec.get_normal();
+        vertex.rec = Some(rec);
+        vertex
+    }
+
+    // 相机路径逃逸出场景，顶点取在光线方向上单位距离处
+    fn escaped(r: &Ray, beta: Color) -> Vertex {
+        let mut vertex = Vertex::new(VertexType::Light, r.original() + r.direction().unit_vector(), copy_ray(r), beta);
+        vertex.infinite = true;
+        vertex
+    }
+
+    fn light(ctx: &Context, index: usize, p: Point, beta: Color) -> Vertex {
+        let light = &ctx.scene.get_lights()[index];
+        let mut vertex = Vertex::new(VertexType::Light, p, Ray::default(), beta);
+        vertex.light = Some(index);
+        vertex.infinite = light.is_infinite();
+        vertex.delta_light = light.is_delta();
+        vertex
+    }
+
+    fn is_on_surface(&self) -> bool {
+        self.kind == VertexType::Surface
+    }
+
+    // 沿next方向离开该顶点的BSDF与cos项之积，非表面顶点为0
+    fn f(&self, next: &Vertex) -> Color {
+        match &self.rec {
+            Some(rec) => rec.get_material().eval(&self.r_in, rec, (next.p - self.p).unit_vector()),
+            None => Color::default()
+        }
+    }
+
+    // 相机路径到达该顶点时带回的自发光，逃逸时为无穷远光源的le，默认背景另行计算
+    fn le(&self, scene: &Scene) -> Color {
+        match &self.rec {
+            Some(rec) => rec.get_material().emitted(rec),
+            None if self.kind == VertexType::Light => scene.get_lights().iter()
+                .fold(Color::default(), |le, light| le + light.le(self.r_in.direction())),
+            None => Color::default()
+        }
+    }
+
+    // 该顶点的自发光能否由光源采样得到，自发光材质不能
+    fn is_sampleable_emitter(&self) -> bool {
+        self.kind == VertexType::Light
+    }
+
+    // 该顶点处的立体角概率密度换算为next处的面积概率密度
+    fn convert_density(&self, pdf: f64, next: &Vertex) -> f64 {
+        if next.infinite {
+            return pdf;
+        }
+        let w = next.p - self.p;
+        let distance_squared = w.length_squared();
+        if distance_squared == 0.0 {
+            return 0.0;
+        }
+        let mut pdf = pdf / distance_squared;
+        if next.is_on_surface() {
+            pdf *= dot(next.n, w / distance_squared.sqrt()).abs();
+        }
+        pdf
+    }
+
+    /*
+    光线从prev到达该顶点后散射到next的面积概率密度
+     */
+    fn pdf(&self, ctx: &Context, prev: Option<&Vertex>, next: &Vertex) -> f64 {
+        if self.kind == VertexType::Light {
+            return self.pdf_light(ctx, next);
+        }
+        let wn = next.p - self.p;
+        if wn.length_squared() == 0.0 {
+            return 0.0;
+        }
+        let pdf = match (&self.rec, self.kind) {
+            (_, VertexType::Camera) => ctx.camera.pdf_importance(self.p, wn).1,
+            (Some(rec), _) => {
+                let r_in = match prev {
+                    Some(prev) => Ray::new(prev.p, self.p - prev.p, self.r_in.get_time()),
+                    None => copy_ray(&self.r_in)
+                };
+                rec.get_material().pdf(&r_in, rec, wn.unit_vector())
+            }
+            _ => 0.0
+        };
+        self.convert_density(pdf, next)
+    }
+
+    // 光源顶点向next发光的面积概率密度
+    fn pdf_light(&self, ctx: &Context, next: &Vertex) -> f64 {
+        let w = next.p - self.p;
+        let distance_squared = w.length_squared();
+        if distance_squared == 0.0 {
+            return 0.0;
+        }
+        let w = w / distance_squared.sqrt();
+        let mut pdf = if self.infinite {
+            let radius = ctx.bounds.1;
+            1.0 / (PI * radius * radius)
+        } else {
+            match self.light {
+                Some(index) => ctx.scene.get_lights()[index].pdf_le(w, ctx.bounds).1 / distance_squared,
+                None => 0.0
+            }
+        };
+        if next.is_on_surface() {
+            pdf *= dot(next.n, w).abs();
+        }
+        pdf
+    }
+
+    // 光源子路径以该顶点为起点(并朝向next)的概率密度，包括选择光源的概率
+    fn pdf_light_origin(&self, ctx: &Context, next: &Vertex) -> f64 {
+        let w = (next.p - self.p).unit_vector();
+        if self.infinite {
+            return infinite_light_density(ctx, w);
+        }
+        match self.light {
+            Some(index) => ctx.scene.get_lights()[index].pdf_le(w, ctx.bounds).0 * ctx.light_pdf,
+            None => 0.0
+        }
+    }
+}
+
+struct Context<'a> {
+    camera: &'a Camera,
+    scene: &'a Scene,
+    // 场景包围球
+    bounds: (Point, f64),
+    // 均匀选择一个光源的概率
+    light_pdf: f64,
+    time: f64
+}
+
+/*
+双向路径追踪(Veach 1997)：分别从相机与光源生成子路径，
+把相机子路径的前t个顶点与光源子路径的前s个顶点连接成完整路径，
+所有(s, t)策略的结果按平衡启发式的多重重要性采样加权求和。
+t=1的策略把光源子路径直接连接到镜头(光线追踪)，贡献写入对应像素而不是当前像素，
+对焦散等相机路径难以找到光源的光照收敛得快得多。
+发光位置可被采样的光源(scene.get_lights())才参与光源子路径，
+自发光材质只能由相机路径击中
+ */
+pub struct BdptIntegrator {
+    max_depth: i32
+}
+
+impl BdptIntegrator {
+    pub fn new(max_depth: i32) -> BdptIntegrator {
+        BdptIntegrator {
+            max_depth
+        }
+    }
+}
+
+impl Integrator for BdptIntegrator {
+    fn li(&self, r: &Ray, camera: &Camera, scene: &Scene, sampler: &mut dyn Sampler, aovs: &mut SampleAovs) -> Color {
+        let light_count = scene.get_lights().len();
+        let ctx = Context {
+            camera,
+            scene,
+            bounds: scene.bounding_sphere(),
+            light_pdf: if light_count > 0 { 1.0 / light_count as f64 } else { 0.0 },
+            time: r.get_time()
+        };
+        let max_depth = self.max_depth.max(0) as usize;
+        let light_tracing = camera.supports_light_tracing();
+
+        let camera_path = camera_subpath(&ctx, r, sampler, max_depth + 2, light_tracing);
+        let light_path = light_subpath(&ctx, sampler, max_depth + 1);
+        if let Some(rec) = camera_path.get(1).and_then(|vertex| vertex.rec.as_ref()) {
+            record_first_hit(aovs, r, rec);
+        }
+
+        for t in 1..=camera_path.len() {
+            // s=1的光源顶点单独采样，光源子路径为空(所选光源没有发光)时也要计算
+            for s in 0..=light_path.len().max(1) {
+                let depth = s + t;
+                if (s == 1 && t == 1) || depth < 2 || depth - 2 > max_depth || (t == 1 && !light_tracing) {
+                    continue;
+                }
+                let (contribution, raster) = connect(&ctx, &light_path, &camera_path, s, t, sampler);
+                if contribution == Color::default() {
+                    continue;
+                }
+                match (raster, depth) {
+                    (Some((x, y)), _) => aovs.splats.push((x, y, contribution)),
+                    (None, 2) => aovs.emission += contribution,
+                    (None, 3) => aovs.direct += contribution,
+                    (None, _) => aovs.indirect += contribution
+                }
+            }
+        }
+
+        aovs.emission + aovs.direct + aovs.indirect
+    }
+}
+
+fn copy_ray(r: &Ray) -> Ray {
+    Ray::new(r.original(), r.direction(), r.get_time())
+}
+
+/*
+相机子路径：镜头上的起点加上沿相机光线随机游走得到的顶点。
+不支持光线追踪的相机将起点标记为delta，使t=1的策略不参与加权
+ */
+fn camera_subpath(ctx: &Context, r: &Ray, sampler: &mut dyn Sampler, max_vertices: usize, light_tracing: bool) -> Vec<Vertex> {
+    let mut path = Vec::with_capacity(max_vertices);
+    let mut start = Vertex::new(VertexType::Camera, r.original(), Ray::default(), Color::new(1.0, 1.0, 1.0));
+    start.delta = !light_tracing;
+    path.push(start);
+    let pdf_dir = if light_tracing { ctx.camera.pdf_importance(r.original(), r.direction()).1 } else { 1.0 };
+    random_walk(ctx, copy_ray(r), sampler, Color::new(1.0, 1.0, 1.0), pdf_dir, max_vertices - 1, true, &mut path);
+    path
+}
+
+/*
+光源子路径：均匀选择一个光源发出光线，再随机游走
+ */
+fn light_subpath(ctx: &Context, sampler: &mut dyn Sampler, max_vertices: usize) -> Vec<Vertex> {
+    let lights = ctx.scene.get_lights();
+    let mut path = Vec::with_capacity(max_vertices);
+    if lights.is_empty() {
+        return path;
+    }
+    let index = ((sampler.get_1d() * lights.len() as f64) as usize).min(lights.len() - 1);
+    let (u1, u2) = (sampler.get_2d(), sampler.get_2d());
+    let emission = match lights[index].sample_le(u1, u2, ctx.bounds) {
+        Some(emission) if emission.pdf_pos > 0.0 && emission.pdf_dir > 0.0 && emission.le != Color::default() => emission,
+        _ => return path
+    };
+
+    let mut start = Vertex::light(ctx, index, emission.origin, emission.le);
+    start.pdf_fwd = emission.pdf_pos * ctx.light_pdf;
+    let infinite = start.infinite;
+    path.push(start);
+    let beta = emission.le / (ctx.light_pdf * emission.pdf_pos * emission.pdf_dir);
+    let ray = Ray::new(emission.origin, emission.direction, ctx.time);
+    random_walk(ctx, ray, sampler, beta, emission.pdf_dir, max_vertices - 1, false, &mut path);
+
+    // 无穷远光源：第一个交点的密度由发射圆盘上的位置密度决定，起点的密度为方向密度
+    if infinite {
+        if path.len() > 1 {
+            let mut pdf = emission.pdf_pos;
+            if path[1].is_on_surface() {
+                pdf *= dot(emission.direction, path[1].n).abs();
+            }
+            path[1].pdf_fwd = pdf;
+        }
+        path[0].pdf_fwd = infinite_light_density(ctx, emission.direction);
+    }
+    path
+}
+
+/*
+从path最后一个顶点出发沿ray随机游走，最多再添加max_vertices个顶点。
+radiance为相机子路径，逃逸出场景时添加一个无穷远的光源顶点
+ */
+#[allow(clippy::too_many_arguments)]
+fn random_walk(ctx: &Context, ray: Ray, sampler: &mut dyn Sampler, beta: Color, pdf: f64,
+               max_vertices: usize, radiance: bool, path: &mut Vec<Vertex>) {
+    let mut ray = ray;
+    let mut beta = beta;
+    let mut pdf_fwd = pdf;
+    let mut vertices = 0;
+    while vertices < max_vertices {
+        let prev = path.len() - 1;
+        let mut rec = HitRecord::new_default();
+        if !ctx.scene.get_world().hit(&ray, 0.001, INFINITY, &mut rec) {
+            if radiance {
+                let mut vertex = Vertex::escaped(&ray, beta);
+                vertex.pdf_fwd = pdf_fwd;
+                path.push(vertex);
+            }
+            break;
+        }
+
+        let mut vertex = Vertex::surface(rec, &ray, beta);
+        vertex.pdf_fwd = path[prev].convert_density(pdf_fwd, &vertex);
+        path.push(vertex);
+        vertices += 1;
+        if vertices >= max_vertices {
+            break;
+        }
+
+        let current = path.len() - 1;
+        let rec = path[current].rec.as_ref().unwrap();
+        let material = rec.get_material();
+        let mut scattered = Ray::default();
+        let mut attenuation = Color::default();
+        if !material.scatter(&ray, rec, &mut attenuation, &mut scattered, sampler) {
+            break;
+        }
+        pdf_fwd = material.pdf(&ray, rec, scattered.direction());
+        // 反方向：光线沿散射方向的反方向到达，散射回上一个顶点
+        let reverse = Ray::new(rec.get_p(), -scattered.direction(), ray.get_time());
+        let mut pdf_rev = material.pdf(&reverse, rec, -ray.direction().unit_vector());
+        if pdf_fwd == 0.0 {
+            path[current].delta = true;
+            pdf_rev = 0.0;
+        }
+        beta *= attenuation;
+        path[prev].pdf_rev = path[current].convert_density(pdf_rev, &path[prev]);
+        ray = scattered;
+    }
+}
+
+/*
+连接光源子路径的前s个顶点与相机子路径的前t个顶点，返回加权后的贡献；
+t=1时同时返回贡献所在的图像坐标
+ */
+fn connect(ctx: &Context, light_path: &[Vertex], camera_path: &[Vertex], s: usize, t: usize,
+           sampler: &mut dyn Sampler) -> (Color, Option<(f64, f64)>) {
+    let black = (Color::default(), None);
+    // 逃逸的相机路径只能作为完整路径(s=0)
+    if t > 1 && s != 0 && camera_path[t - 1].kind == VertexType::Light {
+        return black;
+    }
+
+    let mut sampled = None;
+    let mut raster = None;
+    // 默认背景只有相机子路径逃逸(s=0)能得到，不参与MIS
+    let mut unweighted = Color::default();
+    let contribution = if s == 0 {
+        let pt = &camera_path[t - 1];
+        if pt.kind == VertexType::Light {
+            unweighted = pt.beta * default_background(&pt.r_in, ctx.scene);
+        }
+        pt.beta * pt.le(ctx.scene)
+    } else if t == 1 {
+        // 光线追踪：在镜头上采样一点连接光源子路径的末端
+        let qs = &light_path[s - 1];
+        if !qs.is_on_surface() {
+            return black;
+        }
+        let sample = match ctx.camera.sample_importance(qs.p, sampler.get_2d()) {
+            Some(sample) if sample.pdf > 0.0 && sample.importance > 0.0 => sample,
+            _ => return black
+        };
+        let vertex = Vertex::new(VertexType::Camera, sample.lens_point, Ray::default(),
+                                 Color::new(1.0, 1.0, 1.0) * (sample.importance / sample.pdf));
+        let contribution = qs.beta * qs.f(&vertex) * vertex.beta;
+        if contribution == Color::default() || !visible(ctx, qs.p, sample.wi, sample.distance) {
+            return black;
+        }
+        raster = Some(sample.raster);
+        sampled = Some(vertex);
+        contribution
+    } else if s == 1 {
+        // 对相机子路径的末端做光源采样
+        let pt = &camera_path[t - 1];
+        let lights = ctx.scene.get_lights();
+        if !pt.is_on_surface() || lights.is_empty() {
+            return black;
+        }
+        let index = ((sampler.get_1d() * lights.len() as f64) as usize).min(lights.len() - 1);
+        let sample = match lights[index].sample_li(pt.p, sampler.get_2d()) {
+            Some(sample) if sample.pdf > 0.0 && sample.li != Color::default() => sample,
+            _ => return black
+        };
+        // 无穷远光源的顶点放在场景包围球之外
+        let distance = if sample.distance.is_finite() { sample.distance } else { 2.0 * ctx.bounds.1 };
+        let mut vertex = Vertex::light(ctx, index, pt.p + sample.wi * distance, sample.li / (sample.pdf * ctx.light_pdf));
+        vertex.pdf_fwd = vertex.pdf_light_origin(ctx, pt);
+        let contribution = pt.beta * pt.f(&vertex) * vertex.beta;
+        if contribution == Color::default() || !visible(ctx, pt.p, sample.wi, sample.distance) {
+            return black;
+        }
+        sampled = Some(vertex);
+        contribution
+    } else {
+        let (qs, pt) = (&light_path[s - 1], &camera_path[t - 1]);
+        let w = qs.p - pt.p;
+        let distance_squared = w.length_squared();
+        if distance_squared == 0.0 {
+            return black;
+        }
+        let contribution = qs.beta * qs.f(pt) * pt.f(qs) * pt.beta / distance_squared;
+        let distance = distance_squared.sqrt();
+        if contribution == Color::default() || !visible(ctx, pt.p, w / distance, distance) {
+            return black;
+        }
+        contribution
+    };
+
+    if contribution == Color::default() {
+        return (unweighted, None);
+    }
+    let weight = mis_weight(ctx, light_path, camera_path, sampled.as_ref(), s, t);
+    (contribution * weight + unweighted, raster)
+}
+
+// 从p沿单位方向w到distance处之间没有遮挡
+fn visible(ctx: &Context, p: Point, w: Vec3, distance: f64) -> bool {
+    let shadow_ray = Ray::new(p, w, ctx.time);
+    let mut rec = HitRecord::new_default();
+    !ctx.scene.get_world().hit(&shadow_ray, 0.001, distance - 0.001, &mut rec)
+}
+
+/*
+所有无穷远光源沿传播方向w发光的立体角概率密度之和，包括选择光源的概率
+ */
+fn infinite_light_density(ctx: &Context, w: Vec3) -> f64 {
+    ctx.scene.get_lights().iter()
+        .filter(|light| light.is_infinite())
+        .map(|light| light.pdf_li(Point::default(), -w) * ctx.light_pdf)
+        .sum()
+}
+
+/*
+(s, t)策略的平衡启发式权重：
+沿两条子路径依次计算其他策略生成同一路径的概率密度与本策略之比ri，权重为1 / (1 + Σri)。
+连接处顶点的反向概率密度在这里临时计算，sampled为s=1或t=1时新采样的端点
+ */
+fn mis_weight(ctx: &Context, light_path: &[Vertex], camera_path: &[Vertex], sampled: Option<&Vertex>,
+              s: usize, t: usize) -> f64 {
+    if s + t == 2 {
+        return 1.0;
+    }
+    let pt = if t == 1 { sampled.unwrap() } else { &camera_path[t - 1] };
+    let qs = match s {
+        0 => None,
+        1 => sampled,
+        _ => Some(&light_path[s - 1])
+    };
+    let pt_minus = if t > 1 { Some(&camera_path[t - 2]) } else { None };
+    let qs_minus = if s > 1 { Some(&light_path[s - 2]) } else { None };
+
+    // 其他策略都无法生成该路径
+    if s == 0 && !pt.is_sampleable_emitter() {
+        return 1.0;
+    }
+
+    // (pdf_fwd, pdf_rev, delta)
+    let mut camera_pdfs: Vec<(f64, f64, bool)> = camera_path[..t].iter().map(|v| (v.pdf_fwd, v.pdf_rev, v.delta)).collect();
+    let mut light_pdfs: Vec<(f64, f64, bool)> = light_path.iter().take(s.saturating_sub(1)).map(|v| (v.pdf_fwd, v.pdf_rev, v.delta)).collect();
+    camera_pdfs[t - 1] = (pt.pdf_fwd, pt.pdf_rev, false);
+    if let Some(qs) = qs {
+        light_pdfs.push((qs.pdf_fwd, qs.pdf_rev, false));
+    }
+
+    camera_pdfs[t - 1].1 = match (qs, pt_minus) {
+        (Some(qs), _) => qs.pdf(ctx, qs_minus, pt),
+        (None, Some(pt_minus)) => pt.pdf_light_origin(ctx, pt_minus),
+        (None, None) => 0.0
+    };
+    if let Some(pt_minus) = pt_minus {
+        camera_pdfs[t - 2].1 = match qs {
+            Some(qs) => pt.pdf(ctx, Some(qs), pt_minus),
+            None => pt.pdf_light(ctx, pt_minus)
+        };
+    }
+    if let Some(qs) = qs {
+        light_pdfs[s - 1].1 = pt.pdf(ctx, pt_minus, qs);
+    }
+    if let (Some(qs), Some(qs_minus)) = (qs, qs_minus) {
+        light_pdfs[s - 2].1 = qs.pdf(ctx, Some(pt), qs_minus);
+    }
+
+    // delta顶点的概率密度为0，比值中按1处理
+    let remap = |pdf: f64| if pdf != 0.0 { pdf } else { 1.0 };
+    let mut sum = 0.0;
+    let mut ri = 1.0;
+    for i in (1..t).rev() {
+        ri *= remap(camera_pdfs[i].1) / remap(camera_pdfs[i].0);
+        if !camera_pdfs[i].2 && !camera_pdfs[i - 1].2 {
+            sum += ri;
+        }
+    }
+    ri = 1.0;
+    for i in (0..s).rev() {
+        ri *= remap(light_pdfs[i].1) / remap(light_pdfs[i].0);
+        let delta_light = if i > 0 {
+            light_pdfs[i - 1].2
+        } else if s == 1 {
+            qs.is_some_and(|qs| qs.delta_light)
+        } else {
+            light_path[0].delta_light
+        };
+        if !light_pdfs[i].2 && !delta_light {
+            sum += ri;
+        }
+    }
+    1.0 / (1.0 + sum)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::rc::Rc;
+    use super::*;
+    use crate::depth::DepthLimits;
+    use crate::film::Film;
+    use crate::integrator::PathIntegrator;
+    use crate::light::{PointLight, SunLight};
+    use crate::material::Lambertian;
+    use crate::sphere::{HittableList, Sphere};
+
+    // 地面上的灰色球，enclosed时用全吸收的外罩挡住天空
+    fn scene(enclosed: bool) -> Scene {
+        let mut world = HittableList::new();
+        let grey = Rc::new(Lambertian::new(Color::new(0.5, 0.5, 0.5)));
+        world.add(Box::new(Sphere::new(Point::new(0.0, -1000.0, 0.0), 1000.0, grey.clone())));
+        world.add(Box::new(Sphere::new(Point::new(0.0, 1.0, 0.0), 1.0, grey)));
+        if enclosed {
+            world.add(Box::new(Sphere::new(Point::default(), 100.0, Rc::new(Lambertian::new(Color::default())))));
+        }
+        Scene::new(Box::new(world))
+    }
+
+    fn mean(integrator: Box<dyn Integrator>, scene: &Scene, spp: i32) -> f64 {
+        let mut camera = Camera::new(8, 40.0, 1.0, spp, Point::new(0.0, 2.0, 5.0), Point::new(0.0, 1.0, 0.0),
+                                     Vec3::new(0.0, 1.0, 0.0), 5.0, 0.0);
+        camera.set_integrator(integrator);
+        let film: Film = camera.render_film(scene, &[]);
+        let mut sum = 0.0;
+        for y in 0..film.get_height() {
+            for x in 0..film.get_width() {
+                sum += film.get_pixel(x, y).luminance();
+            }
+        }
+        sum / f64::from(film.get_width() * film.get_height())
+    }
+
+    #[test]
+    fn bdpt_matches_path_tracing_under_point_light() {
+        let mut scene = scene(true);
+        scene.add_light(Box::new(PointLight::new(Point::new(1.0, 4.0, 2.0), Color::new(8.0, 8.0, 8.0))));
+        let path = mean(Box::new(PathIntegrator::new(DepthLimits::default(), true)), &scene, 256);
+        let bdpt = mean(Box::new(BdptIntegrator::new(8)), &scene, 64);
+        assert!((bdpt - path).abs() < 0.03 * path, "{} vs {}", bdpt, path);
+    }
+
+    #[test]
+    fn bdpt_reaches_background_without_lights() {
+        // 没有光源时只有相机子路径(s=0)击中背景的策略
+        let scene = scene(false);
+        let path = mean(Box::new(PathIntegrator::new(DepthLimits::default(), false)), &scene, 256);
+        let bdpt = mean(Box::new(BdptIntegrator::new(8)), &scene, 256);
+        assert!((bdpt - path).abs() < 0.02 * path, "{} vs {}", bdpt, path);
+    }
+
+    #[test]
+    fn bdpt_matches_path_tracing_under_sun_and_sky() {
+        // 没有环境光：渐变天空只能由相机子路径得到，太阳则各策略都能得到
+        let mut scene = scene(false);
+        scene.add_light(Box::new(SunLight::new(Vec3::new(0.5, 1.0, 0.3), Color::new(3.0, 3.0, 3.0), 5.0)));
+        let path = mean(Box::new(PathIntegrator::new(DepthLimits::default(), true)), &scene, 256);
+        let bdpt = mean(Box::new(BdptIntegrator::new(8)), &scene, 64);
+        assert!((bdpt - path).abs() < 0.03 * path, "{} vs {}", bdpt, path);
+    }
+
+    #[test]
+    fn depth_limit_bounds_path_length() {
+        let mut scene = scene(true);
+        scene.add_light(Box::new(PointLight::new(Point::new(1.0, 4.0, 2.0), Color::new(8.0, 8.0, 8.0))));
+        // max_depth为0时只剩直接看到光源的路径，点光源不可见
+        assert_eq!(mean(Box::new(BdptIntegrator::new(0)), &scene, 4), 0.0);
+        // 直接光照：与只做一次光源采样的路径追踪一致
+        let direct = mean(Box::new(BdptIntegrator::new(1)), &scene, 64);
+        let path = mean(Box::new(PathIntegrator::new(DepthLimits { max_depth: 1, ..DepthLimits::default() }, true)), &scene, 64);
+        assert!((direct - path).abs() < 0.03 * path, "{} vs {}", direct, path);
+    }
+}
@@ -1,11 +1,34 @@
-use std::cell::RefCell;
-use std::env::var;
-use std::fs::{File, remove_file};
-use std::io::Write;
-use crate::common::{degrees_to_radians, get_random_double, INFINITY};
+use crate::adaptive::{AdaptiveSampling, PixelStats, write_sample_map};
+use crate::aperture::{Aperture, CircularAperture};
+use crate::depth::DepthLimits;
+use crate::film::{Aov, Film, SampleAovs};
+use crate::filter::FilterType;
+use crate::integrator::{Integrator, PathIntegrator};
+use crate::common::{degrees_to_radians, INFINITY, PI, seed_pixel_sample};
+use crate::physical_camera::PhysicalCamera;
+use crate::projection::{CameraBasis, CameraModel, Cylindrical, Equirectangular, Fisheye, Orthographic, Perspective, Projection};
 use crate::ray::Ray;
+use crate::sampler::{Sampler, SamplerType};
+use crate::scene::Scene;
 use crate::sphere::{HitRecord, Hittable};
-use crate::vec3::{Color, cross, Point, Vec3};
+use crate::stereo::{Eye, StereoRig};
+use crate::vec3::{Color, cross, dot, Point, Vec3};
+
+/*
+从场景中的点向镜头采样的结果，用于光线追踪把光子路径连接到相机
+ */
+pub struct CameraSample {
+    // 从该点指向镜头采样点的单位方向
+    pub wi: Vec3,
+    pub lens_point: Point,
+    pub distance: f64,
+    // 镜头点沿-wi方向的重要性函数We
+    pub importance: f64,
+    // 以该点为中心的立体角概率密度
+    pub pdf: f64,
+    // 对应的连续图像坐标
+    pub raster: (f64, f64)
+}
 
 pub struct Camera {
     // Rendered image width
@@ -14,41 +37,46 @@ pub struct Camera {
     image_height: i32,
     // 视角 (广角，影响viewport_height与viewport_width)
     vfov:f64,
-    //  Point camera is looking from
-    look_from:Point,
-    // Point camera is looking at
-    look_at:Point,
-    // Camera-相对"up" 方向
-    vup:Vec3,
-    // Ratio of image width over height
-    aspect_ratio:f64,
     // 抽样
     samples_per_pixel:i32,
-    // 反射递归层数
-    max_depth:i32,
-    // 相机中心点(0,0,0)
-    center: Vec3,
-    // 视窗原点(0,0,-1),右手系规范,Y轴向上,X轴向右,摄像机看向方向为Z轴负方向
-    pixel00_loc: Vec3,
-    // 视窗向右(X轴)偏移单位向量，例如视窗宽度为2，width 800,单位向量为 (2/800,0,0)
-    pixel_delta_u: Vec3,
-    // 视窗向下(Y轴)偏移单位向量，例如视窗高度为1，height 600，单位向量为 (0,1/600,0)
-    pixel_delta_v: Vec3,
-    // Camera frame basis vectors
-    u: Vec3,
-    v: Vec3,
-    w: Vec3,
+    // 光传输算法
+    integrator: Box<dyn Integrator>,
+    // 相机坐标系,原点为lookfrom点,右手系规范,Y轴向上,X轴向右,摄像机看向方向为Z轴负方向
+    basis: CameraBasis,
+    // 投影模型，将图像坐标映射为相机光线
+    model: Box<dyn CameraModel>,
+    // 当前选择的投影方式，None表示由set_model设置的自定义模型(例如真实镜头)
+    projection: Option<Projection>,
+    // 光圈形状，决定散景形状
+    aperture: Box<dyn Aperture>,
+    // 猫眼渐晕强度，画面边缘处光圈被镜筒遮挡呈猫眼形，0表示关闭
+    cat_eye:f64,
     // 散焦角度
     defocus_angle:f64,
-    // 散焦水平半径
-    defocus_disk_u:Vec3,
-    // 散焦垂直半径
-    defocus_disk_v:Vec3,
     // 相机lookfrom点到屏幕焦距
     focus_dist:f64,
+    // 快门开启与关闭时刻，光线时间在此区间内均匀采样
+    shutter_open:f64,
+    shutter_close:f64,
+    // 立体渲染，左右眼图像拼接到同一张输出图像
+    stereo: Option<StereoRig>,
+    // 物理相机参数，设置后由其推导视角、散焦与曝光
+    physical: Option<PhysicalCamera>,
+    // 曝光系数，写出颜色前与辐射亮度相乘
+    exposure:f64,
+    // 样本生成方式
+    sampler: SamplerType,
+    // 渲染种子，像素样本的随机数流都由它派生
+    seed: u64,
+    // 自适应采样，None时每个像素固定samples_per_pixel个样本
+    adaptive: Option<AdaptiveSampling>,
+    // 像素重建滤波器及其半径
+    filter: FilterType,
+    filter_radius: f64,
 }
 
 impl Camera {
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
         width: i32, vfov: f64, aspect_ratio: f64, samples_per_pixel: i32,
         look_from: Point, look_at: Point, vup: Vec3,
@@ -58,31 +86,12 @@ impl Camera {
         let height = if height < 1 { 1 } else { height };
 
 
-        // Camera
-        let camera_center = look_from;
-        // Camera 视距 Z轴
-        let theta = degrees_to_radians(vfov);
-        //视窗高度
-        let viewport_height = 2.0 * (theta / 2.0).tan() * focus_dist;
-        //视窗宽度
-        let viewport_width = viewport_height * (f64::from(width) / f64::from(height));
-
         // Calculate the u,v,w unit basis vectors for the camera coordinate frame.
         let w = (look_from - look_at).unit_vector();
         let u = cross(vup,w).unit_vector();
         let v = cross(w, u);
+        let basis = CameraBasis { origin: look_from, u, v, w };
 
-        // Calculate the vectors across the horizontal and down the vertical viewport edges.
-        let viewport_u = u * viewport_width;
-        let viewport_v = (-v) * viewport_height;
-
-        // Calculate the horizontal and vertical delta vectors from pixel to pixel.
-        let pixel_delta_u = viewport_u / width;
-        let pixel_delta_v = viewport_v / height;
-
-        // Calculate the location of the upper left pixel.
-        let viewport_upper_left = camera_center - w * focus_dist
-            - viewport_u / 2 - viewport_v / 2;
         //  Q     ->△u
         //    P . . . . . .
         // ↓  . . . . . . .
@@ -90,108 +99,517 @@ impl Camera {
         //    . . . . . . .
         //    . . . . . . .
         //    . . . . . . .
-        // pixel00即是图中P点，P为视窗中心，视窗负责将3D 2k渲染画面投影到2D上
-        let pixel00_loc = viewport_upper_left + (pixel_delta_u + pixel_delta_v) * 0.5;
-
-        // 计算摄像机散镜片基本向量
-        let defocus_radius = focus_dist * degrees_to_radians(defocus_angle /2.0).tan();
-        let defocus_disk_u = u * defocus_radius;
-        let defocus_disk_v = v * defocus_radius;
+        // 视窗负责将3D渲染画面投影到2D上，图像坐标(s,t)对应视窗上从左上角Q开始的归一化位置
+        let model = Box::new(Perspective::new(vfov, f64::from(width) / f64::from(height), focus_dist, defocus_angle));
 
         Camera {
             image_height: height,
             image_width: width,
             vfov,
-            look_from,
-            look_at,
-            vup,
-            aspect_ratio,
             samples_per_pixel,
-            max_depth: 50,
-            center: camera_center,
-            pixel00_loc,
-            pixel_delta_u,
-            pixel_delta_v,
-            u,
-            v,
-            w,
+            integrator: Box::new(PathIntegrator::new(DepthLimits::default(), false)),
+            basis,
+            model,
+            projection: Some(Projection::Perspective),
+            aperture: Box::new(CircularAperture::new()),
+            cat_eye: 0.0,
             defocus_angle,
-            defocus_disk_u,
-            defocus_disk_v,
-            focus_dist
+            focus_dist,
+            shutter_open: 0.0,
+            shutter_close: 1.0,
+            stereo: None,
+            physical: None,
+            exposure: 1.0,
+            sampler: SamplerType::Independent,
+            seed: 0,
+            adaptive: None,
+            filter: FilterType::Box,
+            filter_radius: FilterType::Box.default_radius()
         }
     }
 
-    pub fn render(&self, world: &dyn Hittable) {
-        // Render
-        if let Err(err) = remove_file("image.ppm"){
-            if err.kind() != std::io::ErrorKind::NotFound {
-                println!("{:?}", err);
-            }
+    // 自定义投影模型，之后的set_physical不会替换它，光线追踪也不再假定薄透镜
+    pub fn set_model(&mut self, model: Box<dyn CameraModel>) {
+        self.model = model;
+        self.projection = None;
+    }
+
+    pub fn set_aperture(&mut self, aperture: Box<dyn Aperture>) {
+        self.aperture = aperture;
+    }
+
+    pub fn set_cat_eye(&mut self, strength: f64) {
+        self.cat_eye = strength;
+    }
+
+    pub fn set_projection(&mut self, projection: Projection) {
+        self.projection = Some(projection);
+        let aspect_ratio = f64::from(self.image_width) / f64::from(self.image_height);
+        self.model = match projection {
+            Projection::Perspective => Box::new(Perspective::new(self.vfov, aspect_ratio, self.focus_dist, self.defocus_angle)),
+            // 正交视窗大小与透视投影在焦平面处的视窗一致
+            Projection::Orthographic => Box::new(Orthographic::new(
+                2.0 * (degrees_to_radians(self.vfov) / 2.0).tan() * self.focus_dist, aspect_ratio)),
+            Projection::Fisheye => Box::new(Fisheye::new(180.0, aspect_ratio)),
+            Projection::Equirectangular => Box::new(Equirectangular::new()),
+            Projection::Cylindrical => Box::new(Cylindrical::new(360.0, aspect_ratio)),
+        };
+    }
+
+    pub fn set_shutter(&mut self, open: f64, close: f64) {
+        self.shutter_open = open;
+        self.shutter_close = close;
+    }
+
+    // 单眼图像的宽高比
+    pub fn get_aspect_ratio(&self) -> f64 {
+        f64::from(self.image_width) / f64::from(self.image_height)
+    }
+
+    pub fn get_shutter(&self) -> (f64, f64) {
+        (self.shutter_open, self.shutter_close)
+    }
+
+    pub fn set_stereo(&mut self, stereo: Option<StereoRig>) {
+        self.stereo = stereo;
+    }
+
+    pub fn get_focus_dist(&self) -> f64 {
+        self.focus_dist
+    }
+
+    pub fn set_focus_dist(&mut self, focus_dist: f64) {
+        // 物理相机重新对焦时光圈大小不变，散焦角随对焦距离变化
+        if let Some(physical) = &self.physical {
+            self.defocus_angle = physical.defocus_angle(focus_dist);
+        }
+        self.focus_dist = focus_dist;
+        self.model.set_focus(focus_dist, self.defocus_angle);
+    }
+
+    pub fn set_exposure(&mut self, exposure: f64) {
+        self.exposure = exposure;
+    }
+
+    pub fn set_integrator(&mut self, integrator: Box<dyn Integrator>) {
+        self.integrator = integrator;
+    }
+
+    pub fn set_sampler(&mut self, sampler: SamplerType) {
+        self.sampler = sampler;
+    }
+
+    pub fn set_seed(&mut self, seed: u64) {
+        self.seed = seed;
+    }
+
+    pub fn get_seed(&self) -> u64 {
+        self.seed
+    }
+
+    pub fn set_adaptive(&mut self, adaptive: Option<AdaptiveSampling>) {
+        self.adaptive = adaptive;
+    }
+
+    pub fn set_filter(&mut self, filter: FilterType, radius: f64) {
+        self.filter = filter;
+        self.filter_radius = radius;
+    }
+
+    /*
+    物理相机模式：由焦距、传感器尺寸得到视角，由光圈值得到散焦，
+    由光圈值、快门时间与感光度得到曝光，快门时间同时决定运动模糊区间。
+    投影模型会按新的视角重建，自定义模型只重新对焦
+     */
+    pub fn set_physical(&mut self, physical: PhysicalCamera) {
+        let aspect_ratio = f64::from(self.image_width) / f64::from(self.image_height);
+        self.vfov = physical.vfov(aspect_ratio);
+        self.defocus_angle = physical.defocus_angle(self.focus_dist);
+        self.exposure = physical.exposure();
+        self.shutter_close = self.shutter_open + physical.shutter_time;
+        self.physical = Some(physical);
+        match self.projection {
+            Some(projection) => self.set_projection(projection),
+            None => self.model.set_focus(self.focus_dist, self.defocus_angle)
+        }
+    }
+
+    /*
+    自动对焦：从镜头中心向像素(i,j)中心发射探测光线，以击中点的深度作为对焦距离。
+    未击中任何物体时保持原对焦距离并返回false
+     */
+    pub fn autofocus(&mut self, world: &dyn Hittable, i: i32, j: i32) -> bool {
+        let s = (f64::from(i) + 0.5) / f64::from(self.image_width);
+        let t = (f64::from(j) + 0.5) / f64::from(self.image_height);
+        let (origin, direction) = match self.model.generate_ray(&self.basis, s, t, Vec3::default()) {
+            Some(ray) => ray,
+            None => return false
+        };
+
+        let probe = Ray::new(origin, direction, self.shutter_open);
+        let mut rec = HitRecord::new_default();
+        if !world.hit(&probe, 0.001, INFINITY, &mut rec) {
+            return false;
         }
-        let mut file = File::create("image.ppm")
-            .expect("Failed to create image.ppm.");
-        file.write_all(format!("P3\n{} {}\n255\n", self.image_width, self.image_height).as_bytes())
+
+        // 对焦距离沿视线方向度量；全景投影等视线方向不确定时使用直线距离
+        let offset = rec.get_p() - self.basis.origin;
+        let depth = dot(offset, -self.basis.w);
+        self.set_focus_dist(if depth > 0.0 && !self.model.is_panoramic() { depth } else { offset.length() });
+        true
+    }
+
+    // 输出图像尺寸，立体渲染时为两只眼睛拼接后的尺寸
+    pub fn output_size(&self) -> (i32, i32) {
+        match &self.stereo {
+            Some(stereo) => stereo.output_size(self.image_width, self.image_height),
+            None => (self.image_width, self.image_height)
+        }
+    }
+
+    pub fn render(&self, scene: &Scene) {
+        // Render
+        self.render_film(scene, &[]).save_ppm("image.ppm")
             .expect("Failed to Write Color.");
+    }
 
-        for j in 0..self.image_height {
-            println!("Scan lines remaining: {}", (self.image_height - j));
-            for i in 0..self.image_width {
-                let mut temp_color = Color::new(0.0, 0.0, 0.0);
-                for s in 0..self.samples_per_pixel {
-                    let r = self.get_ray(i, j);
-                    temp_color += self.ray_color(&r, world, self.max_depth);
+    /*
+    渲染到胶片，aovs为需要同时记录的输出变量
+     */
+    pub fn render_film(&self, scene: &Scene, aovs: &[Aov]) -> Film {
+        if let Some(film) = self.integrator.render(self, scene, aovs) {
+            return film;
+        }
+        let (output_width, output_height) = self.output_size();
+        let mut film = self.new_film(aovs);
+
+        let mut sampler = self.new_sampler();
+        let max_samples = self.max_samples();
+        // 调试输出不是辐射亮度，不乘曝光系数
+        let exposure = if self.integrator.is_radiometric() { self.exposure } else { 1.0 };
+        let mut sample_counts = Vec::new();
+        let mut total_samples = 0;
+        for y in 0..output_height {
+            println!("Scan lines remaining: {}", (output_height - y));
+            for x in 0..output_width {
+                let (eye, i, j) = self.locate(x, y);
+                let window = self.get_window(x, y);
+                let mut stats = PixelStats::new();
+                for s in 0..max_samples {
+                    // 以输出图像坐标区分像素，立体渲染时左右眼的样本互不相关
+                    seed_pixel_sample(self.seed, x, y, s);
+                    sampler.start_sample(x, y, s);
+                    let (px, py) = self.pixel_sample_square(sampler.as_mut());
+                    let mut sample_aovs = SampleAovs::new();
+                    let color = match self.get_offset_ray(i, j, eye, (px, py), sampler.as_mut()) {
+                        Some(r) => self.integrator.li(&r, self, scene, sampler.as_mut(), &mut sample_aovs),
+                        None => Color::default()
+                    } * exposure;
+                    sample_aovs.direct *= exposure;
+                    sample_aovs.indirect *= exposure;
+                    sample_aovs.emission *= exposure;
+                    film.add_sample(f64::from(x) + 0.5 + px, f64::from(y) + 0.5 + py, color, window);
+                    film.add_aovs(x, y, &sample_aovs);
+                    for &(sx, sy, splat) in sample_aovs.splats.iter() {
+                        film.add_splat(sx, sy, splat * exposure);
+                    }
+                    total_samples += 1;
+                    stats.add(color);
+                    // 已收敛的像素提前结束，噪声大的像素继续采样直到max_samples
+                    if let Some(adaptive) = &self.adaptive {
+                        if adaptive.converged(&stats) {
+                            break;
+                        }
+                    }
                 }
-                temp_color.write_color(&mut file, self.samples_per_pixel)
-                    .expect(&format!("Failed to Write Color:{}_{}", i, j));
+                sample_counts.push(stats.get_count());
+            }
+        }
+
+        // 每个相机样本对应一条光子路径
+        if total_samples > 0 {
+            film.set_splat_scale(f64::from(output_width * output_height) / f64::from(total_samples));
+        }
+
+        if let Some(path) = self.adaptive.as_ref().and_then(|adaptive| adaptive.sample_map.as_ref()) {
+            if let Err(err) = write_sample_map(path, &sample_counts, output_width, output_height, max_samples) {
+                println!("Failed to write sample map {}: {}", path, err);
             }
         }
+        film
     }
 
-    pub fn get_ray(&self, i: i32, j: i32) -> Ray {
-        // Get a randomly sampled camera ray for the pixel at location i,j.
+    pub fn get_samples_per_pixel(&self) -> i32 {
+        self.samples_per_pixel
+    }
 
-        let pixel_center = self.pixel00_loc + (self.pixel_delta_u * i) + (self.pixel_delta_v * j);
-        let pixel_sample = pixel_center + self.pixel_sample_square();
+    pub fn get_exposure(&self) -> f64 {
+        self.exposure
+    }
 
-        let ray_origin = if self.defocus_angle <= 0.0 { self.center } else { self.defocus_disk_sample() };
-        let ray_direction = pixel_sample - ray_origin;
-        let ray_time = get_random_double();
-        return Ray::new(ray_origin, ray_direction, ray_time)
+    // 与输出图像同样大小、使用相机重建滤波器的胶片
+    pub fn new_film(&self, aovs: &[Aov]) -> Film {
+        let (output_width, output_height) = self.output_size();
+        Film::new(output_width, output_height, self.filter.create(self.filter_radius), aovs)
     }
 
-    pub fn defocus_disk_sample(&self) -> Point {
-        let p = Vec3::random_in_unit_disk();
-        self.center + (self.defocus_disk_u * p.x()) + (self.defocus_disk_v * p.y())
+    // 每个像素最多的样本数，自适应采样时为其上限
+    fn max_samples(&self) -> i32 {
+        match &self.adaptive {
+            Some(adaptive) => adaptive.max_samples,
+            None => self.samples_per_pixel
+        }
     }
 
-    pub fn pixel_sample_square(&self)->Vec3 {
-        let px = -0.5 + get_random_double();
-        let py = -0.5 + get_random_double();
-        return self.pixel_delta_u * px + self.pixel_delta_v * py;
+    // 分层类采样器按每像素最多的样本数分层，自适应采样追加的样本不会重复已用的层
+    pub fn new_sampler(&self) -> Box<dyn Sampler> {
+        self.sampler.create(self.max_samples(), self.seed)
     }
 
-    pub fn ray_color(&self, r: &Ray, world: &dyn Hittable, depth: i32) -> Color {
-        if depth <= 0 {
-            // 达到反射层数上线，返回黑色（也可返回红色看看哪里不停的散射，但每层都需要返回红色）
-            return Color::new(0.0, 0.0, 0.0);
+    // 输出图像像素(x, y)所属的眼睛及其在单眼图像中的坐标
+    fn locate(&self, x: i32, y: i32) -> (Option<Eye>, i32, i32) {
+        match &self.stereo {
+            Some(stereo) => {
+                let (eye, i, j) = stereo.locate(x, y, self.image_width, self.image_height);
+                (Some(eye), i, j)
+            }
+            None => (None, x, y)
         }
-        
-        // 和(0,0,-1)小球求交集
-        let mut temp_rec = HitRecord::new_default();
-        // 防止阴影痤疮(shadow ance)，在接近t=0时会再次击中自己
-        if world.hit(r,0.001,INFINITY,&mut temp_rec) {
-            let mut scattered = Ray::default();
-            let mut attenuation = Color::default();
-            if temp_rec.get_material().scatter(r, &temp_rec, &mut attenuation, &mut scattered) {
-                return attenuation * self.ray_color(&scattered, world, depth - 1);
+    }
+
+    // 像素(x, y)的样本允许写入的范围，即所属眼睛的图像区域
+    pub fn get_window(&self, x: i32, y: i32) -> (i32, i32, i32, i32) {
+        let (_, i, j) = self.locate(x, y);
+        (x - i, y - j, x - i + self.image_width, y - j + self.image_height)
+    }
+
+    /*
+    输出图像像素(x, y)内偏移offset处的相机光线，立体渲染时按像素所属的眼睛生成
+     */
+    pub fn get_output_ray(&self, x: i32, y: i32, offset: (f64, f64), sampler: &mut dyn Sampler) -> Option<Ray> {
+        let (eye, i, j) = self.locate(x, y);
+        self.get_offset_ray(i, j, eye, offset, sampler)
+    }
+
+    // 快门时间内的一个时刻，u为[0,1)的随机数
+    pub fn sample_time(&self, u: f64) -> f64 {
+        self.shutter_open + (self.shutter_close - self.shutter_open) * u
+    }
+
+    pub fn get_ray(&self, i: i32, j: i32, sampler: &mut dyn Sampler) -> Option<Ray> {
+        self.get_eye_ray(i, j, None, sampler)
+    }
+
+    pub fn get_eye_ray(&self, i: i32, j: i32, eye: Option<Eye>, sampler: &mut dyn Sampler) -> Option<Ray> {
+        let offset = self.pixel_sample_square(sampler);
+        self.get_offset_ray(i, j, eye, offset, sampler)
+    }
+
+    /*
+    (px, py)为样本相对像素中心的偏移，由调用者采样以便将结果按同一位置写入胶片
+     */
+    fn get_offset_ray(&self, i: i32, j: i32, eye: Option<Eye>, (px, py): (f64, f64), sampler: &mut dyn Sampler) -> Option<Ray> {
+        // Get a randomly sampled camera ray for the pixel at location i,j.
+        // 投影范围之外(例如鱼眼圆外)返回None
+
+        let s = (f64::from(i) + 0.5 + px) / f64::from(self.image_width);
+        let t = (f64::from(j) + 0.5 + py) / f64::from(self.image_height);
+
+        let lens = self.aperture.sample(sampler.get_2d());
+        if self.cat_eye > 0.0 {
+            // 离轴像素看到的出瞳被镜筒截去一部分：镜头采样点还须落在向画面外侧偏移的单位圆内
+            let offset = Vec3::new(2.0 * s - 1.0, 1.0 - 2.0 * t, 0.0) * self.cat_eye;
+            if (lens - offset).length_squared() > 1.0 {
+                return None;
             }
+        }
+        let (ray_origin, ray_direction) = self.model.generate_ray(&self.basis, s, t, lens)?;
+        let (ray_origin, ray_direction) = match (&self.stereo, eye) {
+            (Some(stereo), Some(eye)) => stereo.eye_ray(eye, &self.basis, self.model.is_panoramic(), ray_origin, ray_direction),
+            _ => (ray_origin, ray_direction)
+        };
+        let ray_time = self.sample_time(sampler.get_1d());
+        Some(Ray::new(ray_origin, ray_direction, ray_time))
+    }
+
+    pub fn pixel_sample_square(&self, sampler: &mut dyn Sampler) -> (f64, f64) {
+        let (u, v) = sampler.get_2d();
+        (-0.5 + u, -0.5 + v)
+    }
+
+    pub fn ray_color(&self, r: &Ray, scene: &Scene, sampler: &mut dyn Sampler) -> Color {
+        self.integrator.li(r, self, scene, sampler, &mut SampleAovs::new())
+    }
+
+    /*
+    能否把场景中的点投影到图像上(光线追踪)：目前只支持单目、均匀光圈的薄透镜透视投影
+     */
+    pub fn supports_light_tracing(&self) -> bool {
+        self.projection == Some(Projection::Perspective) && self.stereo.is_none()
+            && self.cat_eye <= 0.0 && self.aperture.area().is_some()
+    }
+
+    // 散焦圆盘半径
+    fn lens_radius(&self) -> f64 {
+        self.focus_dist * degrees_to_radians(self.defocus_angle / 2.0).tan()
+    }
+
+    // 镜头面积，针孔相机为1
+    fn lens_area(&self) -> f64 {
+        let radius = self.lens_radius();
+        if radius <= 0.0 {
+            return 1.0;
+        }
+        radius * radius * self.aperture.area().unwrap_or(PI)
+    }
+
+    // 到相机距离为1的平面上视窗的宽与高
+    fn view_size(&self) -> (f64, f64) {
+        let height = 2.0 * (degrees_to_radians(self.vfov) / 2.0).tan();
+        (height * f64::from(self.image_width) / f64::from(self.image_height), height)
+    }
+
+    /*
+    从镜头上origin点沿direction方向离开的光线的重要性函数与图像坐标，
+    光线不在画面内时返回None。We在画面上积分为1：We = 1 / (A · 镜头面积 · cos⁴θ)
+     */
+    pub fn importance(&self, origin: Point, direction: Vec3) -> Option<(f64, (f64, f64))> {
+        let forward = -self.basis.w;
+        let direction = direction.unit_vector();
+        let cos_theta = dot(direction, forward);
+        if cos_theta <= 0.0 {
+            return None;
+        }
+        // 光线与焦平面的交点按针孔投影到图像上
+        let focus = if self.lens_radius() > 0.0 { self.focus_dist } else { 1.0 };
+        let p_focus = origin + direction * (focus / cos_theta) - self.basis.origin;
+        let depth = dot(p_focus, forward);
+        let (view_width, view_height) = self.view_size();
+        let s = 0.5 + dot(p_focus, self.basis.u) / (depth * view_width);
+        let t = 0.5 - dot(p_focus, self.basis.v) / (depth * view_height);
+        if !(0.0..1.0).contains(&s) || !(0.0..1.0).contains(&t) {
+            return None;
+        }
+        let cos2 = cos_theta * cos_theta;
+        let importance = 1.0 / (view_width * view_height * self.lens_area() * cos2 * cos2);
+        Some((importance, (s * f64::from(self.image_width), t * f64::from(self.image_height))))
+    }
 
-            return Color::new(0.0, 0.0, 0.0);
+    // 相机光线的位置(镜头面积)与方向(立体角)概率密度
+    pub fn pdf_importance(&self, origin: Point, direction: Vec3) -> (f64, f64) {
+        if self.importance(origin, direction).is_none() {
+            return (0.0, 0.0);
         }
+        let cos_theta = dot(direction.unit_vector(), -self.basis.w);
+        let (view_width, view_height) = self.view_size();
+        (1.0 / self.lens_area(), 1.0 / (view_width * view_height * cos_theta * cos_theta * cos_theta))
+    }
+
+    /*
+    从场景中的点p向镜头采样一点，u为[0,1)^2的随机数
+     */
+    pub fn sample_importance(&self, p: Point, u: (f64, f64)) -> Option<CameraSample> {
+        let lens = self.aperture.sample(u) * self.lens_radius();
+        let lens_point = self.basis.origin + self.basis.u * lens.x() + self.basis.v * lens.y();
+        let to_lens = lens_point - p;
+        let distance = to_lens.length();
+        if distance <= 0.0 {
+            return None;
+        }
+        let wi = to_lens / distance;
+        let (importance, raster) = self.importance(lens_point, -wi)?;
+        // 镜头面积密度换算为p处的立体角密度
+        let cos_lens = dot(-wi, -self.basis.w);
+        Some(CameraSample {
+            wi,
+            lens_point,
+            distance,
+            importance,
+            pdf: distance * distance / (cos_lens * self.lens_area()),
+            raster
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::rc::Rc;
+    use super::*;
+    use crate::material::Lambertian;
+    use crate::sampler::IndependentSampler;
+    use crate::sphere::{HittableList, Sphere};
 
-        let unit_direction = r.direction().unit_vector();
-        let a = (unit_direction.y() + 1.0) * 0.5;
-        Color::new(1.0, 1.0, 1.0) * (1.0 - a) + Color::new(0.5, 0.7, 1.0) * a
+    // 所有像素都沿同一方向出射的自定义模型
+    struct Parallel;
+
+    impl CameraModel for Parallel {
+        fn generate_ray(&self, basis: &CameraBasis, _s: f64, _t: f64, _lens: Vec3) -> Option<(Point, Vec3)> {
+            Some((basis.origin, Vec3::new(0.0, 0.0, 1.0)))
+        }
     }
-}
\ No newline at end of file
+
+    fn test_camera() -> Camera {
+        Camera::new(40, 40.0, 1.0, 16, Point::new(0.0, 0.0, 0.0), Point::new(0.0, 0.0, -1.0),
+                    Vec3::new(0.0, 1.0, 0.0), 1.0, 2.0)
+    }
+
+    fn missed_rays(camera: &Camera, i: i32, j: i32) -> usize {
+        let mut sampler = IndependentSampler::new();
+        (0..400).filter(|_| camera.get_ray(i, j, &mut sampler).is_none()).count()
+    }
+
+    #[test]
+    fn cat_eye_clips_only_off_axis_pixels() {
+        let mut camera = test_camera();
+        assert_eq!(missed_rays(&camera, 0, 0), 0);
+        camera.set_cat_eye(0.8);
+        // 画面中心几乎不受影响，角落的出瞳被截去一大半
+        assert!(missed_rays(&camera, 20, 20) < 20);
+        assert!(missed_rays(&camera, 0, 0) > 200);
+        assert!(!camera.supports_light_tracing());
+    }
+
+    #[test]
+    fn custom_model_survives_set_physical() {
+        let mut camera = test_camera();
+        assert!(camera.supports_light_tracing());
+        camera.set_model(Box::new(Parallel));
+        camera.set_physical(PhysicalCamera::default());
+        assert!(!camera.supports_light_tracing());
+        assert_eq!(camera.get_exposure(), 1.0);
+
+        let mut sampler = IndependentSampler::new();
+        let ray = camera.get_ray(3, 30, &mut sampler).unwrap();
+        assert_eq!(ray.direction(), Vec3::new(0.0, 0.0, 1.0));
+    }
+
+    #[test]
+    fn set_projection_restores_light_tracing() {
+        let mut camera = test_camera();
+        camera.set_model(Box::new(Parallel));
+        camera.set_projection(Projection::Perspective);
+        assert!(camera.supports_light_tracing());
+        camera.set_projection(Projection::Fisheye);
+        assert!(!camera.supports_light_tracing());
+    }
+
+    #[test]
+    fn seed_determines_render() {
+        // 渐变天空下的漫反射球，各采样器的结果只取决于相机的种子
+        let mut world = HittableList::new();
+        world.add(Box::new(Sphere::new(Point::new(0.0, 0.0, -3.0), 1.0, Rc::new(Lambertian::new(Color::new(0.5, 0.5, 0.5))))));
+        let scene = Scene::new(Box::new(world));
+        for sampler in [SamplerType::Independent, SamplerType::Sobol] {
+            let render = |seed: u64| {
+                let mut camera = Camera::new(6, 40.0, 1.0, 2, Point::default(), Point::new(0.0, 0.0, -1.0),
+                                             Vec3::new(0.0, 1.0, 0.0), 3.0, 0.0);
+                camera.set_sampler(sampler);
+                camera.set_seed(seed);
+                let film = camera.render_film(&scene, &[]);
+                (0..36).map(|k| film.get_pixel(k % 6, k / 6)).collect::<Vec<Color>>()
+            };
+            assert!(render(1) == render(1));
+            assert!(render(1) != render(2));
+        }
+    }
+}
@@ -1,5 +1,6 @@
-use std::cmp::min;
-use rand::Rng;
+use std::cell::RefCell;
+use rand::{Rng, SeedableRng};
+use rand::rngs::StdRng;
 
 pub const PI:f64 = std::f64::consts::PI;
 pub const INFINITY:f64 = f64::INFINITY;
@@ -8,15 +9,57 @@ pub fn degrees_to_radians(degree:f64)->f64 {
     degree * std::f64::consts::PI / 180.0
 }
 pub fn ff_min(a: f64, b: f64) -> f64 {
-    return if a <= b { a } else { b };
+    if a <= b { a } else { b }
 }
 
 pub fn ff_max(a: f64, b: f64) -> f64 {
-    return if a >= b { a } else { b };
+    if a >= b { a } else { b }
+}
+
+thread_local! {
+    // 每个线程一个随机数发生器，使用前按(渲染种子, 随机数流)重新设置种子
+    static RNG: RefCell<StdRng> = RefCell::new(StdRng::seed_from_u64(0));
+}
+
+// 64位整数哈希(splitmix64的混合函数)
+pub fn mix_bits(mut v: u64) -> u64 {
+    v ^= v >> 31;
+    v = v.wrapping_mul(0x7fb5d329728ea185);
+    v ^= v >> 27;
+    v = v.wrapping_mul(0x81dadef4bc2dd44d);
+    v ^= v >> 33;
+    v
+}
+
+pub fn hash(a: u64, b: u64) -> u64 {
+    mix_bits(a ^ mix_bits(b).wrapping_add(0x9e3779b97f4a7c15))
+}
+
+/*
+渲染种子(所有随机数流都由它派生，同一种子的渲染结果逐位一致)与像素坐标组合得到的像素种子
+ */
+pub fn pixel_seed(seed: u64, i: i32, j: i32) -> u64 {
+    hash(seed, ((i as u32 as u64) << 32) | j as u32 as u64)
+}
+
+/*
+将当前线程的随机数发生器切换到由渲染种子派生的第stream个随机数流
+ */
+pub fn seed_random(seed: u64, stream: u64) {
+    let seed = hash(seed, stream);
+    RNG.with(|rng| *rng.borrow_mut() = StdRng::seed_from_u64(seed));
+}
+
+/*
+每个像素样本使用独立的随机数流，结果与像素的渲染顺序、线程划分无关
+ */
+pub fn seed_pixel_sample(seed: u64, i: i32, j: i32, index: i32) {
+    let seed = hash(pixel_seed(seed, i, j), index as u64);
+    RNG.with(|rng| *rng.borrow_mut() = StdRng::seed_from_u64(seed));
 }
 
 pub fn get_random_double() ->f64 {
-    rand::thread_rng().gen_range(0.0..1.0)
+    RNG.with(|rng| rng.borrow_mut().gen_range(0.0..1.0))
 }
 
 pub fn random_double(min:f64,max:f64)->f64 {
@@ -26,10 +69,10 @@ pub fn random_double(min:f64,max:f64)->f64 {
 pub fn clamp(x: f64, min: f64, max: f64) -> f64 {
     if x < min { return min; }
     if x > max { return max; }
-    return x;
+    x
 }
 
-#[derive(Default)]
+#[derive(Default, Copy, Clone)]
 pub struct Interval {
     pub min: f64,
     pub max: f64
@@ -43,6 +86,11 @@ impl Interval {
         }
     }
 
+    pub fn new_with_interval(a: &Interval, b: &Interval) -> Self {
+        // Create the interval tightly enclosing the two input intervals.
+        Interval::new(ff_min(a.min, b.min), ff_max(a.max, b.max))
+    }
+
     pub fn size(&self) -> f64 {
         self.max - self.min
     }
@@ -55,4 +103,20 @@ impl Interval {
         let padding = delta / 2.0;
         Interval::new(self.min - padding, self.max + padding)
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn seeded_samples_are_reproducible() {
+        let draw = |seed, i, j, index| {
+            seed_pixel_sample(seed, i, j, index);
+            (get_random_double(), get_random_double())
+        };
+        assert_eq!(draw(1, 3, 5, 7), draw(1, 3, 5, 7));
+        assert_ne!(draw(1, 3, 5, 7), draw(1, 3, 5, 8));
+        assert_ne!(draw(1, 3, 5, 7), draw(2, 3, 5, 7));
+    }
+}
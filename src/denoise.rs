@@ -0,0 +1,171 @@
+use crate::imageio::Image;
+use crate::vec3::{Color, dot};
+
+// B3样条核
+const KERNEL: [f64; 5] = [1.0 / 16.0, 1.0 / 4.0, 3.0 / 8.0, 1.0 / 4.0, 1.0 / 16.0];
+
+/*
+边缘保持的À-Trous小波降噪(Dammertz et al., Edge-Avoiding À-Trous Wavelet Transform, 2010)。
+先除以反照率得到光照，对光照做多次间隔逐次加倍的5x5滤波，再乘回反照率；
+滤波权重由颜色、法向量与反照率的差异决定，保留几何边缘与纹理细节
+ */
+#[derive(Debug, Copy, Clone)]
+pub struct Denoiser {
+    // 滤波次数，第i次采样间隔为2^i个像素
+    pub iterations: usize,
+    // 颜色(gamma空间亮度)差异的容差，每次迭代减半
+    pub sigma_color: f64,
+    // 法向量权重指数，越大越容易在法向量变化处停止
+    pub sigma_normal: f64,
+    // 反照率差异的容差
+    pub sigma_albedo: f64
+}
+
+impl Default for Denoiser {
+    fn default() -> Self {
+        Denoiser {
+            iterations: 5,
+            sigma_color: 0.15,
+            sigma_normal: 64.0,
+            sigma_albedo: 0.1
+        }
+    }
+}
+
+impl Denoiser {
+    pub fn denoise(&self, color: &Image, albedo: &Image, normal: &Image) -> Image {
+        let (width, height) = (color.get_width(), color.get_height());
+        // 反照率过小(背景、黑色材质)的像素不做调制
+        let modulation = |x: usize, y: usize| {
+            let a = albedo.get_pixel(x, y);
+            Color::new(if a.x() > 0.01 { a.x() } else { 1.0 },
+                       if a.y() > 0.01 { a.y() } else { 1.0 },
+                       if a.z() > 0.01 { a.z() } else { 1.0 })
+        };
+
+        let mut current = Image::new(width, height);
+        for y in 0..height {
+            for x in 0..width {
+                current.set_pixel(x, y, color.get_pixel(x, y) / modulation(x, y));
+            }
+        }
+
+        let extent = width.max(height);
+        for iteration in 0..self.iterations {
+            // 间隔达到图像尺寸后只剩中心像素，之后的迭代不再改变结果，也避免移位溢出
+            if iteration >= 62 || 1usize << iteration >= extent {
+                break;
+            }
+            let step = 1i64 << iteration;
+            let sigma_color = self.sigma_color / (1u64 << iteration) as f64;
+            let mut next = Image::new(width, height);
+            for y in 0..height {
+                for x in 0..width {
+                    let center = current.get_pixel(x, y);
+                    let center_luminance = center.luminance().max(0.0).sqrt();
+                    let center_normal = normal.get_pixel(x, y);
+                    let center_albedo = albedo.get_pixel(x, y);
+
+                    let mut sum = Color::default();
+                    let mut total = 0.0;
+                    for (j, ky) in KERNEL.iter().enumerate() {
+                        let qy = y as i64 + (j as i64 - 2) * step;
+                        if qy < 0 || qy >= height as i64 {
+                            continue;
+                        }
+                        for (i, kx) in KERNEL.iter().enumerate() {
+                            let qx = x as i64 + (i as i64 - 2) * step;
+                            if qx < 0 || qx >= width as i64 {
+                                continue;
+                            }
+                            let (qx, qy) = (qx as usize, qy as usize);
+                            let sample = current.get_pixel(qx, qy);
+
+                            let dl = sample.luminance().max(0.0).sqrt() - center_luminance;
+                            let w_color = (-dl * dl / (sigma_color * sigma_color)).exp();
+                            let w_normal = normal_weight(center_normal, normal.get_pixel(qx, qy), self.sigma_normal);
+                            let da = (albedo.get_pixel(qx, qy) - center_albedo).length_squared();
+                            let w_albedo = (-da / (self.sigma_albedo * self.sigma_albedo)).exp();
+
+                            let weight = kx * ky * w_color * w_normal * w_albedo;
+                            sum += sample * weight;
+                            total += weight;
+                        }
+                    }
+                    next.set_pixel(x, y, if total > 0.0 { sum / total } else { center });
+                }
+            }
+            current = next;
+        }
+
+        for y in 0..height {
+            for x in 0..width {
+                current.set_pixel(x, y, current.get_pixel(x, y) * modulation(x, y));
+            }
+        }
+        current
+    }
+}
+
+// 未击中物体的像素法向量为0，只与同样未击中的像素混合
+fn normal_weight(a: Color, b: Color, sigma: f64) -> f64 {
+    let a_empty = a.length_squared() == 0.0;
+    let b_empty = b.length_squared() == 0.0;
+    if a_empty || b_empty {
+        return if a_empty == b_empty { 1.0 } else { 0.0 };
+    }
+    dot(a.unit_vector(), b.unit_vector()).max(0.0).powf(sigma)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn image(width: usize, height: usize, pixel: impl Fn(usize, usize) -> Color) -> Image {
+        let mut image = Image::new(width, height);
+        for y in 0..height {
+            for x in 0..width {
+                image.set_pixel(x, y, pixel(x, y));
+            }
+        }
+        image
+    }
+
+    fn pixels(image: &Image) -> Vec<Color> {
+        (0..image.get_height()).flat_map(|y| (0..image.get_width()).map(move |x| image.get_pixel(x, y))).collect()
+    }
+
+    // 带噪声的灰色平面，法向量朝向相机
+    fn noisy() -> (Image, Image, Image) {
+        let color = image(12, 5, |x, y| Color::new(1.0, 1.0, 1.0) * (0.5 + 0.2 * (((x * 7 + y * 13) % 5) as f64 / 4.0 - 0.5)));
+        let albedo = image(12, 5, |_, _| Color::new(0.5, 0.5, 0.5));
+        let normal = image(12, 5, |_, _| Color::new(0.0, 0.0, 1.0));
+        (color, albedo, normal)
+    }
+
+    #[test]
+    fn iterations_beyond_image_size_are_skipped() {
+        let (color, albedo, normal) = noisy();
+        let denoiser = |iterations| Denoiser { iterations, ..Denoiser::default() };
+        // 12像素宽的图像在间隔16时已只剩中心像素
+        let expected = pixels(&denoiser(4).denoise(&color, &albedo, &normal));
+        assert_eq!(pixels(&denoiser(64).denoise(&color, &albedo, &normal)), expected);
+        assert_eq!(pixels(&denoiser(usize::MAX).denoise(&color, &albedo, &normal)), expected);
+    }
+
+    #[test]
+    fn flat_image_is_preserved_and_noise_reduced() {
+        let (color, albedo, normal) = noisy();
+        let flat = image(12, 5, |_, _| Color::new(0.3, 0.3, 0.3));
+        let denoiser = Denoiser::default();
+        for c in pixels(&denoiser.denoise(&flat, &albedo, &normal)) {
+            assert!((c - Color::new(0.3, 0.3, 0.3)).length() < 1e-12);
+        }
+
+        let variance = |values: Vec<Color>| {
+            let mean = values.iter().map(|c| c.x()).sum::<f64>() / values.len() as f64;
+            values.iter().map(|c| (c.x() - mean).powi(2)).sum::<f64>() / values.len() as f64
+        };
+        assert!(variance(pixels(&denoiser.denoise(&color, &albedo, &normal))) < 0.5 * variance(pixels(&color)));
+    }
+}
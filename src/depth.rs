@@ -0,0 +1,140 @@
+/*
+散射事件的类型，各类型分别限制路径中的反弹次数
+ */
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum RayType {
+    // 可被光源采样的非镜面散射(漫反射等)
+    Diffuse,
+    // 镜面或光泽反射
+    Specular,
+    // 穿过表面的折射
+    Transmission,
+    // 参与介质中的散射
+    Volume
+}
+
+/*
+路径深度限制与俄罗斯轮盘赌参数
+ */
+#[derive(Debug, Copy, Clone)]
+pub struct DepthLimits {
+    // 路径总顶点数上限
+    pub max_depth: i32,
+    pub diffuse: i32,
+    pub specular: i32,
+    pub transmission: i32,
+    pub volume: i32,
+    // 反弹次数达到该值后按路径通量做俄罗斯轮盘赌，负数表示关闭(默认)
+    pub roulette_depth: i32
+}
+
+impl Default for DepthLimits {
+    fn default() -> Self {
+        DepthLimits {
+            max_depth: 50,
+            diffuse: 50,
+            specular: 50,
+            transmission: 50,
+            volume: 50,
+            roulette_depth: -1
+        }
+    }
+}
+
+impl DepthLimits {
+    pub fn get_limit(&self, ray_type: RayType) -> i32 {
+        match ray_type {
+            RayType::Diffuse => self.diffuse,
+            RayType::Specular => self.specular,
+            RayType::Transmission => self.transmission,
+            RayType::Volume => self.volume
+        }
+    }
+}
+
+/*
+路径中各类型散射已发生的次数
+ */
+#[derive(Debug, Default, Copy, Clone)]
+pub struct BounceCounts {
+    pub diffuse: i32,
+    pub specular: i32,
+    pub transmission: i32,
+    pub volume: i32
+}
+
+impl BounceCounts {
+    // 记录一次散射，返回该类型的累计次数
+    pub fn increment(&mut self, ray_type: RayType) -> i32 {
+        let count = match ray_type {
+            RayType::Diffuse => &mut self.diffuse,
+            RayType::Specular => &mut self.specular,
+            RayType::Transmission => &mut self.transmission,
+            RayType::Volume => &mut self.volume
+        };
+        *count += 1;
+        *count
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::rc::Rc;
+    use super::*;
+    use crate::camera::Camera;
+    use crate::film::SampleAovs;
+    use crate::integrator::{Integrator, PathIntegrator};
+    use crate::material::{Lambertian, Material, Metal};
+    use crate::ray::Ray;
+    use crate::sampler::IndependentSampler;
+    use crate::scene::Scene;
+    use crate::sphere::{HittableList, Sphere};
+    use crate::vec3::{Color, Point, Vec3};
+
+    #[test]
+    fn bounce_counts_are_per_type() {
+        let limits = DepthLimits {
+            diffuse: 1,
+            specular: 2,
+            transmission: 3,
+            volume: 4,
+            ..DepthLimits::default()
+        };
+        assert_eq!(limits.get_limit(RayType::Specular), 2);
+        assert_eq!(limits.get_limit(RayType::Volume), 4);
+        let mut counts = BounceCounts::default();
+        assert_eq!(counts.increment(RayType::Diffuse), 1);
+        assert_eq!(counts.increment(RayType::Specular), 1);
+        assert_eq!(counts.increment(RayType::Diffuse), 2);
+        assert_eq!(counts.increment(RayType::Transmission), 1);
+        assert_eq!((counts.diffuse, counts.specular, counts.transmission, counts.volume), (2, 1, 1, 0));
+        assert!(DepthLimits::default().roulette_depth < 0);
+    }
+
+    #[test]
+    fn depth_limits_apply_per_scatter_type() {
+        // 天空渐变的蓝色分量恒为1，正对球心的光线只反弹一次就逃逸
+        let li = |material: Rc<dyn Material>, limits: DepthLimits| {
+            let mut world = HittableList::new();
+            world.add(Box::new(Sphere::new(Point::default(), 1.0, material)));
+            let scene = Scene::new(Box::new(world));
+            let camera = Camera::new(9, 20.0, 1.0, 1, Point::new(0.0, 0.0, 5.0), Point::default(),
+                                     Vec3::new(0.0, 1.0, 0.0), 5.0, 0.0);
+            let ray = Ray::new(Point::new(0.0, 0.0, 5.0), Vec3::new(0.0, 0.0, -1.0), 0.0);
+            PathIntegrator::new(limits, false).li(&ray, &camera, &scene, &mut IndependentSampler::new(), &mut SampleAovs::new()).z()
+        };
+        let mirror = || -> Rc<dyn Material> { Rc::new(Metal::new(Color::new(0.8, 0.8, 0.8), 0.0)) };
+        let clay = || -> Rc<dyn Material> { Rc::new(Lambertian::new(Color::new(0.5, 0.5, 0.5))) };
+        let limits = DepthLimits::default();
+
+        assert!((li(mirror(), limits) - 0.8).abs() < 1e-9);
+        assert_eq!(li(mirror(), DepthLimits { specular: 0, ..limits }), 0.0);
+        // 漫反射的上限不影响镜面反射
+        assert!((li(mirror(), DepthLimits { diffuse: 0, ..limits }) - 0.8).abs() < 1e-9);
+        assert_eq!(li(clay(), DepthLimits { diffuse: 0, ..limits }), 0.0);
+        assert!((li(clay(), DepthLimits { diffuse: 1, ..limits }) - 0.5).abs() < 1e-9);
+        // 总顶点数上限：第二个顶点(背景)超出上限
+        assert_eq!(li(mirror(), DepthLimits { max_depth: 1, ..limits }), 0.0);
+        assert!((li(mirror(), DepthLimits { max_depth: 2, ..limits }) - 0.8).abs() < 1e-9);
+    }
+}
@@ -0,0 +1,162 @@
+/*
+一维分段常数分布，按函数值比例进行重要性采样
+ */
+pub struct Distribution1D {
+    func: Vec<f64>,
+    cdf: Vec<f64>,
+    // 函数在[0,1]上的积分
+    integral: f64
+}
+
+impl Distribution1D {
+    pub fn new(func: Vec<f64>) -> Distribution1D {
+        let n = func.len();
+        let mut cdf = vec![0.0; n + 1];
+        for i in 1..=n {
+            cdf[i] = cdf[i - 1] + func[i - 1].abs() / n as f64;
+        }
+        let integral = cdf[n];
+        if integral == 0.0 {
+            // 函数全为0时退化为均匀分布
+            for (i, c) in cdf.iter_mut().enumerate().skip(1) {
+                *c = i as f64 / n as f64;
+            }
+        } else {
+            for c in cdf.iter_mut().skip(1) {
+                *c /= integral;
+            }
+        }
+
+        Distribution1D {
+            func,
+            cdf,
+            integral
+        }
+    }
+
+    pub fn count(&self) -> usize {
+        self.func.len()
+    }
+
+    pub fn get_integral(&self) -> f64 {
+        self.integral
+    }
+
+    // 找到满足 cdf[i] <= u < cdf[i+1] 的区间
+    fn find_interval(&self, u: f64) -> usize {
+        let index = self.cdf.partition_point(|&c| c <= u);
+        index.clamp(1, self.count()) - 1
+    }
+
+    /*
+    连续采样，返回 ([0,1]内的采样值, 概率密度, 所在区间)
+     */
+    pub fn sample_continuous(&self, u: f64) -> (f64, f64, usize) {
+        let offset = self.find_interval(u);
+        let mut du = u - self.cdf[offset];
+        let width = self.cdf[offset + 1] - self.cdf[offset];
+        if width > 0.0 {
+            du /= width;
+        }
+        let pdf = if self.integral > 0.0 { self.func[offset].abs() / self.integral } else { 1.0 };
+        ((offset as f64 + du) / self.count() as f64, pdf, offset)
+    }
+
+    /*
+    离散采样，返回 (区间序号, 概率)
+     */
+    pub fn sample_discrete(&self, u: f64) -> (usize, f64) {
+        let offset = self.find_interval(u);
+        (offset, self.discrete_pdf(offset))
+    }
+
+    pub fn discrete_pdf(&self, index: usize) -> f64 {
+        self.cdf[index + 1] - self.cdf[index]
+    }
+}
+
+/*
+二维分段常数分布：先按行的边缘分布选择v，再按该行的条件分布选择u
+ */
+pub struct Distribution2D {
+    conditional: Vec<Distribution1D>,
+    marginal: Distribution1D
+}
+
+impl Distribution2D {
+    // func按行优先存储，共height行、每行width个值
+    pub fn new(func: &[f64], width: usize, height: usize) -> Distribution2D {
+        let conditional: Vec<Distribution1D> = (0..height)
+            .map(|v| Distribution1D::new(func[v * width..(v + 1) * width].to_vec()))
+            .collect();
+        let marginal = Distribution1D::new(conditional.iter().map(|c| c.get_integral()).collect());
+        Distribution2D {
+            conditional,
+            marginal
+        }
+    }
+
+    /*
+    返回 ((u, v) ∈ [0,1]^2, 概率密度)
+     */
+    pub fn sample_continuous(&self, u: (f64, f64)) -> ((f64, f64), f64) {
+        let (v, pdf_v, row) = self.marginal.sample_continuous(u.1);
+        let (x, pdf_u, _) = self.conditional[row].sample_continuous(u.0);
+        ((x, v), pdf_u * pdf_v)
+    }
+
+    pub fn pdf(&self, p: (f64, f64)) -> f64 {
+        let width = self.conditional[0].count();
+        let height = self.marginal.count();
+        let iu = ((p.0 * width as f64) as usize).min(width - 1);
+        let iv = ((p.1 * height as f64) as usize).min(height - 1);
+        let integral = self.marginal.get_integral();
+        if integral == 0.0 {
+            return 1.0;
+        }
+        self.conditional[iv].func[iu].abs() / integral
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn samples_follow_function_values() {
+        let distribution = Distribution1D::new(vec![1.0, 0.0, 3.0]);
+        assert!((distribution.get_integral() - 4.0 / 3.0).abs() < 1e-12);
+        // 前1/4的随机数落在第一个区间，其余落在第三个区间，零值区间不会被采样
+        let (x, pdf, index) = distribution.sample_continuous(0.125);
+        assert_eq!(index, 0);
+        assert!((x - 1.0 / 6.0).abs() < 1e-12);
+        assert!((pdf - 0.75).abs() < 1e-12);
+        assert_eq!(distribution.sample_continuous(0.25).2, 2);
+        assert_eq!(distribution.sample_discrete(0.9), (2, 0.75));
+        assert_eq!(distribution.discrete_pdf(1), 0.0);
+    }
+
+    #[test]
+    fn zero_function_is_uniform() {
+        let distribution = Distribution1D::new(vec![0.0; 4]);
+        let (x, pdf, index) = distribution.sample_continuous(0.6);
+        assert_eq!((index, pdf), (2, 1.0));
+        assert!((x - 0.6).abs() < 1e-12);
+    }
+
+    #[test]
+    fn pdf_2d_matches_sampling() {
+        // 2×3网格，中间一行全为0
+        let func = [1.0, 2.0, 0.0, 0.0, 4.0, 1.0];
+        let distribution = Distribution2D::new(&func, 2, 3);
+        let n = 16;
+        for k in 0..n * n {
+            let u = (((k % n) as f64 + 0.5) / n as f64, ((k / n) as f64 + 0.5) / n as f64);
+            let ((x, y), pdf) = distribution.sample_continuous(u);
+            assert!(!(1.0 / 3.0..2.0 / 3.0).contains(&y), "sampled the empty row at {}", y);
+            assert!((pdf - distribution.pdf((x, y))).abs() < 1e-12);
+        }
+        // 密度为函数值除以其在[0,1]^2上的积分
+        assert!((distribution.pdf((0.75, 0.9)) - 1.0 / (8.0 / 6.0)).abs() < 1e-12);
+    }
+}
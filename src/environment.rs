@@ -0,0 +1,181 @@
+use std::io;
+use crate::common::{degrees_to_radians, INFINITY, PI};
+use crate::distribution::Distribution2D;
+use crate::imageio::{Image, load_hdr_image};
+use crate::light::{EmissionSample, Light, LightSample, sample_infinite_le};
+use crate::vec3::{Color, Point, Vec3};
+
+/*
+HDRI环境光：等距柱状(经纬度)图像包围整个场景，既作为背景也作为光源。
+图像u方向对应方位角，v方向从上(+y)到下(-y)对应天顶角；
+按像素亮度乘以sinθ构建二维分布做重要性采样
+ */
+pub struct EnvironmentLight {
+    image: Image,
+    // 绕+y轴的旋转(弧度)
+    rotation: f64,
+    intensity: f64,
+    distribution: Distribution2D
+}
+
+impl EnvironmentLight {
+    // rotation为绕竖直轴的旋转角(角度制)
+    pub fn new(image: Image, rotation: f64, intensity: f64) -> EnvironmentLight {
+        let (width, height) = (image.get_width(), image.get_height());
+        let mut func = Vec::with_capacity(width * height);
+        for y in 0..height {
+            // 经纬度图越靠近两极像素对应的立体角越小
+            let sin_theta = (PI * (y as f64 + 0.5) / height as f64).sin();
+            for x in 0..width {
+                func.push(image.get_pixel(x, y).luminance() * sin_theta);
+            }
+        }
+
+        EnvironmentLight {
+            distribution: Distribution2D::new(&func, width, height),
+            image,
+            rotation: degrees_to_radians(rotation),
+            intensity
+        }
+    }
+
+    pub fn load(path: &str, rotation: f64, intensity: f64) -> io::Result<EnvironmentLight> {
+        Ok(EnvironmentLight::new(load_hdr_image(path)?, rotation, intensity))
+    }
+
+    // 世界方向 -> 图像坐标 (u, v) ∈ [0,1]^2
+    fn direction_to_uv(&self, direction: Vec3) -> (f64, f64) {
+        let d = direction.unit_vector();
+        let theta = d.y().clamp(-1.0, 1.0).acos();
+        let mut phi = d.x().atan2(-d.z()) - self.rotation;
+        phi = phi.rem_euclid(2.0 * PI);
+        (phi / (2.0 * PI), theta / PI)
+    }
+
+    fn uv_to_direction(&self, u: f64, v: f64) -> Vec3 {
+        let theta = v * PI;
+        let phi = u * 2.0 * PI + self.rotation;
+        Vec3::new(theta.sin() * phi.sin(), theta.cos(), -theta.sin() * phi.cos())
+    }
+
+    fn lookup(&self, u: f64, v: f64) -> Color {
+        let (width, height) = (self.image.get_width(), self.image.get_height());
+        let x = ((u * width as f64) as usize).min(width - 1);
+        let y = ((v * height as f64) as usize).min(height - 1);
+        self.image.get_pixel(x, y) * self.intensity
+    }
+}
+
+impl Light for EnvironmentLight {
+    fn sample_li(&self, _p: Point, u: (f64, f64)) -> Option<LightSample> {
+        let ((su, sv), map_pdf) = self.distribution.sample_continuous(u);
+        if map_pdf == 0.0 {
+            return None;
+        }
+        let sin_theta = (sv * PI).sin();
+        if sin_theta == 0.0 {
+            return None;
+        }
+        // 图像空间概率密度转换为立体角概率密度：dω = 2π² sinθ du dv
+        Some(LightSample {
+            wi: self.uv_to_direction(su, sv),
+            li: self.lookup(su, sv),
+            distance: INFINITY,
+            pdf: map_pdf / (2.0 * PI * PI * sin_theta)
+        })
+    }
+
+    fn is_delta(&self) -> bool {
+        false
+    }
+
+    fn pdf_li(&self, _p: Point, wi: Vec3) -> f64 {
+        let (u, v) = self.direction_to_uv(wi);
+        let sin_theta = (v * PI).sin();
+        if sin_theta == 0.0 {
+            return 0.0;
+        }
+        self.distribution.pdf((u, v)) / (2.0 * PI * PI * sin_theta)
+    }
+
+    fn le(&self, direction: Vec3) -> Color {
+        let (u, v) = self.direction_to_uv(direction);
+        self.lookup(u, v)
+    }
+
+    fn is_infinite(&self) -> bool {
+        true
+    }
+
+    fn sample_le(&self, u1: (f64, f64), u2: (f64, f64), bounds: (Point, f64)) -> Option<EmissionSample> {
+        sample_infinite_le(self, u1, u2, bounds)
+    }
+
+    fn pdf_le(&self, direction: Vec3, (_, radius): (Point, f64)) -> (f64, f64) {
+        (1.0 / (PI * radius * radius), self.pdf_li(Point::default(), -direction))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // 8×4的暗色经纬度图，其中一个像素很亮
+    fn hot_spot() -> EnvironmentLight {
+        let mut image = Image::new(8, 4);
+        for y in 0..4 {
+            for x in 0..8 {
+                image.set_pixel(x, y, Color::new(0.1, 0.1, 0.1));
+            }
+        }
+        image.set_pixel(5, 1, Color::new(50.0, 40.0, 30.0));
+        EnvironmentLight::new(image, 30.0, 2.0)
+    }
+
+    fn grid(n: usize) -> impl Iterator<Item = (f64, f64)> {
+        (0..n * n).map(move |k| (((k % n) as f64 + 0.5) / n as f64, ((k / n) as f64 + 0.5) / n as f64))
+    }
+
+    #[test]
+    fn direction_and_image_coordinates_round_trip() {
+        let light = hot_spot();
+        for (u, v) in grid(8) {
+            let (u2, v2) = light.direction_to_uv(light.uv_to_direction(u, v));
+            assert!((u - u2).abs() < 1e-9 && (v - v2).abs() < 1e-9, "{:?} -> {:?}", (u, v), (u2, v2));
+        }
+        // 图像上边缘对应+y
+        assert!((light.uv_to_direction(0.3, 0.0) - Vec3::new(0.0, 1.0, 0.0)).length() < 1e-9);
+    }
+
+    #[test]
+    fn samples_match_pdf_and_radiance() {
+        let light = hot_spot();
+        for u in grid(16) {
+            let sample = light.sample_li(Point::default(), u).unwrap();
+            assert!((sample.pdf - light.pdf_li(Point::default(), sample.wi)).abs() < 1e-9 * sample.pdf);
+            assert!((sample.li - light.le(sample.wi)).length() < 1e-9);
+        }
+    }
+
+    #[test]
+    fn importance_sampling_integrates_radiance() {
+        let light = hot_spot();
+        // 每个像素的亮度乘以其立体角 Δφ·(cosθ0 - cosθ1)
+        let mut expected = 0.0;
+        for y in 0..4 {
+            let solid_angle = 2.0 * PI / 8.0 * ((PI * y as f64 / 4.0).cos() - (PI * (y + 1) as f64 / 4.0).cos());
+            for x in 0..8 {
+                expected += light.image.get_pixel(x, y).luminance() * light.intensity * solid_angle;
+            }
+        }
+        let n = 128;
+        let estimate = grid(n).map(|u| {
+            let sample = light.sample_li(Point::default(), u).unwrap();
+            sample.li.luminance() / sample.pdf
+        }).sum::<f64>() / (n * n) as f64;
+        assert!((estimate - expected).abs() < 0.01 * expected, "{} vs {}", estimate, expected);
+        // 大部分样本落在亮像素上
+        let hits = grid(n).filter(|&u| light.sample_li(Point::default(), u).unwrap().li.x() > 1.0).count();
+        assert!(hits > n * n * 9 / 10);
+    }
+}
@@ -0,0 +1,147 @@
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+
+/*
+OpenEXR写出(单部件、扫描线、无压缩、32位浮点通道)。
+每个通道为width*height个按行优先存储的值，多层图像使用"层名.通道名"形式的通道名，
+例如"albedo.R"；不带层名的R、G、B为主图像
+ */
+pub fn write_exr(path: &str, width: usize, height: usize, channels: &[(String, Vec<f32>)]) -> io::Result<()> {
+    // 文件中的通道必须按名称排序
+    let mut sorted: Vec<&(String, Vec<f32>)> = channels.iter().collect();
+    sorted.sort_by(|a, b| a.0.as_bytes().cmp(b.0.as_bytes()));
+
+    let mut header = Vec::new();
+    let mut channel_list = Vec::new();
+    for (name, _) in sorted.iter() {
+        channel_list.extend_from_slice(name.as_bytes());
+        channel_list.push(0);
+        // 像素类型FLOAT、pLinear与保留字节、x/y采样率
+        channel_list.extend_from_slice(&2i32.to_le_bytes());
+        channel_list.extend_from_slice(&[0, 0, 0, 0]);
+        channel_list.extend_from_slice(&1i32.to_le_bytes());
+        channel_list.extend_from_slice(&1i32.to_le_bytes());
+    }
+    channel_list.push(0);
+    write_attribute(&mut header, "channels", "chlist", &channel_list);
+    // 无压缩
+    write_attribute(&mut header, "compression", "compression", &[0]);
+    let mut window = Vec::new();
+    for value in [0, 0, width as i32 - 1, height as i32 - 1] {
+        window.extend_from_slice(&value.to_le_bytes());
+    }
+    write_attribute(&mut header, "dataWindow", "box2i", &window);
+    write_attribute(&mut header, "displayWindow", "box2i", &window);
+    // 扫描线自上而下
+    write_attribute(&mut header, "lineOrder", "lineOrder", &[0]);
+    write_attribute(&mut header, "pixelAspectRatio", "float", &1f32.to_le_bytes());
+    write_attribute(&mut header, "screenWindowCenter", "v2f", &[0u8; 8]);
+    write_attribute(&mut header, "screenWindowWidth", "float", &1f32.to_le_bytes());
+    header.push(0);
+
+    let mut file = BufWriter::new(File::create(path)?);
+    // 魔数与版本号2(单部件扫描线文件)
+    file.write_all(&[0x76, 0x2f, 0x31, 0x01, 2, 0, 0, 0])?;
+    file.write_all(&header)?;
+
+    // 偏移表：每条扫描线一个块，块内为y坐标、数据长度与逐通道的像素数据
+    let line_size = sorted.len() * width * 4;
+    let table_end = 8 + header.len() + height * 8;
+    for y in 0..height {
+        let offset = (table_end + y * (8 + line_size)) as u64;
+        file.write_all(&offset.to_le_bytes())?;
+    }
+    for y in 0..height {
+        file.write_all(&(y as i32).to_le_bytes())?;
+        file.write_all(&(line_size as i32).to_le_bytes())?;
+        for (_, data) in sorted.iter() {
+            for value in &data[y * width..(y + 1) * width] {
+                file.write_all(&value.to_le_bytes())?;
+            }
+        }
+    }
+    file.flush()
+}
+
+fn write_attribute(header: &mut Vec<u8>, name: &str, kind: &str, value: &[u8]) {
+    header.extend_from_slice(name.as_bytes());
+    header.push(0);
+    header.extend_from_slice(kind.as_bytes());
+    header.push(0);
+    header.extend_from_slice(&(value.len() as i32).to_le_bytes());
+    header.extend_from_slice(value);
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use super::*;
+
+    fn read_i32(data: &[u8], pos: usize) -> i32 {
+        i32::from_le_bytes([data[pos], data[pos + 1], data[pos + 2], data[pos + 3]])
+    }
+
+    fn read_string(data: &[u8], pos: &mut usize) -> String {
+        let end = *pos + data[*pos..].iter().position(|&b| b == 0).unwrap();
+        let s = String::from_utf8(data[*pos..end].to_vec()).unwrap();
+        *pos = end + 1;
+        s
+    }
+
+    #[test]
+    fn channels_are_sorted_and_scanlines_addressable() {
+        let (width, height) = (3, 2);
+        let plane = |base: f32| (0..width * height).map(|i| base + i as f32).collect::<Vec<f32>>();
+        let channels = vec![
+            ("R".to_string(), plane(0.0)),
+            ("G".to_string(), plane(10.0)),
+            ("B".to_string(), plane(20.0)),
+            ("depth.Z".to_string(), plane(30.0))
+        ];
+        let path = std::env::temp_dir().join("rayce_layers.exr");
+        write_exr(path.to_str().unwrap(), width, height, &channels).unwrap();
+        let data = fs::read(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(&data[..8], &[0x76, 0x2f, 0x31, 0x01, 2, 0, 0, 0]);
+        // 读出头部属性，直到空的属性名
+        let mut pos = 8;
+        let mut names = Vec::new();
+        loop {
+            let name = read_string(&data, &mut pos);
+            if name.is_empty() {
+                break;
+            }
+            let kind = read_string(&data, &mut pos);
+            let size = read_i32(&data, pos) as usize;
+            pos += 4;
+            if kind == "chlist" {
+                let mut p = pos;
+                loop {
+                    let channel = read_string(&data, &mut p);
+                    if channel.is_empty() {
+                        break;
+                    }
+                    assert_eq!(read_i32(&data, p), 2);
+                    names.push(channel);
+                    p += 16;
+                }
+            }
+            pos += size;
+        }
+        assert_eq!(names, ["B", "G", "R", "depth.Z"]);
+
+        // 第二条扫描线：y坐标、数据长度，然后按排序后的通道依次存放
+        let offset = u64::from_le_bytes(data[pos + 8..pos + 16].try_into().unwrap()) as usize;
+        assert_eq!(read_i32(&data, offset), 1);
+        assert_eq!(read_i32(&data, offset + 4), (4 * width * 4) as i32);
+        let value = |channel: usize, x: usize| {
+            let p = offset + 8 + (channel * width + x) * 4;
+            f32::from_le_bytes(data[p..p + 4].try_into().unwrap())
+        };
+        assert_eq!(value(0, 0), 23.0);
+        assert_eq!(value(2, 2), 5.0);
+        assert_eq!(value(3, 1), 34.0);
+        assert_eq!(data.len(), offset + 8 + 4 * width * 4);
+    }
+}
@@ -0,0 +1,539 @@
+use std::collections::HashMap;
+use std::fs::{File, remove_file};
+use std::io::{self, Write};
+use crate::common::INFINITY;
+use crate::exr::write_exr;
+use crate::filter::Filter;
+use crate::imageio::{Image, write_pfm};
+use crate::vec3::{Color, Point, Vec3};
+
+/*
+输出变量(AOV)，与主图像一起记录的辅助通道
+ */
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Aov {
+    // 相机光线首个交点的材质反照率
+    Albedo,
+    // 首个交点的着色法向量
+    Normal,
+    // 首个交点到相机的距离
+    Depth,
+    // 首个交点的世界坐标
+    Position,
+    // 首个交点所属物体在场景列表中的序号
+    ObjectId,
+    // 首个交点的材质序号，按渲染中首次出现的顺序编号
+    MaterialId,
+    // 首个交点的直接光照(光源采样与一次反弹后击中光源)
+    Direct,
+    // 首个交点的间接光照
+    Indirect,
+    // 相机直接看到的自发光与背景
+    Emission
+}
+
+impl Aov {
+    pub const ALL: [Aov; 9] = [Aov::Albedo, Aov::Normal, Aov::Depth, Aov::Position, Aov::ObjectId,
+        Aov::MaterialId, Aov::Direct, Aov::Indirect, Aov::Emission];
+
+    pub fn from_name(name: &str) -> Option<Aov> {
+        Aov::ALL.iter().copied().find(|aov| aov.name() == name)
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Aov::Albedo => "albedo",
+            Aov::Normal => "normal",
+            Aov::Depth => "depth",
+            Aov::Position => "position",
+            Aov::ObjectId => "object_id",
+            Aov::MaterialId => "material_id",
+            Aov::Direct => "direct",
+            Aov::Indirect => "indirect",
+            Aov::Emission => "emission"
+        }
+    }
+
+    // 单通道输出变量在EXR中使用的通道名
+    fn single_channel(self) -> Option<&'static str> {
+        match self {
+            Aov::Depth => Some("Z"),
+            Aov::ObjectId | Aov::MaterialId => Some("id"),
+            _ => None
+        }
+    }
+
+    // 几何量只在击中物体的样本上取平均
+    fn is_geometric(self) -> bool {
+        matches!(self, Aov::Albedo | Aov::Normal | Aov::Depth | Aov::Position)
+    }
+
+    // 序号不能取平均，使用像素的第一个样本
+    fn is_id(self) -> bool {
+        matches!(self, Aov::ObjectId | Aov::MaterialId)
+    }
+}
+
+/*
+单个相机样本的输出变量
+ */
+pub struct SampleAovs {
+    pub hit: bool,
+    pub albedo: Color,
+    pub normal: Vec3,
+    pub depth: f64,
+    pub position: Point,
+    pub object_id: i32,
+    // 材质的标识(Rc指针地址)，由胶片换算为材质序号
+    pub material_key: Option<usize>,
+    pub direct: Color,
+    pub indirect: Color,
+    pub emission: Color,
+    // 光线追踪写入其他像素的贡献(连续图像坐标x, y与颜色)，只计入主图像
+    pub splats: Vec<(f64, f64, Color)>
+}
+
+impl Default for SampleAovs {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl SampleAovs {
+    pub fn new() -> SampleAovs {
+        SampleAovs {
+            hit: false,
+            albedo: Color::default(),
+            normal: Vec3::default(),
+            depth: INFINITY,
+            position: Point::default(),
+            object_id: -1,
+            material_key: None,
+            direct: Color::default(),
+            indirect: Color::default(),
+            emission: Color::default(),
+            splats: Vec::new()
+        }
+    }
+}
+
+struct AovLayer {
+    aov: Aov,
+    // 像素值之和，序号层直接存放序号
+    sums: Vec<Color>
+}
+
+/*
+胶片：按重建滤波器把样本加权累加到像素，输出时除以权重之和。
+样本坐标为连续的图像坐标，像素(x,y)的中心位于(x+0.5, y+0.5)。
+输出变量不经过重建滤波器，按像素内样本取平均
+ */
+pub struct Film {
+    width: i32,
+    height: i32,
+    // 加权颜色之和与权重之和
+    pixels: Vec<Color>,
+    weights: Vec<f64>,
+    // 光线追踪溅射的颜色之和，输出时乘以splat_scale后与重建结果相加
+    splats: Vec<Color>,
+    splat_scale: f64,
+    filter: Box<dyn Filter>,
+    layers: Vec<AovLayer>,
+    // 每个像素的样本数与其中击中物体的样本数
+    sample_counts: Vec<i32>,
+    hit_counts: Vec<i32>,
+    material_ids: HashMap<usize, i32>
+}
+
+impl Film {
+    pub fn new(width: i32, height: i32, filter: Box<dyn Filter>, aovs: &[Aov]) -> Film {
+        let count = (width * height) as usize;
+        Film {
+            width,
+            height,
+            pixels: vec![Color::default(); count],
+            weights: vec![0.0; count],
+            splats: vec![Color::default(); count],
+            splat_scale: 1.0,
+            filter,
+            layers: aovs.iter().map(|&aov| AovLayer { aov, sums: vec![Color::default(); count] }).collect(),
+            sample_counts: vec![0; count],
+            hit_counts: vec![0; count],
+            material_ids: HashMap::new()
+        }
+    }
+
+    pub fn get_width(&self) -> i32 {
+        self.width
+    }
+
+    pub fn get_height(&self) -> i32 {
+        self.height
+    }
+
+    pub fn has_aov(&self, aov: Aov) -> bool {
+        self.layers.iter().any(|layer| layer.aov == aov)
+    }
+
+    /*
+    将样本累加到滤波器半径内的像素，window为允许写入的像素范围(x0, y0, x1, y1)，不含x1、y1，
+    立体渲染时用于避免左右眼图像在拼接处互相混合
+     */
+    pub fn add_sample(&mut self, x: f64, y: f64, color: Color, window: (i32, i32, i32, i32)) {
+        let radius = self.filter.radius();
+        let x0 = ((x - 0.5 - radius).ceil() as i32).max(window.0);
+        let x1 = ((x - 0.5 + radius).floor() as i32).min(window.2 - 1);
+        let y0 = ((y - 0.5 - radius).ceil() as i32).max(window.1);
+        let y1 = ((y - 0.5 + radius).floor() as i32).min(window.3 - 1);
+        for py in y0..=y1 {
+            for px in x0..=x1 {
+                let weight = self.filter.evaluate(f64::from(px) + 0.5 - x, f64::from(py) + 0.5 - y);
+                if weight == 0.0 {
+                    continue;
+                }
+                let index = (py * self.width + px) as usize;
+                self.pixels[index] += color * weight;
+                self.weights[index] += weight;
+            }
+        }
+    }
+
+    /*
+    光线追踪的贡献不经过重建滤波器，直接累加到(x, y)所在的像素
+     */
+    pub fn add_splat(&mut self, x: f64, y: f64, color: Color) {
+        let (px, py) = (x.floor() as i32, y.floor() as i32);
+        if px < 0 || py < 0 || px >= self.width || py >= self.height {
+            return;
+        }
+        self.splats[(py * self.width + px) as usize] += color;
+    }
+
+    /*
+    溅射之和的缩放系数：每条光子路径的贡献是对整幅图像的估计，
+    应除以平均每个像素的光子路径数
+     */
+    pub fn set_splat_scale(&mut self, scale: f64) {
+        self.splat_scale = scale;
+    }
+
+    // 记录像素(x,y)中一个样本的输出变量
+    pub fn add_aovs(&mut self, x: i32, y: i32, aovs: &SampleAovs) {
+        let index = (y * self.width + x) as usize;
+        let first = self.sample_counts[index] == 0;
+        self.sample_counts[index] += 1;
+        if aovs.hit {
+            self.hit_counts[index] += 1;
+        }
+        let next_id = self.material_ids.len() as i32;
+        let material_id = match aovs.material_key {
+            Some(key) => *self.material_ids.entry(key).or_insert(next_id),
+            None => -1
+        };
+
+        for layer in self.layers.iter_mut() {
+            let value = match layer.aov {
+                Aov::Albedo => aovs.albedo,
+                Aov::Normal => aovs.normal,
+                Aov::Depth => Color::new(aovs.depth, aovs.depth, aovs.depth),
+                Aov::Position => aovs.position,
+                Aov::ObjectId => Color::new(f64::from(aovs.object_id), 0.0, 0.0),
+                Aov::MaterialId => Color::new(f64::from(material_id), 0.0, 0.0),
+                Aov::Direct => aovs.direct,
+                Aov::Indirect => aovs.indirect,
+                Aov::Emission => aovs.emission
+            };
+            if layer.aov.is_id() {
+                if first {
+                    layer.sums[index] = value;
+                }
+            } else if !layer.aov.is_geometric() || aovs.hit {
+                layer.sums[index] += value;
+            }
+        }
+    }
+
+    // 像素的重建结果
+    pub fn get_pixel(&self, x: i32, y: i32) -> Color {
+        let index = (y * self.width + x) as usize;
+        let weight = self.weights[index];
+        let splat = self.splats[index] * self.splat_scale;
+        if weight == 0.0 { splat } else { self.pixels[index] / weight + splat }
+    }
+
+    // 像素的输出变量，未记录该变量时返回None
+    pub fn get_aov(&self, aov: Aov, x: i32, y: i32) -> Option<Color> {
+        let layer = self.layers.iter().find(|layer| layer.aov == aov)?;
+        let index = (y * self.width + x) as usize;
+        let sum = layer.sums[index];
+        if aov.is_id() {
+            return Some(sum);
+        }
+        let count = if aov.is_geometric() { self.hit_counts[index] } else { self.sample_counts[index] };
+        Some(if count > 0 {
+            sum / f64::from(count)
+        } else if aov == Aov::Depth {
+            Color::new(INFINITY, INFINITY, INFINITY)
+        } else {
+            Color::default()
+        })
+    }
+
+    pub fn get_sample_count(&self, x: i32, y: i32) -> i32 {
+        self.sample_counts[(y * self.width + x) as usize]
+    }
+
+    fn to_image(&self, pixel: impl Fn(i32, i32) -> Color) -> Image {
+        let mut image = Image::new(self.width as usize, self.height as usize);
+        for y in 0..self.height {
+            for x in 0..self.width {
+                image.set_pixel(x as usize, y as usize, pixel(x, y));
+            }
+        }
+        image
+    }
+
+    pub fn get_image(&self) -> Image {
+        self.to_image(|x, y| self.get_pixel(x, y))
+    }
+
+    pub fn get_aov_image(&self, aov: Aov) -> Option<Image> {
+        if !self.has_aov(aov) {
+            return None;
+        }
+        Some(self.to_image(|x, y| self.get_aov(aov, x, y).unwrap_or_default()))
+    }
+
+    // 用处理后的图像(例如降噪结果)替换主图像
+    pub fn set_image(&mut self, image: &Image) {
+        for y in 0..self.height {
+            for x in 0..self.width {
+                let index = (y * self.width + x) as usize;
+                self.pixels[index] = image.get_pixel(x as usize, y as usize);
+                self.weights[index] = 1.0;
+                self.splats[index] = Color::default();
+            }
+        }
+    }
+
+    pub fn write_ppm<W: Write>(&self, stream: &mut W) -> io::Result<()> {
+        stream.write_all(format!("P3\n{} {}\n255\n", self.width, self.height).as_bytes())?;
+        for y in 0..self.height {
+            for x in 0..self.width {
+                self.get_pixel(x, y).write_color(stream, 1)?;
+            }
+        }
+        Ok(())
+    }
+
+    pub fn save_ppm(&self, path: &str) -> io::Result<()> {
+        if let Err(err) = remove_file(path) {
+            if err.kind() != io::ErrorKind::NotFound {
+                println!("{:?}", err);
+            }
+        }
+        let mut file = File::create(path)?;
+        self.write_ppm(&mut file)
+    }
+
+    /*
+    每个输出变量写为单独的PFM图像：<prefix>_<名称>.pfm
+     */
+    pub fn save_aov_images(&self, prefix: &str) -> io::Result<()> {
+        for layer in self.layers.iter() {
+            if let Some(image) = self.get_aov_image(layer.aov) {
+                write_pfm(&format!("{}_{}.pfm", prefix, layer.aov.name()), &image)?;
+            }
+        }
+        Ok(())
+    }
+
+    /*
+    主图像与全部输出变量写入同一个多层EXR文件
+     */
+    pub fn save_exr(&self, path: &str) -> io::Result<()> {
+        let (width, height) = (self.width as usize, self.height as usize);
+        let plane = |pixel: &dyn Fn(i32, i32) -> f64| {
+            let mut data = Vec::with_capacity(width * height);
+            for y in 0..self.height {
+                for x in 0..self.width {
+                    data.push(pixel(x, y) as f32);
+                }
+            }
+            data
+        };
+
+        let mut channels = vec![
+            ("R".to_string(), plane(&|x, y| self.get_pixel(x, y).x())),
+            ("G".to_string(), plane(&|x, y| self.get_pixel(x, y).y())),
+            ("B".to_string(), plane(&|x, y| self.get_pixel(x, y).z()))
+        ];
+        for layer in self.layers.iter() {
+            let aov = layer.aov;
+            let value = |x: i32, y: i32| self.get_aov(aov, x, y).unwrap_or_default();
+            match aov.single_channel() {
+                Some(channel) => {
+                    channels.push((format!("{}.{}", aov.name(), channel), plane(&|x, y| value(x, y).x())));
+                }
+                None => {
+                    let names = if aov == Aov::Normal || aov == Aov::Position { ["X", "Y", "Z"] } else { ["R", "G", "B"] };
+                    channels.push((format!("{}.{}", aov.name(), names[0]), plane(&|x, y| value(x, y).x())));
+                    channels.push((format!("{}.{}", aov.name(), names[1]), plane(&|x, y| value(x, y).y())));
+                    channels.push((format!("{}.{}", aov.name(), names[2]), plane(&|x, y| value(x, y).z())));
+                }
+            }
+        }
+        write_exr(path, width, height, &channels)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::rc::Rc;
+    use super::*;
+    use crate::camera::Camera;
+    use crate::depth::DepthLimits;
+    use crate::filter::FilterType;
+    use crate::integrator::PathIntegrator;
+    use crate::light::PointLight;
+    use crate::material::Lambertian;
+    use crate::scene::Scene;
+    use crate::sphere::{HittableList, Sphere};
+
+    fn film(filter: FilterType, aovs: &[Aov]) -> Film {
+        Film::new(6, 4, filter.create(filter.default_radius()), aovs)
+    }
+
+    #[test]
+    fn constant_color_reconstructs_exactly() {
+        // 有负瓣的滤波器也按权重之和归一化
+        for filter in [FilterType::Box, FilterType::Gaussian, FilterType::Mitchell, FilterType::Lanczos] {
+            let mut film = film(filter, &[]);
+            let c = Color::new(0.2, 0.5, 0.9);
+            for k in 0..24 * 16 {
+                let (x, y) = ((k % 24) as f64 * 0.25 + 0.1, (k / 24) as f64 * 0.25 + 0.05);
+                film.add_sample(x, y, c, (0, 0, 6, 4));
+            }
+            for y in 0..4 {
+                for x in 0..6 {
+                    assert!((film.get_pixel(x, y) - c).length() < 1e-9, "{:?} at {},{}", filter, x, y);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn wide_filters_spread_samples_to_neighbours() {
+        let mut boxed = film(FilterType::Box, &[]);
+        boxed.add_sample(2.5, 1.5, Color::new(1.0, 1.0, 1.0), (0, 0, 6, 4));
+        assert_eq!(boxed.get_pixel(2, 1), Color::new(1.0, 1.0, 1.0));
+        assert_eq!(boxed.get_pixel(3, 1), Color::default());
+
+        let mut tent = film(FilterType::Tent, &[]);
+        tent.add_sample(2.5, 1.5, Color::new(1.0, 1.0, 1.0), (0, 0, 6, 4));
+        tent.add_sample(3.5, 1.5, Color::new(0.0, 0.0, 0.0), (0, 0, 6, 4));
+        // 像素2与像素3各自只收到自己中心的样本(帐篷在1个像素处为0)
+        assert_eq!(tent.get_pixel(2, 1), Color::new(1.0, 1.0, 1.0));
+        tent.add_sample(3.0, 1.5, Color::new(0.0, 0.0, 0.0), (0, 0, 6, 4));
+        assert!((tent.get_pixel(2, 1).x() - 2.0 / 3.0).abs() < 1e-12);
+    }
+
+    #[test]
+    fn window_keeps_samples_inside() {
+        // 立体拼接：左半幅的样本不写入右半幅
+        let mut film = film(FilterType::Gaussian, &[]);
+        film.add_sample(2.9, 1.5, Color::new(1.0, 1.0, 1.0), (0, 0, 3, 4));
+        assert!(film.get_pixel(2, 1).x() > 0.0);
+        assert_eq!(film.get_pixel(3, 1), Color::default());
+    }
+
+    #[test]
+    fn splats_are_scaled_and_added() {
+        let mut film = film(FilterType::Box, &[]);
+        film.add_sample(0.5, 0.5, Color::new(0.25, 0.25, 0.25), (0, 0, 6, 4));
+        film.add_splat(0.7, 0.2, Color::new(2.0, 2.0, 2.0));
+        film.add_splat(4.2, 3.9, Color::new(1.0, 0.0, 0.0));
+        film.add_splat(-0.5, 1.0, Color::new(9.0, 9.0, 9.0));
+        film.set_splat_scale(0.5);
+        assert_eq!(film.get_pixel(0, 0), Color::new(1.25, 1.25, 1.25));
+        assert_eq!(film.get_pixel(4, 3), Color::new(0.5, 0.0, 0.0));
+        // 替换主图像时清除溅射
+        let mut image = Image::new(6, 4);
+        image.set_pixel(4, 3, Color::new(0.1, 0.2, 0.3));
+        film.set_image(&image);
+        assert_eq!(film.get_pixel(4, 3), Color::new(0.1, 0.2, 0.3));
+    }
+
+    fn sample(hit: bool, depth: f64, object_id: i32, material_key: Option<usize>, direct: f64) -> SampleAovs {
+        let mut aovs = SampleAovs::new();
+        aovs.hit = hit;
+        if hit {
+            aovs.depth = depth;
+            aovs.albedo = Color::new(0.5, 0.5, 0.5);
+        }
+        aovs.object_id = object_id;
+        aovs.material_key = material_key;
+        aovs.direct = Color::new(direct, direct, direct);
+        aovs
+    }
+
+    #[test]
+    fn aovs_average_per_kind() {
+        let mut film = film(FilterType::Box, &[Aov::Depth, Aov::Albedo, Aov::ObjectId, Aov::MaterialId, Aov::Direct]);
+        // 像素(0,0)：两个击中样本与一个未击中的样本
+        film.add_aovs(0, 0, &sample(true, 2.0, 3, Some(0xa0), 1.0));
+        film.add_aovs(0, 0, &sample(false, 0.0, -1, None, 0.0));
+        film.add_aovs(0, 0, &sample(true, 4.0, 5, Some(0xb0), 2.0));
+        // 像素(1,0)只有未击中的样本
+        film.add_aovs(1, 0, &sample(false, 0.0, -1, None, 0.0));
+        film.add_aovs(2, 0, &sample(true, 1.0, 7, Some(0xb0), 0.0));
+
+        // 几何量只在击中的样本上平均，光照在全部样本上平均
+        assert_eq!(film.get_aov(Aov::Depth, 0, 0), Some(Color::new(3.0, 3.0, 3.0)));
+        assert_eq!(film.get_aov(Aov::Albedo, 0, 0), Some(Color::new(0.5, 0.5, 0.5)));
+        assert_eq!(film.get_aov(Aov::Direct, 0, 0), Some(Color::new(1.0, 1.0, 1.0)));
+        assert_eq!(film.get_aov(Aov::Depth, 1, 0), Some(Color::new(INFINITY, INFINITY, INFINITY)));
+        assert_eq!(film.get_aov(Aov::Albedo, 1, 0), Some(Color::default()));
+        // 序号取第一个样本，材质按首次出现的顺序编号
+        assert_eq!(film.get_aov(Aov::ObjectId, 0, 0), Some(Color::new(3.0, 0.0, 0.0)));
+        assert_eq!(film.get_aov(Aov::MaterialId, 0, 0), Some(Color::new(0.0, 0.0, 0.0)));
+        assert_eq!(film.get_aov(Aov::MaterialId, 2, 0), Some(Color::new(1.0, 0.0, 0.0)));
+        assert_eq!(film.get_aov(Aov::ObjectId, 1, 0), Some(Color::new(-1.0, 0.0, 0.0)));
+        assert_eq!(film.get_sample_count(0, 0), 3);
+
+        assert_eq!(film.get_aov(Aov::Normal, 0, 0), None);
+        assert!(film.get_aov_image(Aov::Normal).is_none());
+        assert_eq!(film.get_aov_image(Aov::Depth).unwrap().get_pixel(0, 0), Color::new(3.0, 3.0, 3.0));
+    }
+
+    #[test]
+    fn aov_names_round_trip() {
+        for aov in Aov::ALL {
+            assert_eq!(Aov::from_name(aov.name()), Some(aov));
+        }
+        assert_eq!(Aov::from_name("shadow"), None);
+    }
+
+    #[test]
+    fn aovs_split_the_beauty_image() {
+        // 点光源下的白色地面：相机正对地面，深度为1，法向量朝上
+        let mut world = HittableList::new();
+        world.add(Box::new(Sphere::new(Point::new(0.0, -1000.0, 0.0), 1000.0,
+                                       Rc::new(Lambertian::new(Color::new(1.0, 1.0, 1.0))))));
+        let mut scene = Scene::new(Box::new(world));
+        scene.add_light(Box::new(PointLight::new(Point::new(0.0, 2.0, 0.0), Color::new(4.0, 4.0, 4.0))));
+        let mut camera = Camera::new(9, 20.0, 1.0, 4, Point::new(0.0, 1.0, 0.0), Point::default(),
+                                     Vec3::new(0.0, 0.0, -1.0), 1.0, 0.0);
+        camera.set_integrator(Box::new(PathIntegrator::new(DepthLimits::default(), true)));
+        let film = camera.render_film(&scene, &[Aov::Depth, Aov::Normal, Aov::Albedo, Aov::Direct, Aov::Indirect, Aov::Emission]);
+        let depth = film.get_aov(Aov::Depth, 4, 4).unwrap();
+        assert!((depth.x() - 1.0).abs() < 0.01, "{:?}", depth);
+        assert!((film.get_aov(Aov::Normal, 4, 4).unwrap() - Vec3::new(0.0, 1.0, 0.0)).length() < 1e-3);
+        assert_eq!(film.get_aov(Aov::Albedo, 4, 4), Some(Color::new(1.0, 1.0, 1.0)));
+        for (x, y) in [(0, 0), (4, 4), (8, 2)] {
+            let sum = film.get_aov(Aov::Direct, x, y).unwrap() + film.get_aov(Aov::Indirect, x, y).unwrap()
+                + film.get_aov(Aov::Emission, x, y).unwrap();
+            assert!((sum - film.get_pixel(x, y)).length() < 1e-9, "{:?} vs {:?}", sum, film.get_pixel(x, y));
+        }
+    }
+}
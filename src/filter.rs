@@ -0,0 +1,273 @@
+use crate::common::PI;
+
+/*
+像素重建滤波器：样本按其到像素中心的偏移(像素单位)加权后累加到半径内的所有像素
+ */
+pub trait Filter {
+    // 滤波器在x、y方向上的支撑半径
+    fn radius(&self) -> f64;
+
+    fn evaluate(&self, x: f64, y: f64) -> f64;
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum FilterType {
+    Box,
+    Tent,
+    Gaussian,
+    Mitchell,
+    Lanczos
+}
+
+impl FilterType {
+    pub fn from_name(name: &str) -> Option<FilterType> {
+        match name {
+            "box" => Some(FilterType::Box),
+            "tent" | "triangle" => Some(FilterType::Tent),
+            "gaussian" => Some(FilterType::Gaussian),
+            "mitchell" => Some(FilterType::Mitchell),
+            "lanczos" | "sinc" => Some(FilterType::Lanczos),
+            _ => None
+        }
+    }
+
+    // 各滤波器的常用半径
+    pub fn default_radius(self) -> f64 {
+        match self {
+            FilterType::Box => 0.5,
+            FilterType::Tent => 1.0,
+            FilterType::Gaussian => 1.5,
+            FilterType::Mitchell => 2.0,
+            FilterType::Lanczos => 2.0
+        }
+    }
+
+    pub fn create(self, radius: f64) -> Box<dyn Filter> {
+        match self {
+            FilterType::Box => Box::new(BoxFilter::new(radius)),
+            FilterType::Tent => Box::new(TentFilter::new(radius)),
+            FilterType::Gaussian => Box::new(GaussianFilter::new(radius, radius / 3.0)),
+            FilterType::Mitchell => Box::new(MitchellFilter::new(radius, 1.0 / 3.0, 1.0 / 3.0)),
+            FilterType::Lanczos => Box::new(LanczosFilter::new(radius, radius))
+        }
+    }
+}
+
+/*
+盒式滤波器，半径0.5时等价于对像素内样本取平均
+ */
+pub struct BoxFilter {
+    radius: f64
+}
+
+impl BoxFilter {
+    pub fn new(radius: f64) -> BoxFilter {
+        BoxFilter {
+            radius
+        }
+    }
+}
+
+impl Filter for BoxFilter {
+    fn radius(&self) -> f64 {
+        self.radius
+    }
+
+    fn evaluate(&self, x: f64, y: f64) -> f64 {
+        if x.abs() < self.radius && y.abs() < self.radius { 1.0 } else { 0.0 }
+    }
+}
+
+/*
+三角形(帐篷)滤波器
+ */
+pub struct TentFilter {
+    radius: f64
+}
+
+impl TentFilter {
+    pub fn new(radius: f64) -> TentFilter {
+        TentFilter {
+            radius
+        }
+    }
+}
+
+impl Filter for TentFilter {
+    fn radius(&self) -> f64 {
+        self.radius
+    }
+
+    fn evaluate(&self, x: f64, y: f64) -> f64 {
+        (self.radius - x.abs()).max(0.0) * (self.radius - y.abs()).max(0.0)
+    }
+}
+
+/*
+高斯滤波器，减去半径处的值使其在边界处连续地降为0
+ */
+pub struct GaussianFilter {
+    radius: f64,
+    sigma: f64
+}
+
+impl GaussianFilter {
+    pub fn new(radius: f64, sigma: f64) -> GaussianFilter {
+        GaussianFilter {
+            radius,
+            sigma
+        }
+    }
+
+    fn gaussian(&self, x: f64) -> f64 {
+        (-x * x / (2.0 * self.sigma * self.sigma)).exp()
+    }
+}
+
+impl Filter for GaussianFilter {
+    fn radius(&self) -> f64 {
+        self.radius
+    }
+
+    fn evaluate(&self, x: f64, y: f64) -> f64 {
+        let edge = self.gaussian(self.radius);
+        (self.gaussian(x) - edge).max(0.0) * (self.gaussian(y) - edge).max(0.0)
+    }
+}
+
+/*
+Mitchell-Netravali三次滤波器，B=C=1/3时模糊与振铃之间较为平衡；有负瓣
+ */
+pub struct MitchellFilter {
+    radius: f64,
+    b: f64,
+    c: f64
+}
+
+impl MitchellFilter {
+    pub fn new(radius: f64, b: f64, c: f64) -> MitchellFilter {
+        MitchellFilter {
+            radius,
+            b,
+            c
+        }
+    }
+
+    // x ∈ [-2, 2]
+    fn mitchell_1d(&self, x: f64) -> f64 {
+        let (b, c) = (self.b, self.c);
+        let x = x.abs();
+        if x <= 1.0 {
+            ((12.0 - 9.0 * b - 6.0 * c) * x * x * x + (-18.0 + 12.0 * b + 6.0 * c) * x * x + (6.0 - 2.0 * b)) / 6.0
+        } else if x <= 2.0 {
+            ((-b - 6.0 * c) * x * x * x + (6.0 * b + 30.0 * c) * x * x + (-12.0 * b - 48.0 * c) * x + (8.0 * b + 24.0 * c)) / 6.0
+        } else {
+            0.0
+        }
+    }
+}
+
+impl Filter for MitchellFilter {
+    fn radius(&self) -> f64 {
+        self.radius
+    }
+
+    fn evaluate(&self, x: f64, y: f64) -> f64 {
+        self.mitchell_1d(2.0 * x / self.radius) * self.mitchell_1d(2.0 * y / self.radius)
+    }
+}
+
+/*
+Lanczos窗口sinc滤波器，tau为窗口中sinc的周期数；有负瓣
+ */
+pub struct LanczosFilter {
+    radius: f64,
+    tau: f64
+}
+
+impl LanczosFilter {
+    pub fn new(radius: f64, tau: f64) -> LanczosFilter {
+        LanczosFilter {
+            radius,
+            tau
+        }
+    }
+
+    fn windowed_sinc(&self, x: f64) -> f64 {
+        let x = x.abs();
+        if x > self.radius {
+            return 0.0;
+        }
+        sinc(x) * sinc(x / self.tau)
+    }
+}
+
+impl Filter for LanczosFilter {
+    fn radius(&self) -> f64 {
+        self.radius
+    }
+
+    fn evaluate(&self, x: f64, y: f64) -> f64 {
+        self.windowed_sinc(x) * self.windowed_sinc(y)
+    }
+}
+
+fn sinc(x: f64) -> f64 {
+    if x.abs() < 1e-5 {
+        return 1.0;
+    }
+    (PI * x).sin() / (PI * x)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ALL: [FilterType; 5] = [FilterType::Box, FilterType::Tent, FilterType::Gaussian,
+        FilterType::Mitchell, FilterType::Lanczos];
+
+    #[test]
+    fn filter_from_name() {
+        assert_eq!(FilterType::from_name("triangle"), Some(FilterType::Tent));
+        assert_eq!(FilterType::from_name("sinc"), Some(FilterType::Lanczos));
+        assert_eq!(FilterType::from_name("bilinear"), None);
+    }
+
+    #[test]
+    fn filters_vanish_outside_their_radius() {
+        for filter_type in ALL {
+            let radius = filter_type.default_radius();
+            let filter = filter_type.create(radius);
+            assert_eq!(filter.radius(), radius);
+            assert!(filter.evaluate(0.0, 0.0) > 0.0, "{:?}", filter_type);
+            assert_eq!(filter.evaluate(radius * 1.01, 0.0), 0.0, "{:?}", filter_type);
+            assert_eq!(filter.evaluate(0.0, -radius * 1.01), 0.0, "{:?}", filter_type);
+            // 可分离且对称
+            assert!((filter.evaluate(0.3, -0.2) - filter.evaluate(-0.3, 0.2)).abs() < 1e-12);
+            assert!((filter.evaluate(0.3, 0.2) * filter.evaluate(0.0, 0.0)
+                - filter.evaluate(0.3, 0.0) * filter.evaluate(0.0, 0.2)).abs() < 1e-12);
+        }
+        // 高斯在边界处连续降为0
+        assert!(FilterType::Gaussian.create(1.5).evaluate(1.4999, 0.0) < 1e-4);
+    }
+
+    #[test]
+    fn tent_and_mitchell_partition_unity() {
+        // 半径1的帐篷滤波器与B + 2C = 1的Mitchell滤波器，一维权重在整数间隔上的和为1，
+        // 因此沿x方向的和等于y方向的一维权重f(0) = sqrt(f(0, 0))
+        for filter in [FilterType::Tent.create(1.0), FilterType::Mitchell.create(2.0)] {
+            let f0 = filter.evaluate(0.0, 0.0).sqrt();
+            for x in [0.0, 0.1, 0.25, 0.5, 0.9] {
+                let sum: f64 = (-3..=3).map(|k| filter.evaluate(x + f64::from(k), 0.0)).sum();
+                assert!((sum - f0).abs() < 1e-9, "{} at {}", sum, x);
+            }
+        }
+    }
+
+    #[test]
+    fn negative_lobes() {
+        assert!(FilterType::Mitchell.create(2.0).evaluate(1.5, 0.0) < 0.0);
+        assert!(FilterType::Lanczos.create(2.0).evaluate(1.5, 0.0) < 0.0);
+        assert!(FilterType::Gaussian.create(1.5).evaluate(1.0, 0.0) > 0.0);
+    }
+}
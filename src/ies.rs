@@ -0,0 +1,220 @@
+use std::fs;
+use std::io;
+use crate::vec3::{cross, dot, Vec3};
+
+fn invalid_data(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg.to_string())
+}
+
+/*
+IES LM-63 光度数据(C类光度坐标)。
+竖直角0°为灯具正下方(nadir)，水平角绕竖直轴度量；发光强度单位为坎德拉
+ */
+pub struct IesProfile {
+    vertical_angles: Vec<f64>,
+    horizontal_angles: Vec<f64>,
+    // candela[h][v]，已乘以坎德拉倍率与镇流器系数
+    candela: Vec<Vec<f64>>
+}
+
+impl IesProfile {
+    pub fn load(path: &str) -> io::Result<IesProfile> {
+        IesProfile::parse(&fs::read_to_string(path)?)
+    }
+
+    pub fn parse(text: &str) -> io::Result<IesProfile> {
+        // 关键字部分以TILT=行结束，之后全部为数值
+        let mut lines = text.lines();
+        let tilt = loop {
+            let line = lines.next().ok_or_else(|| invalid_data("Missing TILT line in IES file"))?;
+            if let Some(tilt) = line.trim().strip_prefix("TILT=") {
+                break tilt.trim().to_string();
+            }
+        };
+        let rest: Vec<&str> = lines.collect();
+        let mut values = rest.iter()
+            .flat_map(|line| line.split(|c: char| c.is_whitespace() || c == ','))
+            .filter(|token| !token.is_empty())
+            .map(|token| token.parse::<f64>().map_err(|_| invalid_data("Invalid number in IES file")));
+        let mut next = || values.next().unwrap_or_else(|| Err(invalid_data("Truncated IES file")));
+
+        if tilt == "INCLUDE" {
+            // 灯具几何类型、角度与倍率对，渲染中不使用
+            next()?;
+            let pairs = next()? as usize;
+            for _ in 0..pairs * 2 {
+                next()?;
+            }
+        } else if tilt != "NONE" {
+            return Err(invalid_data("External TILT files are not supported"));
+        }
+
+        let _lamps = next()?;
+        let _lumens_per_lamp = next()?;
+        let multiplier = next()?;
+        let vertical_count = next()? as usize;
+        let horizontal_count = next()? as usize;
+        let photometric_type = next()? as i32;
+        if photometric_type != 1 {
+            return Err(invalid_data("Only type C photometry is supported"));
+        }
+        // 单位、灯具尺寸
+        for _ in 0..4 {
+            next()?;
+        }
+        let ballast_factor = next()?;
+        // 镇流器光度系数(保留字段)与输入功率
+        next()?;
+        next()?;
+
+        let vertical_angles = (0..vertical_count).map(|_| next()).collect::<io::Result<Vec<f64>>>()?;
+        let horizontal_angles = (0..horizontal_count).map(|_| next()).collect::<io::Result<Vec<f64>>>()?;
+        let mut candela = Vec::with_capacity(horizontal_count);
+        for _ in 0..horizontal_count {
+            let row = (0..vertical_count)
+                .map(|_| next().map(|v| v * multiplier * ballast_factor))
+                .collect::<io::Result<Vec<f64>>>()?;
+            candela.push(row);
+        }
+        if vertical_angles.is_empty() || horizontal_angles.is_empty() {
+            return Err(invalid_data("IES file contains no angles"));
+        }
+
+        Ok(IesProfile {
+            vertical_angles,
+            horizontal_angles,
+            candela
+        })
+    }
+
+    pub fn max_candela(&self) -> f64 {
+        self.candela.iter().flatten().fold(0.0, |a: f64, &b| a.max(b))
+    }
+
+    // 在有序角度表中查找所在区间与插值系数
+    fn locate(angles: &[f64], angle: f64) -> Option<(usize, f64)> {
+        let last = angles.len() - 1;
+        if angle < angles[0] || angle > angles[last] {
+            return None;
+        }
+        if last == 0 {
+            return Some((0, 0.0));
+        }
+        let index = angles.partition_point(|&a| a <= angle).clamp(1, last) - 1;
+        let span = angles[index + 1] - angles[index];
+        let t = if span > 0.0 { (angle - angles[index]) / span } else { 0.0 };
+        Some((index, t))
+    }
+
+    /*
+    给定竖直角与水平角(角度制)的发光强度，按水平角的对称性折叠后做双线性插值
+     */
+    pub fn candela(&self, vertical: f64, horizontal: f64) -> f64 {
+        let first_horizontal = self.horizontal_angles[0];
+        let last_horizontal = *self.horizontal_angles.last().unwrap_or(&0.0);
+        let mut h = horizontal.rem_euclid(360.0);
+        if self.horizontal_angles.len() == 1 {
+            // 旋转对称
+            h = first_horizontal;
+        } else if first_horizontal == 0.0 && last_horizontal == 90.0 {
+            // 四象限对称
+            h = if h > 270.0 { 360.0 - h } else if h > 180.0 { h - 180.0 } else if h > 90.0 { 180.0 - h } else { h };
+        } else if first_horizontal == 0.0 && last_horizontal == 180.0 && h > 180.0 {
+            // 关于0°-180°平面对称
+            h = 360.0 - h;
+        } else if first_horizontal == 90.0 && last_horizontal == 270.0 {
+            // 关于90°-270°平面对称
+            h = if h < 90.0 { 180.0 - h } else if h > 270.0 { 540.0 - h } else { h };
+        }
+        // 其余不完整的范围取最近的测量平面
+        let h = h.clamp(first_horizontal, last_horizontal);
+
+        let (v_index, v_t) = match IesProfile::locate(&self.vertical_angles, vertical) {
+            Some(located) => located,
+            None => return 0.0
+        };
+        let (h_index, h_t) = IesProfile::locate(&self.horizontal_angles, h).unwrap_or((0, 0.0));
+        let v_next = (v_index + 1).min(self.vertical_angles.len() - 1);
+        let h_next = (h_index + 1).min(self.horizontal_angles.len() - 1);
+
+        let lerp = |row: &Vec<f64>| row[v_index] * (1.0 - v_t) + row[v_next] * v_t;
+        lerp(&self.candela[h_index]) * (1.0 - h_t) + lerp(&self.candela[h_next]) * h_t
+    }
+
+    /*
+    灯具竖直轴指向nadir时，direction方向(由光源指向外)的发光强度
+     */
+    pub fn candela_toward(&self, nadir: Vec3, direction: Vec3) -> f64 {
+        let reference = if nadir.x().abs() > 0.9 { Vec3::new(0.0, 0.0, 1.0) } else { Vec3::new(1.0, 0.0, 0.0) };
+        let tangent = cross(nadir, reference).unit_vector();
+        let bitangent = cross(nadir, tangent);
+        let d = direction.unit_vector();
+        let vertical = dot(d, nadir).clamp(-1.0, 1.0).acos().to_degrees();
+        let horizontal = dot(d, bitangent).atan2(dot(d, tangent)).to_degrees();
+        self.candela(vertical, horizontal)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // 两个竖直角、给定水平角的最小C类文件，candela按水平角逐行给出
+    fn profile(tilt: &str, horizontal: &[f64], candela: &[[f64; 2]]) -> String {
+        let angles: Vec<String> = horizontal.iter().map(|a| a.to_string()).collect();
+        let rows: Vec<String> = candela.iter().map(|row| format!("{} {}", row[0], row[1])).collect();
+        format!("IESNA:LM-63-2002\n[TEST] unit\nTILT={}\n1 1000 2 2 {} 1 2 0 0 0\n0.5 1 100\n0 90\n{}\n{}\n",
+                tilt, horizontal.len(), angles.join(" "), rows.join("\n"))
+    }
+
+    #[test]
+    fn parses_tilt_none_with_multiplier_and_ballast() {
+        let ies = IesProfile::parse(&profile("NONE", &[0.0], &[[100.0, 40.0]])).unwrap();
+        // 倍率2、镇流器系数0.5
+        assert_eq!(ies.max_candela(), 100.0);
+        assert_eq!(ies.candela(0.0, 0.0), 100.0);
+        assert_eq!(ies.candela(90.0, 123.0), 40.0);
+        // 竖直角范围之外没有光
+        assert_eq!(ies.candela(120.0, 0.0), 0.0);
+    }
+
+    #[test]
+    fn skips_included_tilt_table() {
+        let text = profile("NONE", &[0.0], &[[100.0, 40.0]])
+            .replace("TILT=NONE\n", "TILT=INCLUDE\n1\n3\n0 45 90\n1 0.9 0.8\n");
+        let ies = IesProfile::parse(&text).unwrap();
+        assert_eq!(ies.candela(0.0, 0.0), 100.0);
+        assert!(IesProfile::parse(&profile("lamp.tlt", &[0.0], &[[1.0, 1.0]])).is_err());
+        assert!(IesProfile::parse("IESNA:LM-63-2002\n").is_err());
+    }
+
+    #[test]
+    fn interpolates_candela() {
+        let ies = IesProfile::parse(&profile("NONE", &[0.0, 90.0], &[[100.0, 0.0], [50.0, 0.0]])).unwrap();
+        // 竖直方向线性插值
+        assert_eq!(ies.candela(45.0, 0.0), 50.0);
+        // 水平方向线性插值，四象限对称折叠
+        assert_eq!(ies.candela(0.0, 45.0), 75.0);
+        assert_eq!(ies.candela(0.0, 135.0), 75.0);
+        assert_eq!(ies.candela(0.0, 270.0), 50.0);
+    }
+
+    #[test]
+    fn folds_ranges_starting_at_ninety() {
+        let ies = IesProfile::parse(&profile("NONE", &[90.0, 180.0, 270.0], &[[10.0, 0.0], [30.0, 0.0], [50.0, 0.0]])).unwrap();
+        assert_eq!(ies.candela(0.0, 180.0), 30.0);
+        assert_eq!(ies.candela(0.0, 225.0), 40.0);
+        // 关于90°-270°平面对称：0°与180°、315°与225°相同
+        assert_eq!(ies.candela(0.0, 0.0), 30.0);
+        assert_eq!(ies.candela(0.0, 315.0), 40.0);
+        assert_eq!(ies.candela(0.0, 45.0), ies.candela(0.0, 135.0));
+    }
+
+    #[test]
+    fn candela_toward_measures_from_nadir() {
+        let ies = IesProfile::parse(&profile("NONE", &[0.0], &[[100.0, 40.0]])).unwrap();
+        let nadir = Vec3::new(0.0, -1.0, 0.0);
+        assert_eq!(ies.candela_toward(nadir, Vec3::new(0.0, -3.0, 0.0)), 100.0);
+        assert!((ies.candela_toward(nadir, Vec3::new(1.0, 0.0, 0.0)) - 40.0).abs() < 1e-9);
+    }
+}
@@ -1,11 +1,76 @@
 use std::rc::Rc;
+use crate::aperture::ApertureShape;
 use crate::camera::Camera;
-use crate::common::{get_random_double, PI, random_double};
-use crate::material::{Dielectric, Lambertian, Metal};
+use crate::common::{get_random_double, random_double, seed_random};
+use crate::material::{AlphaMaskedMaterial, AlphaMode, CoatedMaterial, Dielectric, Dispersion, Lambertian, Material, Metal, MixMaterial, NormalMappedMaterial};
+use crate::environment::EnvironmentLight;
+use crate::film::Aov;
+use crate::ies::IesProfile;
+use crate::integrator::IntegratorType;
+use crate::lens::RealisticLens;
+use crate::light::{DirectionalLight, PointLight, SpotLight};
+use crate::options::RenderOptions;
+use crate::physical_camera::PhysicalCamera;
+use crate::scene::Scene;
+use crate::sky::PhysicalSky;
+use crate::thin_film::ThinFilm;
+use crate::texture::{ConstantTexture, UvCheckerTexture};
+use crate::transform::{AnimatedInstance, AnimatedTransform, Quaternion, Transform};
+use crate::stereo::StereoRig;
 use crate::vec3::{Color, Point, Vec3};
 use crate::sphere::*;
 
+// 随机场景构建使用的随机数流，与像素样本的随机数流相互独立
+const SCENE_SEED_STREAM: u64 = u64::MAX;
+
+// 输出图像(立体渲染时为单眼图像)的宽高比
+pub const ASPECT_RATIO: f64 = 16.0 / 9.0;
+
 pub fn print_image(width:i32) {
+    print_image_with_options(&RenderOptions::new(width));
+}
+
+/*
+可选的场景
+ */
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum SceneType {
+    // 随机小球(默认)
+    Random,
+    // 关键帧动画的物体，配合快门区间展示运动模糊
+    Motion,
+    // 黑色背景下由点光源、聚光灯与平行光照亮的小球，需要光源采样的积分器
+    Lights,
+    // 一排展示各种材质的小球
+    Materials
+}
+
+impl SceneType {
+    pub fn from_name(name: &str) -> Option<SceneType> {
+        match name {
+            "random" => Some(SceneType::Random),
+            "motion" => Some(SceneType::Motion),
+            "lights" => Some(SceneType::Lights),
+            "materials" => Some(SceneType::Materials),
+            _ => None
+        }
+    }
+
+    /*
+    shutter为相机的快门区间，运动物体的包围盒需要覆盖它；
+    ies为点光源与聚光灯的配光，只有lights场景使用
+     */
+    pub fn build(self, shutter: (f64, f64), ies: Option<Rc<IesProfile>>) -> Scene {
+        match self {
+            SceneType::Random => Scene::new(Box::new(random_spheres(shutter))),
+            SceneType::Motion => Scene::new(Box::new(motion_scene(shutter))),
+            SceneType::Lights => light_scene(ies),
+            SceneType::Materials => Scene::new(Box::new(material_scene()))
+        }
+    }
+}
+
+fn random_spheres(shutter: (f64, f64)) -> HittableList {
     // sphere
     let mut world = HittableList::new();
     let ground_material = Rc::new(Lambertian::new(Color::new(0.5, 0.5, 0.5)));
@@ -22,7 +87,9 @@ pub fn print_image(width:i32) {
                     // diffuse
                     let albedo = Color::random() * Color::random();
                     let center2 = center + Vec3::new(0.0, random_double(0.0, 0.5), 0.0);
-                    world.add(Box::new(Sphere::new_moving_sphere(center,center2, 0.2, Rc::new(Lambertian::new(albedo)))));
+                    let mut sphere = Sphere::new_moving_sphere(center,center2, 0.2, Rc::new(Lambertian::new(albedo)));
+                    sphere.set_shutter(shutter.0, shutter.1);
+                    world.add(Box::new(sphere));
                 }
                 else if choose_mat < 0.95 {
                     // metal
@@ -44,12 +111,298 @@ pub fn print_image(width:i32) {
                                    Rc::new(Lambertian::new(Color::new(0.4, 0.2, 0.1))))));
     world.add(Box::new(Sphere::new(Point::new(4.0, 1.0, 0.0), 1.0,
                                    Rc::new(Metal::new(Color::new(0.7, 0.6, 0.5), 0.0)))));
+    world
+}
+
+/*
+关键帧动画：平移、绕轴旋转(棋盘格纹理便于看出转动)、缩放的物体，
+以及沿直线运动的小球，快门区间为[0,1]时关键帧覆盖整个曝光过程
+ */
+fn motion_scene(shutter: (f64, f64)) -> HittableList {
+    let mut world = HittableList::new();
+    world.add(Box::new(Sphere::new(Point::new(0.0, -1000.0, 0.0), 1000.0,
+                                   Rc::new(Lambertian::new(Color::new(0.5, 0.5, 0.5))))));
+
+    let unit_sphere = |material: Rc<dyn Material>| Box::new(Sphere::new(Point::default(), 1.0, material));
+    let no_rotation = Quaternion::identity();
+    let unit_scale = Vec3::new(1.0, 1.0, 1.0);
+
+    let mut sliding = AnimatedTransform::new();
+    sliding.add_keyframe(0.0, Transform::new(Point::new(0.0, 0.7, -3.5), no_rotation, unit_scale * 0.7));
+    sliding.add_keyframe(1.0, Transform::new(Point::new(0.0, 0.7, -2.0), no_rotation, unit_scale * 0.7));
+    world.add(Box::new(AnimatedInstance::new(unit_sphere(Rc::new(Lambertian::new(Color::new(0.7, 0.2, 0.1)))), sliding)));
+
+    let checker = Rc::new(UvCheckerTexture::new(8.0, 4.0, Rc::new(ConstantTexture::new(Color::default())),
+                                                Rc::new(ConstantTexture::new(Color::new(1.0, 1.0, 1.0)))));
+    let checkered = Rc::new(MixMaterial::new(Rc::new(Lambertian::new(Color::new(0.9, 0.9, 0.9))),
+                                             Rc::new(Lambertian::new(Color::new(0.1, 0.2, 0.5))), checker));
+    let mut spinning = AnimatedTransform::new();
+    let y_axis = Vec3::new(0.0, 1.0, 0.0);
+    spinning.add_keyframe(0.0, Transform::new(Point::new(0.0, 1.0, 0.0), no_rotation, unit_scale));
+    spinning.add_keyframe(1.0, Transform::new(Point::new(0.0, 1.0, 0.0), Quaternion::from_axis_angle(y_axis, 60.0), unit_scale));
+    world.add(Box::new(AnimatedInstance::new(unit_sphere(checkered), spinning)));
+
+    let mut bouncing = AnimatedTransform::new();
+    bouncing.add_keyframe(0.0, Transform::new(Point::new(0.0, 0.5, 2.5), no_rotation, unit_scale * 0.5));
+    bouncing.add_keyframe(0.5, Transform::new(Point::new(0.0, 1.3, 2.5), no_rotation, Vec3::new(0.6, 0.8, 0.6)));
+    bouncing.add_keyframe(1.0, Transform::new(Point::new(0.0, 0.5, 2.5), no_rotation, unit_scale * 0.5));
+    world.add(Box::new(AnimatedInstance::new(unit_sphere(Rc::new(Metal::new(Color::new(0.8, 0.8, 0.8), 0.1))), bouncing)));
+
+    for k in 0..5 {
+        let center = Point::new(2.5, 0.2, -2.0 + f64::from(k));
+        let mut sphere = Sphere::new_moving_sphere(center, center + Vec3::new(0.0, 0.3, 0.0), 0.2,
+                                                   Rc::new(Lambertian::new(Color::new(0.2, 0.6, 0.2))));
+        sphere.set_shutter(shutter.0, shutter.1);
+        world.add(Box::new(sphere));
+    }
+    world
+}
+
+/*
+解析光源照明的场景：暖色点光源、照向右侧小球的聚光灯与微弱的蓝色平行光(月光)。
+给出IES配光时点光源朝下安装，强度按配光的最大坎德拉值归一化
+ */
+fn light_scene(ies: Option<Rc<IesProfile>>) -> Scene {
+    let mut world = HittableList::new();
+    world.add(Box::new(Sphere::new(Point::new(0.0, -1000.0, 0.0), 1000.0,
+                                   Rc::new(Lambertian::new(Color::new(0.5, 0.5, 0.5))))));
+    world.add(Box::new(Sphere::new(Point::new(0.0, 1.0, 0.0), 1.0,
+                                   Rc::new(Lambertian::new(Color::new(0.8, 0.8, 0.8))))));
+    world.add(Box::new(Sphere::new(Point::new(1.0, 0.5, -2.2), 0.5,
+                                   Rc::new(Metal::new(Color::new(0.8, 0.6, 0.2), 0.2)))));
+    world.add(Box::new(Sphere::new(Point::new(1.0, 0.5, 2.2), 0.5,
+                                   Rc::new(Lambertian::new(Color::new(0.2, 0.4, 0.8))))));
+
+    let mut scene = Scene::new(Box::new(world));
+    scene.set_background(Some(Color::default()));
+    let scale = ies.as_ref().map_or(1.0, |profile| 1.0 / profile.max_candela().max(1e-12));
+    let mut point = PointLight::new(Point::new(3.0, 3.0, -1.5), Color::new(16.0, 12.0, 8.0) * scale);
+    let mut spot = SpotLight::new(Point::new(3.0, 4.0, 3.5), Point::new(1.0, 0.5, 2.2) - Point::new(3.0, 4.0, 3.5),
+                                  Color::new(30.0, 30.0, 30.0) * scale, 10.0, 20.0);
+    if let Some(profile) = ies {
+        point.set_profile(profile.clone(), Vec3::new(0.0, -1.0, 0.0));
+        spot.set_profile(profile);
+    }
+    scene.add_light(Box::new(point));
+    scene.add_light(Box::new(spot));
+    scene.add_light(Box::new(DirectionalLight::new(Vec3::new(-0.3, -1.0, -0.5), Color::new(0.05, 0.07, 0.15))));
+    scene
+}
+
+// 材质展示场景中的小球数量
+const MATERIAL_SLOTS: i32 = 7;
+
+/*
+材质展示：在默认相机视野中横向排成一排的小球，从左到右依次为
+薄膜干涉的肥皂泡、带氧化膜的金属、按条纹混合的金与陶土、涂了清漆的红色漫反射、
+法线贴图做出斜面的白色漫反射、透明遮罩镂空成网格的橙色球壳、
+有色散的玻璃(需配合--spectral)
+ */
+fn material_scene() -> HittableList {
+    let mut world = HittableList::new();
+    world.add(Box::new(Sphere::new(Point::new(0.0, -1000.0, 0.0), 1000.0,
+                                   Rc::new(Lambertian::new(Color::new(0.5, 0.5, 0.5))))));
+
+    // 与视线垂直的水平方向
+    let across = Vec3::new(0.22, 0.0, -0.97).unit_vector();
+    let slot = |k: i32| Point::new(0.0, 0.5, 0.0) + across * (1.15 * (f64::from(k) - f64::from(MATERIAL_SLOTS - 1) / 2.0));
+
+    let mut bubble = Dielectric::new(1.0);
+    bubble.set_thin_film(Some(ThinFilm::new(380.0, 1.33)));
+    world.add(Box::new(Sphere::new(slot(0), 0.5, Rc::new(bubble))));
+
+    let mut oxidized = Metal::new(Color::new(0.8, 0.8, 0.85), 0.05);
+    oxidized.set_thin_film(Some(ThinFilm::new(250.0, 2.4)));
+    world.add(Box::new(Sphere::new(slot(1), 0.5, Rc::new(oxidized))));
+
+    let stripes = Rc::new(UvCheckerTexture::new(1.0, 6.0, Rc::new(ConstantTexture::new(Color::default())),
+                                                Rc::new(ConstantTexture::new(Color::new(1.0, 1.0, 1.0)))));
+    let gold = Rc::new(Metal::new(Color::new(0.9, 0.7, 0.3), 0.1));
+    let clay = Rc::new(Lambertian::new(Color::new(0.6, 0.35, 0.25)));
+    world.add(Box::new(Sphere::new(slot(2), 0.5, Rc::new(MixMaterial::new(clay, gold, stripes)))));
+
+    let lacquered = CoatedMaterial::new(Rc::new(Lambertian::new(Color::new(0.7, 0.05, 0.05))), 1.5);
+    world.add(Box::new(Sphere::new(slot(3), 0.5, Rc::new(lacquered))));
+
+    // 切线空间法向量交替向+u、-u倾斜
+    let facets = Rc::new(UvCheckerTexture::new(12.0, 1.0, Rc::new(ConstantTexture::new(Color::new(0.8, 0.5, 0.9))),
+                                               Rc::new(ConstantTexture::new(Color::new(0.2, 0.5, 0.9)))));
+    let faceted = NormalMappedMaterial::new_normal_map(Rc::new(Lambertian::new(Color::new(0.8, 0.8, 0.8))), facets);
+    world.add(Box::new(Sphere::new(slot(4), 0.5, Rc::new(faceted))));
+
+    let lattice = Rc::new(UvCheckerTexture::new(12.0, 6.0, Rc::new(ConstantTexture::new(Color::default())),
+                                                Rc::new(ConstantTexture::new(Color::new(1.0, 1.0, 1.0)))));
+    let cutout = AlphaMaskedMaterial::new(Rc::new(Lambertian::new(Color::new(0.9, 0.5, 0.1))), lattice, AlphaMode::Threshold(0.5));
+    world.add(Box::new(Sphere::new(slot(5), 0.5, Rc::new(cutout))));
+
+    let flint = Dielectric::new_dispersive(1.5, Dispersion::from_abbe(1.5, 25.0));
+    world.add(Box::new(Sphere::new(slot(6), 0.5, Rc::new(flint))));
+    world
+}
 
-    let camera = Camera::new(width, 20.0, 16.0 / 9.0, 100,
+/*
+光圈形状与真实镜头。使用真实镜头时光圈形状作为镜头内光阑的形状，
+传感器宽度取物理相机参数(默认36mm)
+ */
+fn set_lens(camera: &mut Camera, options: &RenderOptions) {
+    let name = match &options.lens {
+        Some(name) => name,
+        None => {
+            match options.aperture.create() {
+                Ok(aperture) => camera.set_aperture(aperture),
+                Err(err) => println!("Failed to load aperture {:?}: {}", options.aperture, err)
+            }
+            return;
+        }
+    };
+    let sensor_width = options.physical.map_or(PhysicalCamera::default().sensor_width, |physical| physical.sensor_width);
+    let lens = if name == "double-gauss" {
+        Ok(RealisticLens::double_gauss_50mm(sensor_width, camera.get_aspect_ratio(), camera.get_focus_dist()))
+    } else {
+        RealisticLens::load(name, sensor_width, camera.get_aspect_ratio(), camera.get_focus_dist())
+    };
+    match lens {
+        Ok(mut lens) => {
+            if options.aperture != ApertureShape::Circle {
+                match options.aperture.create() {
+                    Ok(shape) => lens.set_stop_shape(shape),
+                    Err(err) => println!("Failed to load aperture {:?}: {}", options.aperture, err)
+                }
+            }
+            camera.set_model(Box::new(lens));
+        }
+        Err(err) => println!("Failed to load lens {}: {}", name, err)
+    }
+}
+
+pub fn print_image_with_options(options: &RenderOptions) {
+    seed_random(options.seed, SCENE_SEED_STREAM);
+
+    let mut camera = Camera::new(options.width, 20.0, ASPECT_RATIO, options.samples_per_pixel,
                              Point::new(13.0, 2.0, 3.0),
                              Point::new(0.0, 0.0, 0.0),
                              Vec3::new(0.0, 1.0, 0.0),
                              10.0,
                              0.6);
-    camera.render(&world);
+    camera.set_projection(options.projection);
+    camera.set_sampler(options.sampler);
+    camera.set_seed(options.seed);
+    camera.set_integrator(options.integrator.create(options));
+    camera.set_adaptive(options.adaptive.clone());
+    camera.set_filter(options.filter, options.filter_radius.unwrap_or(options.filter.default_radius()));
+    if let Some(physical) = options.physical {
+        camera.set_physical(physical);
+    }
+    if options.sky.is_some() && !options.physical.is_some_and(|physical| physical.metered) {
+        // 物理天空以cd/m²为单位，未指定测光参数时按"阳光16法则"(f/16, 1/100s, ISO100)曝光
+        let sunny16 = PhysicalCamera { f_stop: 16.0, shutter_time: 1.0 / 100.0, iso: 100.0, metered: true, ..PhysicalCamera::default() };
+        camera.set_exposure(sunny16.exposure());
+    }
+    set_lens(&mut camera, options);
+    camera.set_cat_eye(options.cat_eye);
+    // 显式给出的快门区间优先于物理相机的快门时间
+    if let Some((open, close)) = options.shutter_interval {
+        camera.set_shutter(open, close);
+    }
+
+    let ies = options.ies.as_ref().and_then(|path| match IesProfile::load(path) {
+        Ok(profile) => Some(Rc::new(profile)),
+        Err(err) => {
+            println!("Failed to load IES profile {}: {}", path, err);
+            None
+        }
+    });
+    let mut scene = options.scene.build(camera.get_shutter(), ies);
+    if options.integrator == IntegratorType::Naive && scene.get_lights().iter().any(|light| light.is_delta()) {
+        println!("Point, spot and directional lights are only reached by light sampling, use --integrator path");
+    }
+    if let Some(path) = &options.environment {
+        match EnvironmentLight::load(path, options.env_rotation, options.env_intensity) {
+            Ok(environment) => scene.set_environment(Box::new(environment)),
+            Err(err) => println!("Failed to load environment {}: {}", path, err)
+        }
+    }
+    if let Some((elevation, azimuth)) = options.sky {
+        let sky = PhysicalSky::new(elevation, azimuth, options.turbidity);
+        scene.set_environment(Box::new(sky.environment(512, 256)));
+        scene.add_light(Box::new(sky.sun()));
+    }
+    if options.integrator == IntegratorType::ProgressivePhotonMapping && scene.get_lights().is_empty() {
+        println!("The scene has no lights to emit photons from, rendering with path tracing instead");
+    }
+
+    if let Some((x, y)) = options.autofocus {
+        if !camera.autofocus(scene.get_world(), x, y) {
+            println!("Autofocus probe at {},{} hit nothing, keeping focus distance", x, y);
+        }
+    }
+    if let Some(layout) = options.stereo {
+        let convergence = options.convergence.unwrap_or(camera.get_focus_dist());
+        camera.set_stereo(Some(StereoRig::new(options.interocular, convergence, layout)));
+    }
+    let mut aovs = options.aovs.clone();
+    if options.denoise.is_some() {
+        // 降噪需要反照率与法向量引导
+        for aov in [Aov::Albedo, Aov::Normal] {
+            if !aovs.contains(&aov) {
+                aovs.push(aov);
+            }
+        }
+    }
+    let mut film = camera.render_film(&scene, &aovs);
+    if let Some(denoiser) = &options.denoise {
+        if let (Some(albedo), Some(normal)) = (film.get_aov_image(Aov::Albedo), film.get_aov_image(Aov::Normal)) {
+            let denoised = denoiser.denoise(&film.get_image(), &albedo, &normal);
+            film.set_image(&denoised);
+        }
+    }
+    film.save_ppm("image.ppm").expect("Failed to Write Color.");
+    if let Some(prefix) = &options.aov_prefix {
+        if let Err(err) = film.save_aov_images(prefix) {
+            println!("Failed to write AOV images {}: {}", prefix, err);
+        }
+    }
+    if let Some(path) = &options.exr {
+        if let Err(err) = film.save_exr(path) {
+            println!("Failed to write {}: {}", path, err);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn scene_names() {
+        assert_eq!(SceneType::from_name("motion"), Some(SceneType::Motion));
+        assert_eq!(SceneType::from_name("lights"), Some(SceneType::Lights));
+        assert_eq!(SceneType::from_name("materials"), Some(SceneType::Materials));
+        assert_eq!(SceneType::from_name("cornell"), None);
+    }
+
+    #[test]
+    fn light_scene_uses_analytic_lights() {
+        let scene = SceneType::Lights.build((0.0, 1.0), None);
+        assert_eq!(scene.get_lights().len(), 3);
+        assert!(scene.get_lights().iter().all(|light| light.is_delta()));
+        assert_eq!(scene.get_background(), Some(Color::default()));
+        // 大球顶部被点光源照亮
+        let lit = scene.get_lights().iter()
+            .filter_map(|light| light.sample_li(Point::new(0.0, 2.0, 0.0), (0.5, 0.5)))
+            .count();
+        assert!(lit >= 2);
+    }
+
+    #[test]
+    fn ies_profile_shapes_light_scene() {
+        // 只向下半球发光的配光：点光源照不到比它高的点
+        let text = "IESNA:LM-63-2002\nTILT=NONE\n1 1000 1 3 1 1 2 0 0 0\n1 1 100\n0 90 180\n0\n500 250 0\n";
+        let scene = SceneType::Lights.build((0.0, 1.0), Some(Rc::new(IesProfile::parse(text).unwrap())));
+        let point = &scene.get_lights()[0];
+        let below = point.sample_li(Point::new(3.0, 0.0, -1.5), (0.5, 0.5)).unwrap();
+        // 归一化后正下方强度与各向同性时相同
+        assert!((below.li.x() - 16.0 / 9.0).abs() < 1e-9);
+        assert!(point.sample_li(Point::new(3.0, 6.0, -1.5), (0.5, 0.5)).is_none());
+    }
 }
@@ -0,0 +1,333 @@
+use std::fs;
+use std::io;
+use crate::vec3::Color;
+
+/*
+浮点图像，像素按行优先存储，第0行为图像顶部
+ */
+pub struct Image {
+    width: usize,
+    height: usize,
+    pixels: Vec<Color>
+}
+
+impl Image {
+    pub fn new(width: usize, height: usize) -> Image {
+        Image {
+            width,
+            height,
+            pixels: vec![Color::default(); width * height]
+        }
+    }
+
+    pub fn get_width(&self) -> usize {
+        self.width
+    }
+
+    pub fn get_height(&self) -> usize {
+        self.height
+    }
+
+    pub fn get_pixel(&self, x: usize, y: usize) -> Color {
+        self.pixels[y * self.width + x]
+    }
+
+    pub fn set_pixel(&mut self, x: usize, y: usize, color: Color) {
+        self.pixels[y * self.width + x] = color;
+    }
+}
+
+fn invalid_data(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg.to_string())
+}
+
+/*
+读取PPM/PGM图像(P2/P3文本格式与P5/P6二进制格式)，像素值归一化到[0,1]，不做gamma解码
+ */
+pub fn load_ppm(path: &str) -> io::Result<Image> {
+    let data = fs::read(path)?;
+
+    // 解析文件头：魔数、宽、高、最大值，'#'开头为注释
+    let mut pos = 0;
+    let mut header = Vec::new();
+    while header.len() < 4 {
+        while pos < data.len() && data[pos].is_ascii_whitespace() {
+            pos += 1;
+        }
+        if pos < data.len() && data[pos] == b'#' {
+            while pos < data.len() && data[pos] != b'\n' {
+                pos += 1;
+            }
+            continue;
+        }
+        let start = pos;
+        while pos < data.len() && !data[pos].is_ascii_whitespace() {
+            pos += 1;
+        }
+        if start == pos {
+            return Err(invalid_data("Truncated PPM header"));
+        }
+        header.push(String::from_utf8_lossy(&data[start..pos]).to_string());
+    }
+
+    let magic = header[0].as_str();
+    let width: usize = header[1].parse().map_err(|_| invalid_data("Invalid PPM width"))?;
+    let height: usize = header[2].parse().map_err(|_| invalid_data("Invalid PPM height"))?;
+    let max_value: f64 = header[3].parse().map_err(|_| invalid_data("Invalid PPM max value"))?;
+    let channels = match magic {
+        "P2" | "P5" => 1,
+        "P3" | "P6" => 3,
+        _ => return Err(invalid_data("Unsupported PPM format"))
+    };
+
+    let count = width * height * channels;
+    let values: Vec<f64> = if magic == "P2" || magic == "P3" {
+        String::from_utf8_lossy(&data[pos..])
+            .split_ascii_whitespace()
+            .take(count)
+            .map(|v| v.parse::<f64>().map_err(|_| invalid_data("Invalid PPM pixel")))
+            .collect::<io::Result<Vec<f64>>>()?
+    } else {
+        // 二进制格式头部之后只有一个空白字符
+        let start = pos + 1;
+        let bytes_per_value = if max_value > 255.0 { 2 } else { 1 };
+        if data.len() < start + count * bytes_per_value {
+            return Err(invalid_data("Truncated PPM data"));
+        }
+        (0..count).map(|n| {
+            let offset = start + n * bytes_per_value;
+            if bytes_per_value == 2 {
+                f64::from(u16::from_be_bytes([data[offset], data[offset + 1]]))
+            } else {
+                f64::from(data[offset])
+            }
+        }).collect()
+    };
+    if values.len() < count {
+        return Err(invalid_data("Truncated PPM data"));
+    }
+
+    let mut image = Image::new(width, height);
+    for (n, pixel) in image.pixels.iter_mut().enumerate() {
+        *pixel = if channels == 1 {
+            let v = values[n] / max_value;
+            Color::new(v, v, v)
+        } else {
+            Color::new(values[n * 3] / max_value, values[n * 3 + 1] / max_value, values[n * 3 + 2] / max_value)
+        };
+    }
+    Ok(image)
+}
+
+/*
+按扩展名读取高动态范围图像(.pfm / .hdr)
+ */
+pub fn load_hdr_image(path: &str) -> io::Result<Image> {
+    let lower = path.to_lowercase();
+    if lower.ends_with(".pfm") {
+        load_pfm(path)
+    } else if lower.ends_with(".hdr") {
+        load_rgbe(path)
+    } else {
+        Err(invalid_data("Unsupported HDR image format"))
+    }
+}
+
+/*
+读取PFM图像，扫描线从下到上存储，比例因子为负表示小端序
+ */
+pub fn load_pfm(path: &str) -> io::Result<Image> {
+    let data = fs::read(path)?;
+    let mut pos = 0;
+    let mut header = Vec::new();
+    while header.len() < 4 {
+        while pos < data.len() && data[pos].is_ascii_whitespace() {
+            pos += 1;
+        }
+        let start = pos;
+        while pos < data.len() && !data[pos].is_ascii_whitespace() {
+            pos += 1;
+        }
+        if start == pos {
+            return Err(invalid_data("Truncated PFM header"));
+        }
+        header.push(String::from_utf8_lossy(&data[start..pos]).to_string());
+    }
+    // 头部之后只有一个空白字符
+    pos += 1;
+
+    let channels = match header[0].as_str() {
+        "PF" => 3,
+        "Pf" => 1,
+        _ => return Err(invalid_data("Unsupported PFM format"))
+    };
+    let width: usize = header[1].parse().map_err(|_| invalid_data("Invalid PFM width"))?;
+    let height: usize = header[2].parse().map_err(|_| invalid_data("Invalid PFM height"))?;
+    let scale: f64 = header[3].parse().map_err(|_| invalid_data("Invalid PFM scale"))?;
+    let little_endian = scale < 0.0;
+
+    let count = width * height * channels;
+    if data.len() < pos + count * 4 {
+        return Err(invalid_data("Truncated PFM data"));
+    }
+    let value = |n: usize| {
+        let offset = pos + n * 4;
+        let bytes = [data[offset], data[offset + 1], data[offset + 2], data[offset + 3]];
+        f64::from(if little_endian { f32::from_le_bytes(bytes) } else { f32::from_be_bytes(bytes) })
+    };
+
+    let mut image = Image::new(width, height);
+    for row in 0..height {
+        let y = height - 1 - row;
+        for x in 0..width {
+            let n = (row * width + x) * channels;
+            let color = if channels == 1 {
+                Color::new(value(n), value(n), value(n))
+            } else {
+                Color::new(value(n), value(n + 1), value(n + 2))
+            };
+            image.set_pixel(x, y, color);
+        }
+    }
+    Ok(image)
+}
+
+/*
+写出三通道小端序PFM图像
+ */
+pub fn write_pfm(path: &str, image: &Image) -> io::Result<()> {
+    let (width, height) = (image.get_width(), image.get_height());
+    let mut data = format!("PF\n{} {}\n-1.0\n", width, height).into_bytes();
+    data.reserve(width * height * 12);
+    for row in 0..height {
+        let y = height - 1 - row;
+        for x in 0..width {
+            let color = image.get_pixel(x, y);
+            for c in [color.x(), color.y(), color.z()] {
+                data.extend_from_slice(&(c as f32).to_le_bytes());
+            }
+        }
+    }
+    fs::write(path, data)
+}
+
+/*
+读取Radiance RGBE(.hdr)图像，支持新式游程编码与未压缩扫描线
+ */
+pub fn load_rgbe(path: &str) -> io::Result<Image> {
+    let data = fs::read(path)?;
+    let mut pos = 0;
+    let read_line = |pos: &mut usize| -> io::Result<String> {
+        let start = *pos;
+        while *pos < data.len() && data[*pos] != b'\n' {
+            *pos += 1;
+        }
+        if *pos >= data.len() {
+            return Err(invalid_data("Truncated HDR header"));
+        }
+        *pos += 1;
+        Ok(String::from_utf8_lossy(&data[start..*pos - 1]).to_string())
+    };
+
+    let magic = read_line(&mut pos)?;
+    if !magic.starts_with("#?") {
+        return Err(invalid_data("Not a Radiance HDR file"));
+    }
+    // 头部以空行结束
+    loop {
+        let line = read_line(&mut pos)?;
+        if line.trim().is_empty() {
+            break;
+        }
+        if line.starts_with("FORMAT=") && line.trim() != "FORMAT=32-bit_rle_rgbe" {
+            return Err(invalid_data("Unsupported HDR pixel format"));
+        }
+    }
+    let resolution = read_line(&mut pos)?;
+    let tokens: Vec<&str> = resolution.split_whitespace().collect();
+    if tokens.len() != 4 || tokens[0] != "-Y" || tokens[2] != "+X" {
+        return Err(invalid_data("Unsupported HDR orientation"));
+    }
+    let height: usize = tokens[1].parse().map_err(|_| invalid_data("Invalid HDR height"))?;
+    let width: usize = tokens[3].parse().map_err(|_| invalid_data("Invalid HDR width"))?;
+
+    let mut image = Image::new(width, height);
+    let mut scanline = vec![[0u8; 4]; width];
+    let truncated = || invalid_data("Truncated HDR data");
+    for y in 0..height {
+        let header = data.get(pos..pos + 4).ok_or_else(truncated)?;
+        let is_rle = (8..32768).contains(&width) && header[0] == 2 && header[1] == 2
+            && ((usize::from(header[2]) << 8) | usize::from(header[3])) == width;
+        if is_rle {
+            pos += 4;
+            // 四个通道依次游程编码
+            for channel in 0..4 {
+                let mut x = 0;
+                while x < width {
+                    let count = *data.get(pos).ok_or_else(truncated)? as usize;
+                    pos += 1;
+                    if count > 128 {
+                        let run = count - 128;
+                        let value = *data.get(pos).ok_or_else(truncated)?;
+                        pos += 1;
+                        if x + run > width {
+                            return Err(invalid_data("Corrupt HDR scanline"));
+                        }
+                        for pixel in scanline[x..x + run].iter_mut() {
+                            pixel[channel] = value;
+                        }
+                        x += run;
+                    } else {
+                        if count == 0 || x + count > width {
+                            return Err(invalid_data("Corrupt HDR scanline"));
+                        }
+                        let values = data.get(pos..pos + count).ok_or_else(truncated)?;
+                        for (pixel, value) in scanline[x..x + count].iter_mut().zip(values) {
+                            pixel[channel] = *value;
+                        }
+                        pos += count;
+                        x += count;
+                    }
+                }
+            }
+        } else {
+            for pixel in scanline.iter_mut() {
+                let bytes = data.get(pos..pos + 4).ok_or_else(truncated)?;
+                pixel.copy_from_slice(bytes);
+                pos += 4;
+            }
+        }
+
+        for (x, rgbe) in scanline.iter().enumerate() {
+            let color = if rgbe[3] == 0 {
+                Color::default()
+            } else {
+                let f = 2f64.powi(i32::from(rgbe[3]) - 136);
+                Color::new(f64::from(rgbe[0]) * f, f64::from(rgbe[1]) * f, f64::from(rgbe[2]) * f)
+            };
+            image.set_pixel(x, y, color);
+        }
+    }
+    Ok(image)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pfm_round_trip() {
+        let mut image = Image::new(3, 2);
+        image.set_pixel(0, 0, Color::new(1.5, -2.0, 0.25));
+        image.set_pixel(2, 1, Color::new(1e4, 0.0, 3.0));
+        let path = std::env::temp_dir().join("rayce_round_trip.pfm");
+        write_pfm(path.to_str().unwrap(), &image).unwrap();
+        let loaded = load_pfm(path.to_str().unwrap()).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!((loaded.get_width(), loaded.get_height()), (3, 2));
+        // PFM自下而上存储，读回后行序不变
+        assert_eq!(loaded.get_pixel(0, 0), Color::new(1.5, -2.0, 0.25));
+        assert_eq!(loaded.get_pixel(2, 1), Color::new(1e4, 0.0, 3.0));
+        assert_eq!(loaded.get_pixel(1, 0), Color::default());
+    }
+}
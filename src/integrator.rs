@@ -0,0 +1,592 @@
+use std::rc::Rc;
+use crate::bdpt::BdptIntegrator;
+use crate::camera::Camera;
+use crate::common::INFINITY;
+use crate::depth::{BounceCounts, DepthLimits};
+use crate::film::{Aov, Film, SampleAovs};
+use crate::mlt::MltIntegrator;
+use crate::options::RenderOptions;
+use crate::ray::Ray;
+use crate::sampler::Sampler;
+use crate::scene::Scene;
+use crate::spectrum::SampledWavelengths;
+use crate::sphere::HitRecord;
+use crate::sppm::SppmIntegrator;
+use crate::vec3::{Color, Vec3};
+
+// 深度调试图像中亮度衰减为1/e的距离
+const DEPTH_SCALE: f64 = 10.0;
+
+/*
+光传输算法：计算相机光线带回的颜色，同时记录首个交点的输出变量
+ */
+pub trait Integrator {
+    // camera用于需要把贡献投影到图像上的算法(光线追踪)
+    fn li(&self, r: &Ray, camera: &Camera, scene: &Scene, sampler: &mut dyn Sampler, aovs: &mut SampleAovs) -> Color;
+
+    // 结果是否为辐射亮度，调试输出与环境光遮蔽不乘相机曝光系数
+    fn is_radiometric(&self) -> bool {
+        true
+    }
+
+    /*
+    自行组织整个渲染过程(例如渐进式光子映射在相机光线与光子之间交替迭代)，
+    返回None时由相机逐像素、逐样本调用li
+     */
+    fn render(&self, _camera: &Camera, _scene: &Scene, _aovs: &[Aov]) -> Option<Film> {
+        None
+    }
+}
+
+/*
+调试积分器显示的几何量
+ */
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum DebugMode {
+    // 着色法向量，[-1,1]映射到[0,1]
+    Normal,
+    // 表面参数坐标(u, v, 0)
+    Uv,
+    // 相机到交点的距离，越远越暗
+    Depth
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum IntegratorType {
+    // 光源采样与材质采样按多重重要性采样结合的路径追踪
+    Path,
+    // 只按材质采样的路径追踪(默认)，点光源等delta光源无法被击中
+    Naive,
+    AmbientOcclusion,
+    Direct,
+    // 双向路径追踪
+    Bidirectional,
+    // 随机渐进式光子映射
+    ProgressivePhotonMapping,
+    // 主样本空间Metropolis光传输
+    Metropolis,
+    Debug(DebugMode)
+}
+
+impl IntegratorType {
+    pub fn from_name(name: &str) -> Option<IntegratorType> {
+        match name {
+            "path" => Some(IntegratorType::Path),
+            "naive" => Some(IntegratorType::Naive),
+            "ao" | "ambient-occlusion" => Some(IntegratorType::AmbientOcclusion),
+            "direct" => Some(IntegratorType::Direct),
+            "bdpt" | "bidirectional" => Some(IntegratorType::Bidirectional),
+            "sppm" | "photon" => Some(IntegratorType::ProgressivePhotonMapping),
+            "mlt" | "pssmlt" => Some(IntegratorType::Metropolis),
+            "normals" | "normal" => Some(IntegratorType::Debug(DebugMode::Normal)),
+            "uv" => Some(IntegratorType::Debug(DebugMode::Uv)),
+            "depth" => Some(IntegratorType::Debug(DebugMode::Depth)),
+            _ => None
+        }
+    }
+
+    // 只有基于PathIntegrator的积分器按波长追踪路径
+    pub fn supports_spectral(self) -> bool {
+        matches!(self, IntegratorType::Path | IntegratorType::Naive | IntegratorType::Metropolis)
+    }
+
+    pub fn create(self, options: &RenderOptions) -> Box<dyn Integrator> {
+        let depth_limits = options.depth_limits;
+        let path = |light_sampling: bool| {
+            let mut path = PathIntegrator::new(depth_limits, light_sampling);
+            path.set_spectral(options.spectral);
+            path
+        };
+        match self {
+            IntegratorType::Path => Box::new(path(true)),
+            IntegratorType::Naive => Box::new(path(false)),
+            IntegratorType::AmbientOcclusion => Box::new(AmbientOcclusionIntegrator::new(options.ao_distance)),
+            IntegratorType::Direct => Box::new(DirectLightingIntegrator::new(depth_limits.max_depth)),
+            IntegratorType::Bidirectional => Box::new(BdptIntegrator::new(depth_limits.max_depth)),
+            IntegratorType::ProgressivePhotonMapping => Box::new(SppmIntegrator::new(
+                depth_limits.max_depth, options.photons, options.photon_radius, path(true))),
+            IntegratorType::Metropolis => Box::new(MltIntegrator::new(path(true), options.mlt)),
+            IntegratorType::Debug(mode) => Box::new(DebugIntegrator::new(mode))
+        }
+    }
+}
+
+/*
+迭代式路径追踪：每个顶点累加自发光与光源直接采样，再按材质采样继续；
+路径通量在达到轮盘赌深度后按概率终止，各类散射分别受深度上限约束。
+同时记录首个交点的输出变量：
+自发光 + 直接光照(光源采样与下一交点的自发光) + 间接光照。
+关闭光源采样时只靠材质采样击中自发光物体与背景
+ */
+pub struct PathIntegrator {
+    depth_limits: DepthLimits,
+    light_sampling: bool,
+    // 光谱渲染：每条路径采样一组波长，RGB反射率与辐射亮度上采样为光谱
+    spectral: bool
+}
+
+impl PathIntegrator {
+    pub fn new(depth_limits: DepthLimits, light_sampling: bool) -> PathIntegrator {
+        PathIntegrator {
+            depth_limits,
+            light_sampling,
+            spectral: false
+        }
+    }
+
+    pub fn set_spectral(&mut self, spectral: bool) {
+        self.spectral = spectral;
+    }
+}
+
+// 光谱渲染时把RGB值换算为路径各波长处的光谱值
+fn to_spectrum(wavelengths: &Option<SampledWavelengths>, rgb: Color) -> Color {
+    match wavelengths {
+        Some(wavelengths) => wavelengths.upsample(rgb),
+        None => rgb
+    }
+}
+
+impl Integrator for PathIntegrator {
+    fn li(&self, r: &Ray, _camera: &Camera, scene: &Scene, sampler: &mut dyn Sampler, aovs: &mut SampleAovs) -> Color {
+        let mut ray = Ray::new(r.original(), r.direction(), r.get_time());
+        let mut throughput = Color::new(1.0, 1.0, 1.0);
+        // 产生当前光线的材质采样概率密度，用于与光源采样做多重重要性采样(MIS)，相机光线与镜面散射光线为0
+        let mut bsdf_pdf = 0.0;
+        let mut bounces = BounceCounts::default();
+        // 光源采样结果是RGB乘积，整体上采样；色散材质可能在途中终止次波长
+        let mut wavelengths = if self.spectral { Some(SampledWavelengths::sample_visible(sampler.get_1d())) } else { None };
+        ray.set_wavelengths(wavelengths);
+
+        let mut depth = 0;
+        while depth < self.depth_limits.max_depth {
+            // 防止阴影痤疮(shadow ance)，在接近t=0时会再次击中自己
+            let mut rec = HitRecord::new_default();
+            if !scene.get_world().hit(&ray, 0.001, INFINITY, &mut rec) {
+                // 不采样光源时背景只能由材质采样得到，无需加权
+                let pdf = if self.light_sampling { bsdf_pdf } else { 0.0 };
+                add_contribution(aovs, depth, throughput * to_spectrum(&wavelengths, background(&ray, scene, pdf)), Color::default());
+                break;
+            }
+
+            let material = rec.get_material();
+            if depth == 0 {
+                record_first_hit(aovs, &ray, &rec);
+            }
+            let emitted = throughput * to_spectrum(&wavelengths, material.emitted(&rec));
+            let direct = if self.light_sampling {
+                throughput * to_spectrum(&wavelengths, sample_lights(&ray, &rec, scene, sampler))
+            } else {
+                Color::default()
+            };
+            add_contribution(aovs, depth, emitted, direct);
+
+            let mut scattered = Ray::default();
+            let mut attenuation = Color::default();
+            if !material.scatter(&ray, &rec, &mut attenuation, &mut scattered, sampler) {
+                break;
+            }
+            let ray_type = material.ray_type(&ray, &rec, &scattered);
+            if bounces.increment(ray_type) > self.depth_limits.get_limit(ray_type) {
+                break;
+            }
+            bsdf_pdf = if scattered.is_specular() { 0.0 } else { material.pdf(&ray, &rec, scattered.direction()) };
+            throughput *= if scattered.has_spectral_attenuation() { attenuation } else { to_spectrum(&wavelengths, attenuation) };
+            match scattered.get_wavelengths() {
+                Some(changed) => wavelengths = Some(changed),
+                None => scattered.set_wavelengths(wavelengths)
+            }
+
+            // 俄罗斯轮盘赌：通量越小越容易终止，存活的路径按存活概率放大以保持无偏
+            if self.depth_limits.roulette_depth >= 0 && depth + 1 >= self.depth_limits.roulette_depth {
+                let survival = throughput.x().max(throughput.y()).max(throughput.z()).min(0.95);
+                if survival <= 0.0 || sampler.get_1d() >= survival {
+                    break;
+                }
+                throughput /= survival;
+            }
+
+            ray = scattered;
+            depth += 1;
+        }
+
+        if let Some(wavelengths) = wavelengths {
+            aovs.emission = wavelengths.to_rgb(aovs.emission);
+            aovs.direct = wavelengths.to_rgb(aovs.direct);
+            aovs.indirect = wavelengths.to_rgb(aovs.indirect);
+        }
+        aovs.emission + aovs.direct + aovs.indirect
+    }
+}
+
+/*
+只计算直接光照：非镜面交点处做光源采样，再按材质采样一次只取击中的自发光与背景；
+镜面反射与折射继续追踪，直到遇到非镜面表面或达到max_depth
+ */
+pub struct DirectLightingIntegrator {
+    max_depth: i32
+}
+
+impl DirectLightingIntegrator {
+    pub fn new(max_depth: i32) -> DirectLightingIntegrator {
+        DirectLightingIntegrator {
+            max_depth
+        }
+    }
+}
+
+impl Integrator for DirectLightingIntegrator {
+    fn li(&self, r: &Ray, _camera: &Camera, scene: &Scene, sampler: &mut dyn Sampler, aovs: &mut SampleAovs) -> Color {
+        let mut ray = Ray::new(r.original(), r.direction(), r.get_time());
+        let mut throughput = Color::new(1.0, 1.0, 1.0);
+        let mut bsdf_pdf = 0.0;
+
+        for depth in 0..self.max_depth {
+            let mut rec = HitRecord::new_default();
+            if !scene.get_world().hit(&ray, 0.001, INFINITY, &mut rec) {
+                add_contribution(aovs, depth, throughput * background(&ray, scene, bsdf_pdf), Color::default());
+                break;
+            }
+
+            let material = rec.get_material();
+            if depth == 0 {
+                record_first_hit(aovs, &ray, &rec);
+            }
+            let emitted = throughput * material.emitted(&rec);
+            // 上一次为非镜面散射时只统计本交点的自发光
+            if bsdf_pdf > 0.0 {
+                add_contribution(aovs, depth, emitted, Color::default());
+                break;
+            }
+            add_contribution(aovs, depth, emitted, throughput * sample_lights(&ray, &rec, scene, sampler));
+
+            let mut scattered = Ray::default();
+            let mut attenuation = Color::default();
+            if !material.scatter(&ray, &rec, &mut attenuation, &mut scattered, sampler) {
+                break;
+            }
+            bsdf_pdf = if scattered.is_specular() { 0.0 } else { material.pdf(&ray, &rec, scattered.direction()) };
+            throughput *= attenuation;
+            ray = scattered;
+        }
+
+        aovs.emission + aovs.direct + aovs.indirect
+    }
+}
+
+/*
+环境光遮蔽：从首个交点按余弦分布向法向半球发出一条光线，
+max_distance内未被遮挡时为1，否则为0；未击中物体的像素为0
+ */
+pub struct AmbientOcclusionIntegrator {
+    max_distance: f64
+}
+
+impl AmbientOcclusionIntegrator {
+    pub fn new(max_distance: f64) -> AmbientOcclusionIntegrator {
+        AmbientOcclusionIntegrator {
+            max_distance
+        }
+    }
+}
+
+impl Integrator for AmbientOcclusionIntegrator {
+    fn li(&self, r: &Ray, _camera: &Camera, scene: &Scene, sampler: &mut dyn Sampler, aovs: &mut SampleAovs) -> Color {
+        let mut rec = HitRecord::new_default();
+        if !scene.get_world().hit(r, 0.001, INFINITY, &mut rec) {
+            return Color::default();
+        }
+        record_first_hit(aovs, r, &rec);
+
+        // 法向量加单位球面上的随机点即为余弦分布
+        let mut direction = rec.get_normal() + Vec3::sample_unit_sphere(sampler.get_2d());
+        if direction.length_squared() < 1e-12 {
+            direction = rec.get_normal();
+        }
+        let occlusion_ray = Ray::new(rec.get_p(), direction.unit_vector(), r.get_time());
+        let mut occlusion_rec = HitRecord::new_default();
+        if scene.get_world().hit(&occlusion_ray, 0.001, self.max_distance, &mut occlusion_rec) {
+            Color::default()
+        } else {
+            Color::new(1.0, 1.0, 1.0)
+        }
+    }
+
+    fn is_radiometric(&self) -> bool {
+        false
+    }
+}
+
+/*
+调试积分器：直接显示首个交点的几何量，未击中物体的像素为黑色
+ */
+pub struct DebugIntegrator {
+    mode: DebugMode
+}
+
+impl DebugIntegrator {
+    pub fn new(mode: DebugMode) -> DebugIntegrator {
+        DebugIntegrator {
+            mode
+        }
+    }
+}
+
+impl Integrator for DebugIntegrator {
+    fn li(&self, r: &Ray, _camera: &Camera, scene: &Scene, _sampler: &mut dyn Sampler, aovs: &mut SampleAovs) -> Color {
+        let mut rec = HitRecord::new_default();
+        if !scene.get_world().hit(r, 0.001, INFINITY, &mut rec) {
+            return Color::default();
+        }
+        record_first_hit(aovs, r, &rec);
+
+        match self.mode {
+            DebugMode::Normal => (rec.get_normal() + Vec3::new(1.0, 1.0, 1.0)) * 0.5,
+            DebugMode::Uv => {
+                let (u, v) = rec.get_uv();
+                Color::new(u, v, 0.0)
+            }
+            DebugMode::Depth => {
+                let brightness = (-aovs.depth / DEPTH_SCALE).exp();
+                Color::new(brightness, brightness, brightness)
+            }
+        }
+    }
+
+    fn is_radiometric(&self) -> bool {
+        false
+    }
+}
+
+// 记录相机光线首个交点的几何输出变量
+pub fn record_first_hit(aovs: &mut SampleAovs, r: &Ray, rec: &HitRecord) {
+    let material = rec.get_material();
+    aovs.hit = true;
+    aovs.albedo = material.albedo(rec);
+    aovs.normal = rec.get_normal();
+    aovs.position = rec.get_p();
+    aovs.depth = (rec.get_p() - r.original()).length();
+    aovs.object_id = rec.get_object_id();
+    aovs.material_key = Some(Rc::as_ptr(material) as *const () as usize);
+}
+
+/*
+按路径深度把贡献计入输出变量：emitted为当前交点(或背景)的自发光，direct为当前交点的光源采样结果。
+相机直接看到的自发光计入emission，首个交点的直接光照与一次反弹后击中的自发光计入direct，其余计入indirect
+ */
+fn add_contribution(aovs: &mut SampleAovs, depth: i32, emitted: Color, direct: Color) {
+    match depth {
+        0 => {
+            aovs.emission += emitted;
+            aovs.direct += direct;
+        }
+        1 => {
+            aovs.direct += emitted;
+            aovs.indirect += direct;
+        }
+        _ => aovs.indirect += emitted + direct
+    }
+}
+
+/*
+没有环境光时的纯色或白-蓝渐变背景，不能被光源采样；有环境光时为0
+ */
+pub fn default_background(r: &Ray, scene: &Scene) -> Color {
+    if scene.has_environment() {
+        return Color::default();
+    }
+    if let Some(color) = scene.get_background() {
+        return color;
+    }
+    let unit_direction = r.direction().unit_vector();
+    let a = (unit_direction.y() + 1.0) * 0.5;
+    Color::new(1.0, 1.0, 1.0) * (1.0 - a) + Color::new(0.5, 0.7, 1.0) * a
+}
+
+/*
+逃逸光线的颜色：默认背景加上环境光、太阳等无穷远光源的le。
+bsdf_pdf为产生该光线的材质采样概率密度，大于0时与光源采样按MIS加权
+ */
+pub fn background(r: &Ray, scene: &Scene, bsdf_pdf: f64) -> Color {
+    let mut result = default_background(r, scene);
+    for light in scene.get_lights().iter() {
+        let le = light.le(r.direction());
+        if le == Color::default() {
+            continue;
+        }
+        // 非镜面散射的光线同样可能被光源采样得到，按MIS加权
+        let weight = if bsdf_pdf > 0.0 {
+            power_heuristic(bsdf_pdf, light.pdf_li(r.original(), r.direction().unit_vector()))
+        } else {
+            1.0
+        };
+        result += le * weight;
+    }
+    result
+}
+
+/*
+对场景中的光源做直接光照采样，并用阴影光线检测遮挡。
+非delta光源也可能被材质采样击中，使用幂启发式(power heuristic)加权
+ */
+pub fn sample_lights(r: &Ray, rec: &HitRecord, scene: &Scene, sampler: &mut dyn Sampler) -> Color {
+    let mut result = Color::default();
+    for light in scene.get_lights().iter() {
+        let sample = match light.sample_li(rec.get_p(), sampler.get_2d()) {
+            Some(sample) if sample.pdf > 0.0 => sample,
+            _ => continue
+        };
+        let f = rec.get_material().eval(r, rec, sample.wi);
+        if f == Color::default() {
+            continue;
+        }
+
+        let shadow_ray = Ray::new(rec.get_p(), sample.wi, r.get_time());
+        let mut shadow_rec = HitRecord::new_default();
+        if scene.get_world().hit(&shadow_ray, 0.001, sample.distance - 0.001, &mut shadow_rec) {
+            continue;
+        }
+        let weight = if light.is_delta() {
+            1.0
+        } else {
+            power_heuristic(sample.pdf, rec.get_material().pdf(r, rec, sample.wi))
+        };
+        result += f * sample.li * (weight / sample.pdf);
+    }
+    result
+}
+
+pub fn power_heuristic(f_pdf: f64, g_pdf: f64) -> f64 {
+    let f = f_pdf * f_pdf;
+    let g = g_pdf * g_pdf;
+    if f + g == 0.0 { 0.0 } else { f / (f + g) }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::rc::Rc;
+    use super::*;
+    use crate::common::PI;
+    use crate::environment::EnvironmentLight;
+    use crate::imageio::Image;
+    use crate::light::{PointLight, SunLight};
+    use crate::material::{CoatedMaterial, Lambertian, Material, Metal, MixMaterial};
+    use crate::sampler::IndependentSampler;
+    use crate::sphere::{HittableList, Sphere};
+    use crate::vec3::Point;
+
+    // 黑色背景下点光源正下方的白色地面
+    fn lit_floor() -> Scene {
+        let mut world = HittableList::new();
+        world.add(Box::new(Sphere::new(Point::new(0.0, -1000.0, 0.0), 1000.0,
+                                       Rc::new(Lambertian::new(Color::new(1.0, 1.0, 1.0))))));
+        let mut scene = Scene::new(Box::new(world));
+        scene.set_background(Some(Color::default()));
+        scene.add_light(Box::new(PointLight::new(Point::new(0.0, 2.0, 0.0), Color::new(4.0, 4.0, 4.0))));
+        scene
+    }
+
+    fn mean_li(integrator: &dyn Integrator, scene: &Scene) -> f64 {
+        let camera = Camera::new(9, 20.0, 1.0, 1, Point::new(0.0, 1.0, 0.0), Point::default(),
+                                 Vec3::new(0.0, 0.0, -1.0), 1.0, 0.0);
+        let mut sampler = IndependentSampler::new();
+        let ray = Ray::new(Point::new(0.0, 1.0, 0.0), Vec3::new(0.0, -1.0, 0.0), 0.0);
+        (0..256).map(|_| integrator.li(&ray, &camera, scene, &mut sampler, &mut SampleAovs::new()).x()).sum::<f64>() / 256.0
+    }
+
+    #[test]
+    fn only_light_sampling_reaches_point_lights() {
+        let scene = lit_floor();
+        let limits = DepthLimits::default();
+        assert_eq!(mean_li(&PathIntegrator::new(limits, false), &scene), 0.0);
+        // 直接光照 albedo/π · I/d² = 1/π，多次反弹在黑色背景下无其他贡献
+        let direct = mean_li(&PathIntegrator::new(limits, true), &scene);
+        assert!((direct - 1.0 / PI).abs() < 1e-9, "{}", direct);
+    }
+
+    /*
+    只由太阳照亮的白色漫反射地面，黑色背景、没有环境光。
+    太阳在正上方，圆盘内积分得到地面的辐照度 E·(1+cosθmax)/2，出射亮度再除以π；
+    只按材质采样时逃逸光线击中太阳圆盘也必须得到太阳的亮度
+     */
+    #[test]
+    fn sun_is_visible_without_environment() {
+        let mut world = HittableList::new();
+        world.add(Box::new(Sphere::new(Point::new(0.0, -1000.0, 0.0), 1000.0,
+                                       Rc::new(Lambertian::new(Color::new(1.0, 1.0, 1.0))))));
+        let mut scene = Scene::new(Box::new(world));
+        scene.set_background(Some(Color::default()));
+        scene.add_light(Box::new(SunLight::new(Vec3::new(0.0, 1.0, 0.0), Color::new(1.0, 1.0, 1.0), 20.0)));
+        let expected = (1.0 + 10.0_f64.to_radians().cos()) / 2.0 / PI;
+
+        let camera = Camera::new(9, 20.0, 1.0, 1, Point::new(0.0, 1.0, 0.0), Point::default(),
+                                 Vec3::new(0.0, 0.0, -1.0), 1.0, 0.0);
+        let ray = Ray::new(Point::new(0.0, 1.0, 0.0), Vec3::new(0.0, -1.0, 0.0), 0.0);
+        for light_sampling in [true, false] {
+            let integrator = PathIntegrator::new(DepthLimits::default(), light_sampling);
+            let mut sampler = IndependentSampler::new();
+            let mean = (0..20000).map(|_| integrator.li(&ray, &camera, &scene, &mut sampler, &mut SampleAovs::new()).x())
+                .sum::<f64>() / 20000.0;
+            assert!((mean - expected).abs() < 0.04, "light sampling {}: {} vs {}", light_sampling, mean, expected);
+        }
+    }
+
+    /*
+    各方向亮度为1的环境光下的球：黑色漫反射与镜面金属各占一半的混合材质应反射一半环境光，
+    黑色基底上的清漆正对视线时只有菲涅尔反射率R0=0.04。
+    镜面分支的逃逸光线不能按MIS加权，否则结果偏暗
+     */
+    #[test]
+    fn specular_lobes_of_layered_materials_see_full_environment() {
+        let black: Rc<dyn Material> = Rc::new(Lambertian::new(Color::default()));
+        let mirror: Rc<dyn Material> = Rc::new(Metal::new(Color::new(1.0, 1.0, 1.0), 0.0));
+        let cases: [(Rc<dyn Material>, f64); 2] = [
+            (Rc::new(MixMaterial::new_constant(black.clone(), mirror, 0.5)), 0.5),
+            (Rc::new(CoatedMaterial::new(black, 1.5)), 0.04)
+        ];
+        let camera = Camera::new(9, 20.0, 1.0, 1, Point::new(0.0, 0.0, 5.0), Point::default(),
+                                 Vec3::new(0.0, 1.0, 0.0), 5.0, 0.0);
+        let ray = Ray::new(Point::new(0.0, 0.0, 5.0), Vec3::new(0.0, 0.0, -1.0), 0.0);
+        for (material, expected) in cases {
+            let mut world = HittableList::new();
+            world.add(Box::new(Sphere::new(Point::default(), 1.0, material)));
+            let mut scene = Scene::new(Box::new(world));
+            let mut sky = Image::new(8, 4);
+            for y in 0..4 {
+                for x in 0..8 {
+                    sky.set_pixel(x, y, Color::new(1.0, 1.0, 1.0));
+                }
+            }
+            scene.set_environment(Box::new(EnvironmentLight::new(sky, 0.0, 1.0)));
+            let integrators: [Box<dyn Integrator>; 2] = [
+                Box::new(PathIntegrator::new(DepthLimits::default(), true)),
+                Box::new(DirectLightingIntegrator::new(5))
+            ];
+            for integrator in integrators {
+                let mut sampler = IndependentSampler::new();
+                let mean = (0..4000).map(|_| integrator.li(&ray, &camera, &scene, &mut sampler, &mut SampleAovs::new()).x())
+                    .sum::<f64>() / 4000.0;
+                assert!((mean - expected).abs() < 0.015, "{} vs {}", mean, expected);
+            }
+        }
+    }
+
+    #[test]
+    fn roulette_is_unbiased() {
+        // 天空背景下的漫反射球：开启轮盘赌前后平均值一致
+        let mut world = HittableList::new();
+        world.add(Box::new(Sphere::new(Point::default(), 1.0, Rc::new(Lambertian::new(Color::new(0.8, 0.8, 0.8))))));
+        let scene = Scene::new(Box::new(world));
+        let camera = Camera::new(9, 20.0, 1.0, 1, Point::new(0.0, 0.0, 5.0), Point::default(),
+                                 Vec3::new(0.0, 1.0, 0.0), 5.0, 0.0);
+        let ray = Ray::new(Point::new(0.0, 0.0, 5.0), Vec3::new(0.0, 0.0, -1.0), 0.0);
+        let mean = |limits: DepthLimits| {
+            let integrator = PathIntegrator::new(limits, false);
+            let mut sampler = IndependentSampler::new();
+            (0..20000).map(|_| integrator.li(&ray, &camera, &scene, &mut sampler, &mut SampleAovs::new()).y()).sum::<f64>() / 20000.0
+        };
+        let plain = mean(DepthLimits::default());
+        let roulette = mean(DepthLimits { roulette_depth: 0, ..DepthLimits::default() });
+        assert!((plain - roulette).abs() < 0.03, "{} vs {}", plain, roulette);
+    }
+}
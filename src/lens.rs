@@ -0,0 +1,330 @@
+use std::fs;
+use std::io;
+use crate::aperture::Aperture;
+use crate::projection::{CameraBasis, CameraModel};
+use crate::vec3::{dot, Point, Vec3};
+
+// 镜头数据以毫米为单位，场景单位视为米
+const MM_TO_SCENE: f64 = 0.001;
+
+/*
+镜头中的一个折射面(或光阑)，参数与常见镜头设计数据一致
+ */
+pub struct LensElement {
+    // 曲率半径(mm)，正值表示球心位于像方一侧，0表示平面光阑
+    curvature_radius: f64,
+    // 沿光轴到下一个面的距离(mm)
+    thickness: f64,
+    // 该面像方一侧介质的折射率，0表示空气
+    ior: f64,
+    // 通光孔径直径(mm)
+    aperture_diameter: f64
+}
+
+impl LensElement {
+    pub fn new(curvature_radius: f64, thickness: f64, ior: f64, aperture_diameter: f64) -> LensElement {
+        LensElement {
+            curvature_radius,
+            thickness,
+            ior,
+            aperture_diameter
+        }
+    }
+
+    fn is_stop(&self) -> bool {
+        self.curvature_radius == 0.0
+    }
+
+    fn medium_ior(&self) -> f64 {
+        if self.ior == 0.0 { 1.0 } else { self.ior }
+    }
+}
+
+/*
+真实多片镜头相机：从传感器出发的光线依次经过各个球面折射后射入场景。
+坐标系中传感器位于z=0，光轴+z指向场景，镜片按从物方到像方的顺序给出
+ */
+pub struct RealisticLens {
+    elements: Vec<LensElement>,
+    // 传感器到最后一片镜片顶点的距离(mm)，由对焦决定
+    film_distance: f64,
+    sensor_width: f64,
+    sensor_height: f64,
+    // 光阑形状，None为圆形
+    stop_shape: Option<Box<dyn Aperture>>
+}
+
+impl RealisticLens {
+    /*
+    sensor_width为传感器宽度(mm)，高度由图像宽高比得到；focus_dist为对焦距离(场景单位)
+     */
+    pub fn new(elements: Vec<LensElement>, sensor_width: f64, aspect_ratio: f64, focus_dist: f64) -> RealisticLens {
+        let mut lens = RealisticLens {
+            elements,
+            film_distance: 0.0,
+            sensor_width,
+            sensor_height: sensor_width / aspect_ratio,
+            stop_shape: None
+        };
+        lens.focus(focus_dist);
+        lens
+    }
+
+    /*
+    Double Gauss 50mm f/2 (US patent 2,673,491，按50mm缩放)
+     */
+    pub fn double_gauss_50mm(sensor_width: f64, aspect_ratio: f64, focus_dist: f64) -> RealisticLens {
+        let elements = vec![
+            LensElement::new(29.475, 3.76, 1.67, 25.2),
+            LensElement::new(84.83, 0.12, 1.0, 25.2),
+            LensElement::new(19.275, 4.025, 1.67, 23.0),
+            LensElement::new(40.77, 3.275, 1.699, 23.0),
+            LensElement::new(12.75, 5.705, 1.0, 18.0),
+            LensElement::new(0.0, 4.5, 0.0, 17.1),
+            LensElement::new(-14.495, 1.18, 1.603, 17.0),
+            LensElement::new(40.77, 6.065, 1.658, 20.0),
+            LensElement::new(-20.385, 0.19, 1.0, 20.0),
+            LensElement::new(437.065, 3.22, 1.717, 20.0),
+            LensElement::new(-39.73, 0.0, 1.0, 20.0),
+        ];
+        RealisticLens::new(elements, sensor_width, aspect_ratio, focus_dist)
+    }
+
+    /*
+    读取镜头数据文件，每行依次为 曲率半径 厚度 折射率 孔径直径，'#'开头为注释
+     */
+    pub fn load(path: &str, sensor_width: f64, aspect_ratio: f64, focus_dist: f64) -> io::Result<RealisticLens> {
+        let text = fs::read_to_string(path)?;
+        let mut elements = Vec::new();
+        for line in text.lines().map(str::trim).filter(|l| !l.is_empty() && !l.starts_with('#')) {
+            let values = line.split_whitespace()
+                .map(|v| v.parse::<f64>())
+                .collect::<Result<Vec<f64>, _>>()
+                .map_err(|_| io::Error::new(io::ErrorKind::InvalidData, format!("Invalid lens data: {}", line)))?;
+            if values.len() != 4 {
+                return Err(io::Error::new(io::ErrorKind::InvalidData, format!("Invalid lens data: {}", line)));
+            }
+            elements.push(LensElement::new(values[0], values[1], values[2], values[3]));
+        }
+        if elements.is_empty() {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "Lens file contains no elements"));
+        }
+        Ok(RealisticLens::new(elements, sensor_width, aspect_ratio, focus_dist))
+    }
+
+    pub fn set_stop_shape(&mut self, shape: Box<dyn Aperture>) {
+        self.stop_shape = Some(shape);
+    }
+
+    // 修改光阑孔径(mm)，用于调整光圈值
+    pub fn set_stop_diameter(&mut self, diameter: f64) {
+        if let Some(stop) = self.elements.iter_mut().find(|e| e.is_stop()) {
+            stop.aperture_diameter = diameter;
+        }
+    }
+
+    // 第i个面的顶点在光轴上的位置
+    fn vertex_z(&self, i: usize) -> f64 {
+        let n = self.elements.len();
+        self.film_distance + self.elements[i..n - 1].iter().map(|e| e.thickness).sum::<f64>()
+    }
+
+    fn rear_radius(&self) -> f64 {
+        self.elements.last().map_or(0.0, |e| e.aperture_diameter / 2.0)
+    }
+
+    /*
+    从传感器一侧追踪光线穿过整个镜头，被镜筒或光阑遮挡、或发生全反射时返回None
+     */
+    fn trace_from_film(&self, origin: Point, direction: Vec3) -> Option<(Point, Vec3)> {
+        let mut o = origin;
+        let mut d = direction.unit_vector();
+        for i in (0..self.elements.len()).rev() {
+            let element = &self.elements[i];
+            let z = self.vertex_z(i);
+
+            let (p, normal) = if element.is_stop() {
+                if d.z() <= 0.0 {
+                    return None;
+                }
+                let t = (z - o.z()) / d.z();
+                (o + d * t, Vec3::new(0.0, 0.0, -1.0))
+            } else {
+                // 球心位于顶点像方一侧(-z方向)曲率半径处
+                let radius = element.curvature_radius;
+                let center = Point::new(0.0, 0.0, z - radius);
+                let oc = o - center;
+                let half_b = dot(oc, d);
+                let c = oc.length_squared() - radius * radius;
+                let discriminant = half_b * half_b - c;
+                if discriminant < 0.0 {
+                    return None;
+                }
+                let root = discriminant.sqrt();
+                // 选择与顶点在同一侧球冠上的交点
+                let t = [-half_b - root, -half_b + root].into_iter()
+                    .find(|&t| t > 1e-9 && (o.z() + d.z() * t - center.z()) * radius > 0.0)?;
+                let p = o + d * t;
+                (p, (p - center) / radius.abs())
+            };
+
+            let half_aperture = element.aperture_diameter / 2.0;
+            if p.x() * p.x() + p.y() * p.y() > half_aperture * half_aperture {
+                return None;
+            }
+            if element.is_stop() {
+                if let Some(shape) = &self.stop_shape {
+                    if !shape.contains(Vec3::new(p.x() / half_aperture, p.y() / half_aperture, 0.0)) {
+                        return None;
+                    }
+                }
+            }
+            o = p;
+
+            if !element.is_stop() {
+                let eta_i = element.medium_ior();
+                let eta_t = if i > 0 { self.elements[i - 1].medium_ior() } else { 1.0 };
+                let n = if dot(normal, -d) < 0.0 { -normal } else { normal };
+                d = refract(d, n, eta_i / eta_t)?;
+            }
+        }
+        Some((o, d))
+    }
+
+    /*
+    求从传感器中心出射的近轴光线离开镜头后与光轴的交点(mm)
+     */
+    fn axial_focus(&self) -> Option<f64> {
+        let h = self.rear_radius() * 0.1;
+        let (o, d) = self.trace_from_film(Point::default(), Vec3::new(h, 0.0, self.film_distance))?;
+        if d.x().abs() < 1e-12 {
+            return None;
+        }
+        Some(o.z() - o.x() * d.z() / d.x())
+    }
+
+    /*
+    移动传感器使focus_dist处的平面清晰成像：
+    先扫描找到成像距离从大于目标变为小于目标的区间，再二分求解
+     */
+    pub fn focus(&mut self, focus_dist: f64) {
+        let target = focus_dist / MM_TO_SCENE;
+        let error_at = |lens: &mut RealisticLens, film_distance: f64| {
+            lens.film_distance = film_distance;
+            lens.axial_focus().map(|z| z - target)
+        };
+
+        let step = 0.25;
+        let mut previous: Option<(f64, f64)> = None;
+        let mut bracket = None;
+        for k in 1..=1000 {
+            let fd = step * f64::from(k);
+            let error = error_at(self, fd);
+            if let (Some((prev_fd, prev_error)), Some(error)) = (previous, error) {
+                if prev_error > 0.0 && error <= 0.0 {
+                    bracket = Some((prev_fd, fd));
+                    break;
+                }
+            }
+            previous = error.map(|e| (fd, e));
+        }
+
+        let (mut lo, mut hi) = match bracket {
+            Some(bracket) => bracket,
+            None => {
+                self.film_distance = self.elements.last().map_or(0.0, |e| e.thickness);
+                return;
+            }
+        };
+        for _ in 0..60 {
+            let mid = (lo + hi) / 2.0;
+            match error_at(self, mid) {
+                Some(error) if error > 0.0 => lo = mid,
+                _ => hi = mid
+            }
+        }
+        self.film_distance = (lo + hi) / 2.0;
+    }
+}
+
+fn refract(d: Vec3, n: Vec3, eta: f64) -> Option<Vec3> {
+    let cos_i = dot(-d, n);
+    let sin2_t = eta * eta * (1.0 - cos_i * cos_i).max(0.0);
+    if sin2_t >= 1.0 {
+        // 全反射
+        return None;
+    }
+    let cos_t = (1.0 - sin2_t).sqrt();
+    Some(d * eta + n * (eta * cos_i - cos_t))
+}
+
+impl CameraModel for RealisticLens {
+    fn generate_ray(&self, basis: &CameraBasis, s: f64, t: f64, lens: Vec3) -> Option<(Point, Vec3)> {
+        // 镜头成倒像，传感器上的坐标与图像坐标方向相反
+        let film = Point::new((0.5 - s) * self.sensor_width, (t - 0.5) * self.sensor_height, 0.0);
+        let rear = self.rear_radius();
+        let pupil = Point::new(lens.x() * rear, lens.y() * rear, self.film_distance);
+        let (o, d) = self.trace_from_film(film, pupil - film)?;
+        Some((basis.origin + basis.to_world(o * MM_TO_SCENE), basis.to_world(d)))
+    }
+
+    fn set_focus(&mut self, focus_dist: f64, _defocus_angle: f64) {
+        self.focus(focus_dist);
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // 传感器位于原点、光轴为+z的相机坐标系
+    fn axis_basis() -> CameraBasis {
+        CameraBasis {
+            origin: Point::new(0.0, 0.0, 0.0),
+            u: Vec3::new(1.0, 0.0, 0.0),
+            v: Vec3::new(0.0, 1.0, 0.0),
+            w: Vec3::new(0.0, 0.0, -1.0)
+        }
+    }
+
+    #[test]
+    fn center_rays_converge_at_focus_distance() {
+        let lens = RealisticLens::double_gauss_50mm(36.0, 1.5, 2.0);
+        let basis = axis_basis();
+        let mut traced = 0;
+        for &x in [-0.4, -0.2, 0.2, 0.4].iter() {
+            if let Some((o, d)) = lens.generate_ray(&basis, 0.5, 0.5, Vec3::new(x, 0.0, 0.0)) {
+                // 光线与光轴的交点即成像平面
+                let z = o.z() - o.x() * d.z() / d.x();
+                assert!((z - 2.0).abs() < 0.1, "ray through {} focuses at {}", x, z);
+                traced += 1;
+            }
+        }
+        assert!(traced >= 2);
+    }
+
+    #[test]
+    fn refocusing_moves_the_film() {
+        let mut lens = RealisticLens::double_gauss_50mm(36.0, 1.5, 10.0);
+        let far = lens.film_distance;
+        lens.set_focus(0.5, 0.0);
+        // 对焦越近，传感器离镜头越远
+        assert!(lens.film_distance > far);
+    }
+
+    #[test]
+    fn stop_shape_vignettes_rays() {
+        // 三角形光阑挡住部分圆形光阑能通过的光线
+        let basis = axis_basis();
+        let round = RealisticLens::double_gauss_50mm(36.0, 1.5, 2.0);
+        let mut triangle = RealisticLens::double_gauss_50mm(36.0, 1.5, 2.0);
+        triangle.set_stop_shape(Box::new(crate::aperture::PolygonAperture::new(3, 90.0)));
+        let count = |lens: &RealisticLens| (0..400).filter(|k| {
+            let p = Vec3::new((k % 20) as f64 / 10.0 - 0.95, (k / 20) as f64 / 10.0 - 0.95, 0.0);
+            lens.generate_ray(&basis, 0.5, 0.5, p).is_some()
+        }).count();
+        assert!(count(&triangle) < count(&round));
+        assert!(count(&triangle) > 0);
+    }
+}
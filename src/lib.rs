@@ -1,11 +1,39 @@
-mod image;
-mod vec3;
-mod ray;
-mod sphere;
-mod common;
-mod camera;
-mod material;
-mod aabb;
+pub mod image;
+pub mod vec3;
+pub mod ray;
+pub mod sphere;
+pub mod common;
+pub mod camera;
+pub mod material;
+pub mod aabb;
+pub mod transform;
+pub mod projection;
+pub mod stereo;
+pub mod imageio;
+pub mod distribution;
+pub mod aperture;
+pub mod lens;
+pub mod physical_camera;
+pub mod ies;
+pub mod light;
+pub mod scene;
+pub mod environment;
+pub mod sky;
+pub mod sampler;
+pub mod adaptive;
+pub mod filter;
+pub mod film;
+pub mod exr;
+pub mod denoise;
+pub mod depth;
+pub mod integrator;
+pub mod bdpt;
+pub mod sppm;
+pub mod mlt;
+pub mod spectrum;
+pub mod thin_film;
+pub mod texture;
+pub mod options;
 
 #[cfg(test)]
 mod tests {
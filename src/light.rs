@@ -0,0 +1,459 @@
+use std::rc::Rc;
+use crate::common::{degrees_to_radians, INFINITY, PI};
+use crate::ies::IesProfile;
+use crate::vec3::{Color, cross, dot, Point, Vec3};
+
+/*
+光源采样结果
+ */
+pub struct LightSample {
+    // 着色点指向光源的单位方向
+    pub wi: Vec3,
+    // 到达着色点的辐射亮度(点光源等为强度除以距离平方)
+    pub li: Color,
+    // 到光源的距离，用于阴影光线，平行光为无穷远
+    pub distance: f64,
+    // 立体角概率密度，delta光源为1
+    pub pdf: f64
+}
+
+/*
+从光源发出的光线，作为光子路径的起点
+ */
+pub struct EmissionSample {
+    pub origin: Point,
+    // 单位传播方向
+    pub direction: Vec3,
+    pub le: Color,
+    // 起点的面积概率密度(点光源等为1)与方向的立体角概率密度(平行光为1)
+    pub pdf_pos: f64,
+    pub pdf_dir: f64
+}
+
+pub trait Light {
+    /*
+    从着色点p向光源采样一个方向，u为[0,1)^2的随机数
+     */
+    fn sample_li(&self, p: Point, u: (f64, f64)) -> Option<LightSample>;
+
+    // 光源是否为delta分布(只能通过显式采样到达)
+    fn is_delta(&self) -> bool {
+        true
+    }
+
+    // 从p点沿wi方向采样到该光源的立体角概率密度，delta光源为0
+    fn pdf_li(&self, _p: Point, _wi: Vec3) -> f64 {
+        0.0
+    }
+
+    // 逃逸出场景的光线沿direction方向接收到的辐射亮度(环境光、太阳等无穷远光源)
+    fn le(&self, _direction: Vec3) -> Color {
+        Color::default()
+    }
+
+    // 无穷远光源(平行光、太阳、环境光)，发光位置在覆盖整个场景的圆盘上
+    fn is_infinite(&self) -> bool {
+        false
+    }
+
+    /*
+    采样一条从光源发出的光线，u1、u2为[0,1)^2的随机数，
+    bounds为场景包围球(球心, 半径)，无穷远光源由此确定发射圆盘
+     */
+    fn sample_le(&self, _u1: (f64, f64), _u2: (f64, f64), _bounds: (Point, f64)) -> Option<EmissionSample> {
+        None
+    }
+
+    /*
+    沿direction发出光线的位置与方向概率密度，与sample_le对应；
+    delta分布的一项为0(点光源的位置、平行光的方向)
+     */
+    fn pdf_le(&self, _direction: Vec3, _bounds: (Point, f64)) -> (f64, f64) {
+        (0.0, 0.0)
+    }
+}
+
+/*
+无穷远光源发出的光线：方向为sample_li采样方向的反方向，
+起点在垂直于该方向、半径与场景包围球相同的圆盘上均匀分布
+ */
+pub fn sample_infinite_le(light: &dyn Light, u1: (f64, f64), u2: (f64, f64), (center, radius): (Point, f64)) -> Option<EmissionSample> {
+    let sample = light.sample_li(center, u1)?;
+    if sample.pdf <= 0.0 {
+        return None;
+    }
+    let (t, b) = coordinate_system(sample.wi);
+    let disk = Vec3::sample_unit_disk(u2);
+    Some(EmissionSample {
+        origin: center + (sample.wi + t * disk.x() + b * disk.y()) * radius,
+        direction: -sample.wi,
+        le: sample.li,
+        pdf_pos: 1.0 / (PI * radius * radius),
+        pdf_dir: sample.pdf
+    })
+}
+
+// 以单位向量v为轴的正交基的另外两个轴
+fn coordinate_system(v: Vec3) -> (Vec3, Vec3) {
+    let a = if v.x().abs() > 0.9 { Vec3::new(0.0, 1.0, 0.0) } else { Vec3::new(1.0, 0.0, 0.0) };
+    let t = cross(v, a).unit_vector();
+    (t, cross(v, t))
+}
+
+// 在以axis为轴、cosθ >= cos_theta_max的圆锥内均匀采样方向
+fn sample_cone(axis: Vec3, cos_theta_max: f64, u: (f64, f64)) -> Vec3 {
+    let cos_theta = 1.0 - u.0 * (1.0 - cos_theta_max);
+    let sin_theta = (1.0 - cos_theta * cos_theta).max(0.0).sqrt();
+    let phi = 2.0 * PI * u.1;
+    let (t, b) = coordinate_system(axis);
+    t * (sin_theta * phi.cos()) + b * (sin_theta * phi.sin()) + axis * cos_theta
+}
+
+/*
+点光源，默认各向同性；设置IES配光后强度按灯具的坎德拉分布调制
+ */
+pub struct PointLight {
+    position: Point,
+    intensity: Color,
+    // IES配光与灯具竖直轴(nadir)方向
+    profile: Option<(Rc<IesProfile>, Vec3)>
+}
+
+impl PointLight {
+    pub fn new(position: Point, intensity: Color) -> PointLight {
+        PointLight {
+            position,
+            intensity,
+            profile: None
+        }
+    }
+
+    // 设置后intensity作为坎德拉值的缩放系数(可带颜色)
+    pub fn set_profile(&mut self, profile: Rc<IesProfile>, nadir: Vec3) {
+        self.profile = Some((profile, nadir.unit_vector()));
+    }
+}
+
+impl Light for PointLight {
+    fn sample_li(&self, p: Point, _u: (f64, f64)) -> Option<LightSample> {
+        let to_light = self.position - p;
+        let distance = to_light.length();
+        if distance <= 0.0 {
+            return None;
+        }
+        let wi = to_light / distance;
+        let candela = match &self.profile {
+            Some((profile, nadir)) => profile.candela_toward(*nadir, -wi),
+            None => 1.0
+        };
+        if candela <= 0.0 {
+            return None;
+        }
+        Some(LightSample {
+            wi,
+            li: self.intensity * (candela / (distance * distance)),
+            distance,
+            pdf: 1.0
+        })
+    }
+
+    fn sample_le(&self, _u1: (f64, f64), u2: (f64, f64), _bounds: (Point, f64)) -> Option<EmissionSample> {
+        let direction = Vec3::sample_unit_sphere(u2);
+        let candela = match &self.profile {
+            Some((profile, nadir)) => profile.candela_toward(*nadir, direction),
+            None => 1.0
+        };
+        Some(EmissionSample {
+            origin: self.position,
+            direction,
+            le: self.intensity * candela,
+            pdf_pos: 1.0,
+            pdf_dir: 1.0 / (4.0 * PI)
+        })
+    }
+
+    fn pdf_le(&self, _direction: Vec3, _bounds: (Point, f64)) -> (f64, f64) {
+        (0.0, 1.0 / (4.0 * PI))
+    }
+}
+
+/*
+聚光灯，光锥内角以内为全强度，内角到外角之间平滑衰减；
+设置IES配光后以光照方向为灯具竖直轴，在光锥内再按坎德拉分布调制
+ */
+pub struct SpotLight {
+    position: Point,
+    // 光照方向(从光源指向外)
+    direction: Vec3,
+    intensity: Color,
+    cos_falloff_start: f64,
+    cos_total_width: f64,
+    profile: Option<Rc<IesProfile>>
+}
+
+impl SpotLight {
+    // 角度为半角，角度制
+    pub fn new(position: Point, direction: Vec3, intensity: Color, falloff_start: f64, total_width: f64) -> SpotLight {
+        SpotLight {
+            position,
+            direction: direction.unit_vector(),
+            intensity,
+            cos_falloff_start: degrees_to_radians(falloff_start).cos(),
+            cos_total_width: degrees_to_radians(total_width).cos(),
+            profile: None
+        }
+    }
+
+    // 设置后intensity作为坎德拉值的缩放系数(可带颜色)
+    pub fn set_profile(&mut self, profile: Rc<IesProfile>) {
+        self.profile = Some(profile);
+    }
+
+    fn falloff(&self, cos_theta: f64) -> f64 {
+        if cos_theta >= self.cos_falloff_start {
+            return 1.0;
+        }
+        if cos_theta <= self.cos_total_width {
+            return 0.0;
+        }
+        // smoothstep
+        let x = (cos_theta - self.cos_total_width) / (self.cos_falloff_start - self.cos_total_width);
+        x * x * (3.0 - 2.0 * x)
+    }
+}
+
+impl Light for SpotLight {
+    fn sample_li(&self, p: Point, _u: (f64, f64)) -> Option<LightSample> {
+        let to_light = self.position - p;
+        let distance = to_light.length();
+        if distance <= 0.0 {
+            return None;
+        }
+        let wi = to_light / distance;
+        let mut falloff = self.falloff(dot(-wi, self.direction));
+        if let Some(profile) = &self.profile {
+            falloff *= profile.candela_toward(self.direction, -wi);
+        }
+        if falloff <= 0.0 {
+            return None;
+        }
+        Some(LightSample {
+            wi,
+            li: self.intensity * (falloff / (distance * distance)),
+            distance,
+            pdf: 1.0
+        })
+    }
+
+    fn sample_le(&self, _u1: (f64, f64), u2: (f64, f64), _bounds: (Point, f64)) -> Option<EmissionSample> {
+        // 在整个光锥内均匀采样方向
+        let direction = sample_cone(self.direction, self.cos_total_width, u2);
+        let mut falloff = self.falloff(dot(direction, self.direction));
+        if let Some(profile) = &self.profile {
+            falloff *= profile.candela_toward(self.direction, direction);
+        }
+        Some(EmissionSample {
+            origin: self.position,
+            direction,
+            le: self.intensity * falloff,
+            pdf_pos: 1.0,
+            pdf_dir: 1.0 / (2.0 * PI * (1.0 - self.cos_total_width))
+        })
+    }
+
+    fn pdf_le(&self, direction: Vec3, _bounds: (Point, f64)) -> (f64, f64) {
+        if dot(direction.unit_vector(), self.direction) >= self.cos_total_width {
+            (0.0, 1.0 / (2.0 * PI * (1.0 - self.cos_total_width)))
+        } else {
+            (0.0, 0.0)
+        }
+    }
+}
+
+/*
+平行光，irradiance为垂直于光线方向平面上的辐照度
+ */
+pub struct DirectionalLight {
+    // 光线传播方向
+    direction: Vec3,
+    irradiance: Color
+}
+
+impl DirectionalLight {
+    pub fn new(direction: Vec3, irradiance: Color) -> DirectionalLight {
+        DirectionalLight {
+            direction: direction.unit_vector(),
+            irradiance
+        }
+    }
+}
+
+impl Light for DirectionalLight {
+    fn sample_li(&self, _p: Point, _u: (f64, f64)) -> Option<LightSample> {
+        Some(LightSample {
+            wi: -self.direction,
+            li: self.irradiance,
+            distance: INFINITY,
+            pdf: 1.0
+        })
+    }
+
+    fn is_infinite(&self) -> bool {
+        true
+    }
+
+    fn sample_le(&self, u1: (f64, f64), u2: (f64, f64), bounds: (Point, f64)) -> Option<EmissionSample> {
+        sample_infinite_le(self, u1, u2, bounds)
+    }
+
+    fn pdf_le(&self, _direction: Vec3, (_, radius): (Point, f64)) -> (f64, f64) {
+        (1.0 / (PI * radius * radius), 0.0)
+    }
+}
+
+/*
+太阳光：具有角直径的远处圆盘光源，在圆盘对应的立体角内均匀采样，产生软阴影
+ */
+pub struct SunLight {
+    // 指向太阳的方向
+    to_sun: Vec3,
+    // 太阳圆盘的辐射亮度
+    radiance: Color,
+    cos_theta_max: f64
+}
+
+impl SunLight {
+    // irradiance为垂直于太阳方向平面上的辐照度，angular_diameter为角直径(角度制，太阳约0.53°)
+    pub fn new(to_sun: Vec3, irradiance: Color, angular_diameter: f64) -> SunLight {
+        let cos_theta_max = degrees_to_radians(angular_diameter / 2.0).cos();
+        let solid_angle = 2.0 * PI * (1.0 - cos_theta_max);
+        SunLight {
+            to_sun: to_sun.unit_vector(),
+            radiance: irradiance / solid_angle,
+            cos_theta_max
+        }
+    }
+
+    pub fn pdf(&self) -> f64 {
+        1.0 / (2.0 * PI * (1.0 - self.cos_theta_max))
+    }
+
+    // 方向是否落在太阳圆盘内
+    pub fn contains(&self, direction: Vec3) -> bool {
+        dot(direction.unit_vector(), self.to_sun) >= self.cos_theta_max
+    }
+}
+
+impl Light for SunLight {
+    fn sample_li(&self, _p: Point, u: (f64, f64)) -> Option<LightSample> {
+        // 在以太阳方向为轴的圆锥内均匀采样
+        Some(LightSample {
+            wi: sample_cone(self.to_sun, self.cos_theta_max, u),
+            li: self.radiance,
+            distance: INFINITY,
+            pdf: self.pdf()
+        })
+    }
+
+    fn is_delta(&self) -> bool {
+        false
+    }
+
+    fn pdf_li(&self, _p: Point, wi: Vec3) -> f64 {
+        if self.contains(wi) { self.pdf() } else { 0.0 }
+    }
+
+    fn le(&self, direction: Vec3) -> Color {
+        if self.contains(direction) { self.radiance } else { Color::default() }
+    }
+
+    fn is_infinite(&self) -> bool {
+        true
+    }
+
+    fn sample_le(&self, u1: (f64, f64), u2: (f64, f64), bounds: (Point, f64)) -> Option<EmissionSample> {
+        sample_infinite_le(self, u1, u2, bounds)
+    }
+
+    fn pdf_le(&self, direction: Vec3, (_, radius): (Point, f64)) -> (f64, f64) {
+        (1.0 / (PI * radius * radius), self.pdf_li(Point::default(), -direction))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn close(a: f64, b: f64) -> bool {
+        (a - b).abs() < 1e-9
+    }
+
+    #[test]
+    fn point_light_falls_off_with_inverse_square() {
+        let light = PointLight::new(Point::new(0.0, 4.0, 0.0), Color::new(8.0, 8.0, 8.0));
+        let near = light.sample_li(Point::new(0.0, 2.0, 0.0), (0.5, 0.5)).unwrap();
+        let far = light.sample_li(Point::new(0.0, 0.0, 0.0), (0.5, 0.5)).unwrap();
+        assert!(close(near.li.x(), 2.0) && close(far.li.x(), 0.5));
+        assert!(close(far.distance, 4.0));
+        assert_eq!(far.wi, Vec3::new(0.0, 1.0, 0.0));
+        // delta光源：采样概率密度按1计，材质采样击中的概率为0
+        assert_eq!(far.pdf, 1.0);
+        assert!(light.is_delta());
+        assert_eq!(light.pdf_li(Point::default(), far.wi), 0.0);
+    }
+
+    #[test]
+    fn spot_light_cone_and_falloff() {
+        let light = SpotLight::new(Point::new(0.0, 1.0, 0.0), Vec3::new(0.0, -1.0, 0.0), Color::new(1.0, 1.0, 1.0), 10.0, 30.0);
+        // 光锥中心为全强度
+        let center = light.sample_li(Point::new(0.0, 0.0, 0.0), (0.5, 0.5)).unwrap();
+        assert!(close(center.li.x(), 1.0));
+        // 20°处位于内外角之间，按smoothstep衰减
+        let edge = light.sample_li(Point::new(degrees_to_radians(20.0).tan(), 0.0, 0.0), (0.5, 0.5)).unwrap();
+        let distance_squared = edge.distance * edge.distance;
+        assert!(edge.li.x() * distance_squared > 0.0 && edge.li.x() * distance_squared < 1.0);
+        // 光锥之外与光源背后没有光照
+        assert!(light.sample_li(Point::new(1.0, 0.0, 0.0), (0.5, 0.5)).is_none());
+        assert!(light.sample_li(Point::new(0.0, 2.0, 0.0), (0.5, 0.5)).is_none());
+    }
+
+    #[test]
+    fn spot_light_emission_stays_in_cone() {
+        let light = SpotLight::new(Point::default(), Vec3::new(0.0, 0.0, 1.0), Color::new(1.0, 1.0, 1.0), 5.0, 15.0);
+        let cos_total_width = degrees_to_radians(15.0).cos();
+        for k in 0..64 {
+            let u = ((k % 8) as f64 / 8.0 + 0.0625, (k / 8) as f64 / 8.0 + 0.0625);
+            let sample = light.sample_le((0.5, 0.5), u, (Point::default(), 1.0)).unwrap();
+            assert!(sample.direction.z() >= cos_total_width - 1e-12);
+            assert!(close(sample.pdf_dir, light.pdf_le(sample.direction, (Point::default(), 1.0)).1));
+        }
+    }
+
+    #[test]
+    fn directional_light_is_constant() {
+        let light = DirectionalLight::new(Vec3::new(0.0, -2.0, 0.0), Color::new(0.3, 0.3, 0.3));
+        let a = light.sample_li(Point::new(0.0, 0.0, 0.0), (0.1, 0.2)).unwrap();
+        let b = light.sample_li(Point::new(50.0, -3.0, 7.0), (0.9, 0.4)).unwrap();
+        assert_eq!(a.li, b.li);
+        assert_eq!(a.wi, Vec3::new(0.0, 1.0, 0.0));
+        assert_eq!(a.distance, INFINITY);
+        assert!(light.is_infinite());
+        // 发射圆盘覆盖半径为2的场景包围球
+        assert!(close(light.pdf_le(Vec3::new(0.0, -1.0, 0.0), (Point::default(), 2.0)).0, 1.0 / (4.0 * PI)));
+    }
+
+    #[test]
+    fn sun_samples_match_pdf() {
+        let light = SunLight::new(Vec3::new(0.0, 1.0, 0.0), Color::new(1.0, 1.0, 1.0), 2.0);
+        let solid_angle = 2.0 * PI * (1.0 - degrees_to_radians(1.0).cos());
+        assert!(close(light.pdf(), 1.0 / solid_angle));
+        for k in 0..16 {
+            let sample = light.sample_li(Point::default(), (k as f64 / 16.0, 0.3)).unwrap();
+            assert!(light.contains(sample.wi));
+            assert!(close(sample.pdf, light.pdf_li(Point::default(), sample.wi)));
+        }
+        assert_eq!(light.pdf_li(Point::default(), Vec3::new(1.0, 0.0, 0.0)), 0.0);
+        // 圆盘辐射亮度乘以立体角为辐照度
+        assert!(close(light.le(Vec3::new(0.0, 1.0, 0.0)).x() * solid_angle, 1.0));
+    }
+}
@@ -1,10 +1,64 @@
-use crate::common::{ff_min, get_random_double, random_double};
+use std::rc::Rc;
+use crate::common::{ff_min, hash, PI};
+use crate::depth::RayType;
 use crate::ray::Ray;
+use crate::sampler::Sampler;
 use crate::sphere::HitRecord;
-use crate::vec3::{Color, dot, Vec3};
+use crate::texture::{ConstantTexture, Texture};
+use crate::thin_film::{Substrate, ThinFilm};
+use crate::vec3::{Color, cross, dot, Vec3};
 
 pub trait Material {
-    fn scatter(&self, r_in: &Ray, hit_record: &HitRecord, attenuation: &mut Vec3, scattered: &mut Ray) -> bool;
+    /*
+    sampler提供散射方向采样所需的随机数。
+    采样到镜面反射/折射分支时把scattered标记为镜面，组合材质的pdf不能区分所选的分支
+     */
+    fn scatter(&self, r_in: &Ray, hit_record: &HitRecord, attenuation: &mut Vec3, scattered: &mut Ray, sampler: &mut dyn Sampler) -> bool;
+
+    // 自发光辐射亮度
+    fn emitted(&self, _hit_record: &HitRecord) -> Color {
+        Color::default()
+    }
+
+    /*
+    BSDF与cos项的乘积，用于光源直接采样(wi指向光源)。
+    镜面反射/折射无法被显式采样，返回0
+     */
+    fn eval(&self, _r_in: &Ray, _hit_record: &HitRecord, _wi: Vec3) -> Color {
+        Color::default()
+    }
+
+    // scatter采样到wi方向的立体角概率密度，镜面反射/折射为0
+    fn pdf(&self, _r_in: &Ray, _hit_record: &HitRecord, _wi: Vec3) -> f64 {
+        0.0
+    }
+
+    /*
+    散射光线的类型，用于按类型限制路径深度。
+    默认：非镜面散射为漫反射，穿过表面的为折射，其余为镜面反射
+     */
+    fn ray_type(&self, _r_in: &Ray, hit_record: &HitRecord, scattered: &Ray) -> RayType {
+        if !scattered.is_specular() {
+            RayType::Diffuse
+        } else if dot(scattered.direction(), hit_record.get_normal()) < 0.0 {
+            RayType::Transmission
+        } else {
+            RayType::Specular
+        }
+    }
+
+    // 反照率，用于输出变量与降噪
+    fn albedo(&self, _hit_record: &HitRecord) -> Color {
+        Color::new(1.0, 1.0, 1.0)
+    }
+
+    /*
+    透明遮罩测试，求交时调用。返回false时光线穿过该交点(例如树叶贴片的镂空部分)，
+    求交继续寻找更远的交点，阴影光线同样穿过
+     */
+    fn alpha_test(&self, _r_in: &Ray, _hit_record: &HitRecord) -> bool {
+        true
+    }
 }
 
 pub struct Lambertian {
@@ -20,17 +74,37 @@ impl Lambertian {
 }
 
 impl Material for Lambertian {
-    fn scatter(&self, r_in: &Ray, hit_record: &HitRecord, attenuation: &mut Vec3, scattered: &mut Ray) ->bool {
-        let scatter_direction = hit_record.get_normal() + Vec3::random_in_unit_sphere();
+    fn scatter(&self, r_in: &Ray, hit_record: &HitRecord, attenuation: &mut Vec3, scattered: &mut Ray, sampler: &mut dyn Sampler) ->bool {
+        let scatter_direction = hit_record.get_normal() + Vec3::sample_unit_sphere(sampler.get_2d());
         attenuation.copy(self.albedo);
         scattered.copy(Ray::new(hit_record.get_p(), scatter_direction, r_in.get_time()));
         true
     }
+
+    fn eval(&self, _r_in: &Ray, hit_record: &HitRecord, wi: Vec3) -> Color {
+        let cosine = dot(hit_record.get_normal(), wi);
+        if cosine <= 0.0 {
+            return Color::default();
+        }
+        self.albedo * (cosine / PI)
+    }
+
+    fn pdf(&self, _r_in: &Ray, hit_record: &HitRecord, wi: Vec3) -> f64 {
+        // 法向量加单位球面随机向量即为余弦分布
+        let cosine = dot(hit_record.get_normal(), wi.unit_vector());
+        if cosine <= 0.0 { 0.0 } else { cosine / PI }
+    }
+
+    fn albedo(&self, _hit_record: &HitRecord) -> Color {
+        self.albedo
+    }
 }
 
 pub struct Metal {
     albedo: Vec3,
-    fuzz: f64
+    fuzz: f64,
+    // 表面的薄膜涂层(例如氧化层)
+    thin_film: Option<ThinFilm>
 }
 
 impl Metal {
@@ -38,60 +112,781 @@ impl Metal {
         Metal {
             albedo,
             fuzz: if f < 1.0 { f } else { 1.0 },
+            thin_film: None
         }
     }
+
+    pub fn set_thin_film(&mut self, thin_film: Option<ThinFilm>) {
+        self.thin_film = thin_film;
+    }
 }
 
 impl Material for Metal {
-    fn scatter(&self, r_in: &Ray, hit_record: &HitRecord, attenuation: &mut Vec3, scattered: &mut Ray) -> bool {
-        let reflected = Vec3::reflect(r_in.direction().unit_vector(), hit_record.get_normal());
-        scattered.copy(Ray::new(hit_record.get_p(), reflected + Vec3::random_in_unit_sphere() * self.fuzz, r_in.get_time()));
-        attenuation.copy(self.albedo);
+    fn scatter(&self, r_in: &Ray, hit_record: &HitRecord, attenuation: &mut Vec3, scattered: &mut Ray, sampler: &mut dyn Sampler) -> bool {
+        let unit_direction = r_in.direction().unit_vector();
+        let reflected = Vec3::reflect(unit_direction, hit_record.get_normal());
+        scattered.copy(Ray::new(hit_record.get_p(), reflected + Vec3::sample_unit_sphere(sampler.get_2d()) * self.fuzz, r_in.get_time()));
+        scattered.set_specular(true);
+        match (&self.thin_film, r_in.get_wavelengths()) {
+            (Some(film), Some(wavelengths)) => {
+                // 光谱渲染：在路径的各波长处计算干涉
+                let cos_theta = dot(-unit_direction, hit_record.get_normal()).clamp(0.0, 1.0);
+                let substrate = Substrate::Reflector(wavelengths.upsample(self.albedo));
+                attenuation.copy(film.reflectance_at(cos_theta, 1.0, substrate, wavelengths.get_lambda()));
+                scattered.set_spectral_attenuation(true);
+            }
+            (Some(film), None) => {
+                let cos_theta = dot(-unit_direction, hit_record.get_normal()).clamp(0.0, 1.0);
+                attenuation.copy(film.reflectance(cos_theta, 1.0, Substrate::Reflector(self.albedo)));
+            }
+            (None, _) => attenuation.copy(self.albedo)
+        }
         dot(scattered.direction(), hit_record.get_normal()) > 0.0
     }
+
+    fn albedo(&self, _hit_record: &HitRecord) -> Color {
+        self.albedo
+    }
+}
+
+/*
+折射率随波长的变化(色散)，波长以微米代入公式
+ */
+#[derive(Debug, Copy, Clone)]
+pub enum Dispersion {
+    // 柯西公式 n = a + b/λ²
+    Cauchy { a: f64, b: f64 },
+    // 塞尔迈耶尔公式 n² = 1 + Σ b_i·λ²/(λ² - c_i)
+    Sellmeier { b: [f64; 3], c: [f64; 3] }
+}
+
+// 夫琅和费d、F、C谱线的波长(纳米)，用于阿贝数
+const LAMBDA_D: f64 = 587.56;
+const LAMBDA_F: f64 = 486.13;
+const LAMBDA_C: f64 = 656.27;
+
+impl Dispersion {
+    /*
+    由d线折射率与阿贝数 V = (n_d - 1)/(n_F - n_C) 得到柯西公式系数，阿贝数越小色散越强
+     */
+    pub fn from_abbe(nd: f64, abbe: f64) -> Dispersion {
+        let inv_sq = |lambda: f64| 1.0 / (lambda * lambda * 1e-6);
+        let b = (nd - 1.0) / (abbe * (inv_sq(LAMBDA_F) - inv_sq(LAMBDA_C)));
+        Dispersion::Cauchy { a: nd - b * inv_sq(LAMBDA_D), b }
+    }
+
+    // 硼硅酸盐冕牌玻璃BK7
+    pub fn bk7() -> Dispersion {
+        Dispersion::Sellmeier { b: [1.03961212, 0.231792344, 1.01046945], c: [0.00600069867, 0.0200179144, 103.560653] }
+    }
+
+    pub fn diamond() -> Dispersion {
+        Dispersion::Sellmeier { b: [4.3356, 0.3306, 0.0], c: [0.1060 * 0.1060, 0.1750 * 0.1750, 0.0] }
+    }
+
+    // 波长lambda(纳米)处的折射率
+    pub fn ior(&self, lambda: f64) -> f64 {
+        let l2 = lambda * lambda * 1e-6;
+        match self {
+            Dispersion::Cauchy { a, b } => a + b / l2,
+            Dispersion::Sellmeier { b, c } => {
+                (1.0 + (0..3).map(|i| b[i] * l2 / (l2 - c[i])).sum::<f64>()).sqrt()
+            }
+        }
+    }
 }
 
 pub struct Dielectric {
-    ri: f64
+    ri: f64,
+    dispersion: Option<Dispersion>,
+    // 外表面的薄膜涂层(例如镜头镀膜、肥皂泡)
+    thin_film: Option<ThinFilm>
 }
 
 impl Dielectric {
     pub fn new(ri:f64)->Dielectric {
         Dielectric {
-            ri
+            ri,
+            dispersion: None,
+            thin_film: None
+        }
+    }
+
+    pub fn set_thin_film(&mut self, thin_film: Option<ThinFilm>) {
+        self.thin_film = thin_film;
+    }
+
+    /*
+    色散介质：光谱渲染时按主波长计算折射率，ri为RGB渲染时使用的折射率
+     */
+    pub fn new_dispersive(ri: f64, dispersion: Dispersion) -> Dielectric {
+        Dielectric {
+            ri,
+            dispersion: Some(dispersion),
+            thin_film: None
         }
     }
 }
 
 impl Material for Dielectric {
-    fn scatter(&self, r_in: &Ray, hit_record: &HitRecord, attenuation: &mut Vec3, scattered: &mut Ray) -> bool {
+    fn scatter(&self, r_in: &Ray, hit_record: &HitRecord, attenuation: &mut Vec3, scattered: &mut Ray, sampler: &mut dyn Sampler) -> bool {
         attenuation.copy(Vec3::new(1.0, 1.0, 1.0));
+        // 折射方向随波长不同，只有主波长能继续沿该路径传播
+        let (ri, wavelengths) = match (&self.dispersion, r_in.get_wavelengths()) {
+            (Some(dispersion), Some(mut wavelengths)) => {
+                wavelengths.terminate_secondary();
+                (dispersion.ior(wavelengths.hero()), Some(wavelengths))
+            }
+            _ => (self.ri, None)
+        };
         let etai_over_etat = if hit_record.get_front_face() {
-            1.0 / self.ri
+            1.0 / ri
         } else {
-            self.ri
+            ri
         };
 
         let unit_direction = r_in.direction().unit_vector();
         let cos_theta = ff_min(dot(-unit_direction, hit_record.get_normal()), 1.0);
         let sin_theta = (1.0 - cos_theta * cos_theta).sqrt();
-        if etai_over_etat * sin_theta > 1.0 {
-            // 反射
-            let reflected = Vec3::reflect(unit_direction, hit_record.get_normal());
-            scattered.copy(Ray::new(hit_record.get_p(), reflected, r_in.get_time()));
+        if let Some(film) = &self.thin_film {
+            // 各通道反射率不同：按平均反射率选择反射或折射，再按通道修正权重。膜不吸收光，透射率为1-R
+            let (n1, n3) = if hit_record.get_front_face() { (1.0, ri) } else { (ri, 1.0) };
+            // 光谱渲染时在路径的各波长处计算干涉，色散终止次波长后只有主波长有效
+            let path_wavelengths = wavelengths.or(r_in.get_wavelengths());
+            let reflectance = match path_wavelengths {
+                Some(lambdas) => film.reflectance_at(cos_theta, n1, Substrate::Dielectric(n3), lambdas.get_lambda()),
+                None => film.reflectance(cos_theta, n1, Substrate::Dielectric(n3))
+            };
+            let reflect_prob = (reflectance.x() + reflectance.y() + reflectance.z()) / 3.0;
+            let direction = if etai_over_etat * sin_theta > 1.0 || sampler.get_1d() < reflect_prob {
+                attenuation.copy(reflectance / reflect_prob);
+                Vec3::reflect(unit_direction, hit_record.get_normal())
+            } else {
+                attenuation.copy((Color::new(1.0, 1.0, 1.0) - reflectance) / (1.0 - reflect_prob));
+                Vec3::refract(unit_direction, hit_record.get_normal(), etai_over_etat)
+            };
+            scattered.copy(Ray::new(hit_record.get_p(), direction, r_in.get_time()));
+            scattered.set_wavelengths(wavelengths);
+            scattered.set_spectral_attenuation(path_wavelengths.is_some());
+            scattered.set_specular(true);
             return true;
         }
+        // 全反射，或按Christophe Schlick近似的菲涅尔反射率选择反射
+        let direction = if etai_over_etat * sin_theta > 1.0 || sampler.get_1d() < Vec3::schlick(cos_theta, etai_over_etat) {
+            Vec3::reflect(unit_direction, hit_record.get_normal())
+        } else {
+            Vec3::refract(unit_direction, hit_record.get_normal(), etai_over_etat)
+        };
+        scattered.copy(Ray::new(hit_record.get_p(), direction, r_in.get_time()));
+        scattered.set_wavelengths(wavelengths);
+        scattered.set_specular(true);
+        true
+    }
+}
+/*
+漫射发光材质，不散射光线
+ */
+pub struct DiffuseLight {
+    emit: Color
+}
+
+impl DiffuseLight {
+    pub fn new(emit: Color) -> DiffuseLight {
+        DiffuseLight {
+            emit
+        }
+    }
+}
+
+impl Material for DiffuseLight {
+    fn scatter(&self, _r_in: &Ray, _hit_record: &HitRecord, _attenuation: &mut Vec3, _scattered: &mut Ray, _sampler: &mut dyn Sampler) -> bool {
+        false
+    }
+
+    fn emitted(&self, _hit_record: &HitRecord) -> Color {
+        self.emit
+    }
+}
+
+// 求交时(没有采样器)随机判定透明度所用的哈希盐值，避免嵌套的组合材质做出相关的选择
+const MIX_SALT: u64 = 0x006d_6978;
+const ALPHA_SALT: u64 = 0x616c_7068;
+
+/*
+交点位置的哈希值映射到[0,1)。组合材质用它代替采样器随机选择组成部分，
+使同一交点上的scatter、eval与pdf选到同一个材质。只取决于位置，与渲染种子无关
+ */
+fn hit_hash(hit_record: &HitRecord, salt: u64) -> f64 {
+    let p = hit_record.get_p();
+    let h = hash(hash(hash(salt, p.x().to_bits()), p.y().to_bits()), p.z().to_bits());
+    (h >> 11) as f64 / (1u64 << 53) as f64
+}
+
+/*
+两种材质按遮罩混合，遮罩纹理的亮度为第二种材质所占的比例。
+散射时按比例用采样器随机选用其中一种材质；光源采样所用的eval与pdf按比例线性混合，
+散射光线的类型按混合后的概率密度判定
+ */
+pub struct MixMaterial {
+    first: Rc<dyn Material>,
+    second: Rc<dyn Material>,
+    mask: Rc<dyn Texture>
+}
+
+impl MixMaterial {
+    pub fn new(first: Rc<dyn Material>, second: Rc<dyn Material>, mask: Rc<dyn Texture>) -> MixMaterial {
+        MixMaterial {
+            first,
+            second,
+            mask
+        }
+    }
+
+    // 固定比例amount的混合
+    pub fn new_constant(first: Rc<dyn Material>, second: Rc<dyn Material>, amount: f64) -> MixMaterial {
+        MixMaterial::new(first, second, Rc::new(ConstantTexture::new(Color::new(amount, amount, amount))))
+    }
+
+    fn amount(&self, hit_record: &HitRecord) -> f64 {
+        let (u, v) = hit_record.get_uv();
+        self.mask.value(u, v, hit_record.get_p()).luminance().clamp(0.0, 1.0)
+    }
+}
+
+impl Material for MixMaterial {
+    fn scatter(&self, r_in: &Ray, hit_record: &HitRecord, attenuation: &mut Vec3, scattered: &mut Ray, sampler: &mut dyn Sampler) -> bool {
+        let material = if sampler.get_1d() < self.amount(hit_record) { &self.second } else { &self.first };
+        material.scatter(r_in, hit_record, attenuation, scattered, sampler)
+    }
+
+    fn emitted(&self, hit_record: &HitRecord) -> Color {
+        let amount = self.amount(hit_record);
+        self.first.emitted(hit_record) * (1.0 - amount) + self.second.emitted(hit_record) * amount
+    }
+
+    fn eval(&self, r_in: &Ray, hit_record: &HitRecord, wi: Vec3) -> Color {
+        let amount = self.amount(hit_record);
+        self.first.eval(r_in, hit_record, wi) * (1.0 - amount) + self.second.eval(r_in, hit_record, wi) * amount
+    }
+
+    fn pdf(&self, r_in: &Ray, hit_record: &HitRecord, wi: Vec3) -> f64 {
+        let amount = self.amount(hit_record);
+        self.first.pdf(r_in, hit_record, wi) * (1.0 - amount) + self.second.pdf(r_in, hit_record, wi) * amount
+    }
+
+    fn albedo(&self, hit_record: &HitRecord) -> Color {
+        let amount = self.amount(hit_record);
+        self.first.albedo(hit_record) * (1.0 - amount) + self.second.albedo(hit_record) * amount
+    }
+
+    fn alpha_test(&self, r_in: &Ray, hit_record: &HitRecord) -> bool {
+        let (first, second) = (self.first.alpha_test(r_in, hit_record), self.second.alpha_test(r_in, hit_record));
+        if first == second {
+            return first;
+        }
+        // 两种材质的判定不同时，按比例随机选用其中一种的判定
+        if hit_hash(hit_record, MIX_SALT) < self.amount(hit_record) { second } else { first }
+    }
+}
+
+/*
+透明涂层(清漆)覆盖在任意基底材质上，例如车漆、上漆的木头。
+散射时按观察方向上涂层的菲涅尔反射率(Schlick近似)用采样器随机选用涂层的镜面反射，否则进入基底材质，
+光线穿出涂层时再乘以该方向的透射率。光源采样只看到基底部分：eval与pdf乘以观察方向的透射率
+ */
+pub struct CoatedMaterial {
+    base: Rc<dyn Material>,
+    ior: f64
+}
+
+impl CoatedMaterial {
+    pub fn new(base: Rc<dyn Material>, ior: f64) -> CoatedMaterial {
+        CoatedMaterial {
+            base,
+            ior
+        }
+    }
+
+    // 涂层沿方向w的透射率
+    fn transmittance(&self, hit_record: &HitRecord, w: Vec3) -> f64 {
+        let cosine = dot(hit_record.get_normal(), w.unit_vector()).abs().min(1.0);
+        1.0 - Vec3::schlick(cosine, self.ior)
+    }
+}
 
-        // Christophe Schlick
-        let reflect_prob = Vec3::schlick(cos_theta,etai_over_etat);
-        if get_random_double() < reflect_prob {
-            let reflected = Vec3::reflect(unit_direction, hit_record.get_normal());
+impl Material for CoatedMaterial {
+    fn scatter(&self, r_in: &Ray, hit_record: &HitRecord, attenuation: &mut Vec3, scattered: &mut Ray, sampler: &mut dyn Sampler) -> bool {
+        if sampler.get_1d() >= self.transmittance(hit_record, r_in.direction()) {
+            let reflected = Vec3::reflect(r_in.direction().unit_vector(), hit_record.get_normal());
             scattered.copy(Ray::new(hit_record.get_p(), reflected, r_in.get_time()));
+            scattered.set_specular(true);
+            attenuation.copy(Color::new(1.0, 1.0, 1.0));
+            return true;
+        }
+        if !self.base.scatter(r_in, hit_record, attenuation, scattered, sampler) {
+            return false;
+        }
+        let transmittance = self.transmittance(hit_record, scattered.direction());
+        attenuation.copy(*attenuation * transmittance);
+        true
+    }
+
+    fn emitted(&self, hit_record: &HitRecord) -> Color {
+        self.base.emitted(hit_record)
+    }
+
+    fn eval(&self, r_in: &Ray, hit_record: &HitRecord, wi: Vec3) -> Color {
+        self.base.eval(r_in, hit_record, wi) * (self.transmittance(hit_record, r_in.direction()) * self.transmittance(hit_record, wi))
+    }
+
+    fn pdf(&self, r_in: &Ray, hit_record: &HitRecord, wi: Vec3) -> f64 {
+        self.base.pdf(r_in, hit_record, wi) * self.transmittance(hit_record, r_in.direction())
+    }
+
+    fn albedo(&self, hit_record: &HitRecord) -> Color {
+        self.base.albedo(hit_record)
+    }
+
+    fn alpha_test(&self, r_in: &Ray, hit_record: &HitRecord) -> bool {
+        self.base.alpha_test(r_in, hit_record)
+    }
+}
+
+// 凹凸贴图求高度差分时参数坐标的步长
+const BUMP_DELTA: f64 = 0.0005;
+
+/*
+着色法向量的扰动方式
+ */
+pub enum NormalPerturbation {
+    // 切线空间法线贴图：RGB映射到[-1,1]分别为沿dpdu、沿dpdv与沿外法向量的分量
+    NormalMap(Rc<dyn Texture>),
+    // 凹凸贴图：纹理亮度乘以scale为沿外法向量的高度偏移
+    Bump { height: Rc<dyn Texture>, scale: f64 }
+}
+
+/*
+扰动着色法向量的材质：按法线贴图或凹凸贴图计算新的法向量，
+替换交点记录中的法向量后交给基底材质，几何形状、光线起点与光源采样所用的几何法向量不变
+ */
+pub struct NormalMappedMaterial {
+    base: Rc<dyn Material>,
+    perturbation: NormalPerturbation
+}
+
+impl NormalMappedMaterial {
+    pub fn new(base: Rc<dyn Material>, perturbation: NormalPerturbation) -> NormalMappedMaterial {
+        NormalMappedMaterial {
+            base,
+            perturbation
+        }
+    }
+
+    pub fn new_normal_map(base: Rc<dyn Material>, normal_map: Rc<dyn Texture>) -> NormalMappedMaterial {
+        NormalMappedMaterial::new(base, NormalPerturbation::NormalMap(normal_map))
+    }
+
+    pub fn new_bump(base: Rc<dyn Material>, height: Rc<dyn Texture>, scale: f64) -> NormalMappedMaterial {
+        NormalMappedMaterial::new(base, NormalPerturbation::Bump { height, scale })
+    }
+
+    // 扰动后的外法向量，切线退化时返回None
+    fn perturbed_normal(&self, hit_record: &HitRecord, outward: Vec3) -> Option<Vec3> {
+        let (u, v) = hit_record.get_uv();
+        let p = hit_record.get_p();
+        let (dpdu, dpdv) = (hit_record.get_dpdu(), hit_record.get_dpdv());
+        let normal = match &self.perturbation {
+            NormalPerturbation::NormalMap(normal_map) => {
+                // 以外法向量对dpdu做正交化得到切线，两极处dpdu为0时改由dpdv构造
+                let mut tangent = dpdu - outward * dot(outward, dpdu);
+                if tangent.length_squared() < 1e-16 {
+                    tangent = cross(dpdv, outward);
+                }
+                if tangent.length_squared() < 1e-16 {
+                    return None;
+                }
+                let tangent = tangent.unit_vector();
+                let bitangent = cross(outward, tangent);
+                let c = normal_map.value(u, v, p) * 2.0 - Color::new(1.0, 1.0, 1.0);
+                tangent * c.x() + bitangent * c.y() + outward * c.z()
+            }
+            NormalPerturbation::Bump { height, scale } => {
+                // 位移后的表面 p + d(u,v)·n 的偏导数，忽略法向量自身随u、v的变化
+                let displacement = |u: f64, v: f64, p: Vec3| height.value(u, v, p).luminance() * scale;
+                let d = displacement(u, v, p);
+                let d_du = (displacement(u + BUMP_DELTA, v, p + dpdu * BUMP_DELTA) - d) / BUMP_DELTA;
+                let d_dv = (displacement(u, v + BUMP_DELTA, p + dpdv * BUMP_DELTA) - d) / BUMP_DELTA;
+                let normal = cross(dpdu + outward * d_du, dpdv + outward * d_dv);
+                if dot(normal, outward) < 0.0 { -normal } else { normal }
+            }
+        };
+        if normal.length_squared() < 1e-16 {
+            return None;
+        }
+        Some(normal.unit_vector())
+    }
+
+    /*
+    把法向量替换为着色法向量的交点记录。与入射方向同侧的朝向保持不变，
+    扰动后翻到几何表面另一侧时退回几何法向量
+     */
+    fn shading_record(&self, hit_record: &HitRecord) -> HitRecord {
+        let geometric = hit_record.get_normal();
+        let outward = if hit_record.get_front_face() { geometric } else { -geometric };
+        let shading = match self.perturbed_normal(hit_record, outward) {
+            Some(n) => if hit_record.get_front_face() { n } else { -n },
+            None => geometric
+        };
+        if dot(shading, geometric) > 0.0 {
+            hit_record.with_normal(shading)
+        } else {
+            hit_record.with_normal(geometric)
         }
+    }
+}
 
-        // 折射
-        let refracted = Vec3::refract(unit_direction, hit_record.get_normal(), etai_over_etat);
-        scattered.copy(Ray::new(hit_record.get_p(), refracted, r_in.get_time()));
+impl Material for NormalMappedMaterial {
+    fn scatter(&self, r_in: &Ray, hit_record: &HitRecord, attenuation: &mut Vec3, scattered: &mut Ray, sampler: &mut dyn Sampler) -> bool {
+        let shading = self.shading_record(hit_record);
+        if !self.base.scatter(r_in, &shading, attenuation, scattered, sampler) {
+            return false;
+        }
+        // 按着色法向量散射的方向可能穿到几何表面的另一侧(反射光线钻入表面、折射光线留在外面)，
+        // 此时按几何表面做镜像，使光线回到着色法向量所在的一侧
+        let d = scattered.direction();
+        let geometric = hit_record.get_normal();
+        if dot(d, shading.get_normal()) * dot(d, geometric) < 0.0 {
+            let mut clamped = Ray::new(scattered.original(), d - geometric * (2.0 * dot(d, geometric)), scattered.get_time());
+            clamped.set_wavelengths(scattered.get_wavelengths());
+            clamped.set_spectral_attenuation(scattered.has_spectral_attenuation());
+            clamped.set_specular(scattered.is_specular());
+            scattered.copy(clamped);
+        }
         true
     }
-}
\ No newline at end of file
+
+    fn emitted(&self, hit_record: &HitRecord) -> Color {
+        self.base.emitted(hit_record)
+    }
+
+    fn eval(&self, r_in: &Ray, hit_record: &HitRecord, wi: Vec3) -> Color {
+        self.base.eval(r_in, &self.shading_record(hit_record), wi)
+    }
+
+    fn pdf(&self, r_in: &Ray, hit_record: &HitRecord, wi: Vec3) -> f64 {
+        self.base.pdf(r_in, &self.shading_record(hit_record), wi)
+    }
+
+    fn ray_type(&self, r_in: &Ray, hit_record: &HitRecord, scattered: &Ray) -> RayType {
+        self.base.ray_type(r_in, &self.shading_record(hit_record), scattered)
+    }
+
+    fn albedo(&self, hit_record: &HitRecord) -> Color {
+        self.base.albedo(hit_record)
+    }
+
+    fn alpha_test(&self, r_in: &Ray, hit_record: &HitRecord) -> bool {
+        self.base.alpha_test(r_in, hit_record)
+    }
+}
+
+/*
+透明遮罩的判定方式
+ */
+#[derive(Debug, Copy, Clone)]
+pub enum AlphaMode {
+    // 不透明度低于阈值的部分完全镂空，适合边缘清晰的树叶、贴花
+    Threshold(f64),
+    // 按不透明度的概率保留交点，多个样本平均后呈现半透明
+    Stochastic
+}
+
+/*
+带透明遮罩的材质：不透明度取遮罩纹理的亮度，被遮罩跳过的交点对光线完全透明，
+其余情况交给基底材质
+ */
+pub struct AlphaMaskedMaterial {
+    base: Rc<dyn Material>,
+    alpha: Rc<dyn Texture>,
+    mode: AlphaMode
+}
+
+impl AlphaMaskedMaterial {
+    pub fn new(base: Rc<dyn Material>, alpha: Rc<dyn Texture>, mode: AlphaMode) -> AlphaMaskedMaterial {
+        AlphaMaskedMaterial {
+            base,
+            alpha,
+            mode
+        }
+    }
+}
+
+impl Material for AlphaMaskedMaterial {
+    fn scatter(&self, r_in: &Ray, hit_record: &HitRecord, attenuation: &mut Vec3, scattered: &mut Ray, sampler: &mut dyn Sampler) -> bool {
+        self.base.scatter(r_in, hit_record, attenuation, scattered, sampler)
+    }
+
+    fn emitted(&self, hit_record: &HitRecord) -> Color {
+        self.base.emitted(hit_record)
+    }
+
+    fn eval(&self, r_in: &Ray, hit_record: &HitRecord, wi: Vec3) -> Color {
+        self.base.eval(r_in, hit_record, wi)
+    }
+
+    fn pdf(&self, r_in: &Ray, hit_record: &HitRecord, wi: Vec3) -> f64 {
+        self.base.pdf(r_in, hit_record, wi)
+    }
+
+    fn ray_type(&self, r_in: &Ray, hit_record: &HitRecord, scattered: &Ray) -> RayType {
+        self.base.ray_type(r_in, hit_record, scattered)
+    }
+
+    fn albedo(&self, hit_record: &HitRecord) -> Color {
+        self.base.albedo(hit_record)
+    }
+
+    fn alpha_test(&self, r_in: &Ray, hit_record: &HitRecord) -> bool {
+        let (u, v) = hit_record.get_uv();
+        let alpha = self.alpha.value(u, v, hit_record.get_p()).luminance();
+        match self.mode {
+            AlphaMode::Threshold(threshold) => alpha >= threshold,
+            AlphaMode::Stochastic => {
+                if alpha >= 1.0 {
+                    return true;
+                }
+                if alpha <= 0.0 {
+                    return false;
+                }
+                // 随机数同时取决于交点与光线方向，不同样本穿过同一点时得到不同的判定
+                let d = r_in.direction();
+                let salt = hash(hash(hash(ALPHA_SALT, d.x().to_bits()), d.y().to_bits()), d.z().to_bits());
+                hit_hash(hit_record, salt) < alpha
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sampler::IndependentSampler;
+    use crate::spectrum::SampledWavelengths;
+    use crate::sphere::{Hittable, Sphere};
+    use crate::vec3::Point;
+
+    // 每个维度都返回同一个值的采样器，用于确定散射分支
+    struct FixedSampler(f64);
+
+    impl Sampler for FixedSampler {
+        fn start_sample(&mut self, _i: i32, _j: i32, _index: i32) {}
+
+        fn get_1d(&mut self) -> f64 {
+            self.0
+        }
+
+        fn get_2d(&mut self) -> (f64, f64) {
+            (self.0, self.0)
+        }
+    }
+
+    // 在原点以法向量+y、从外侧击中material的记录
+    fn hit_from_above(material: Rc<dyn Material>) -> HitRecord {
+        HitRecord::new(Point::new(0.0, 0.0, 0.0), Vec3::new(0.0, 1.0, 0.0), 1.0, true, material)
+    }
+
+    fn scatter(material: &Rc<dyn Material>, r_in: &Ray, u: f64) -> Option<(Color, Ray)> {
+        let rec = hit_from_above(material.clone());
+        let mut attenuation = Color::default();
+        let mut scattered = Ray::default();
+        material.scatter(r_in, &rec, &mut attenuation, &mut scattered, &mut FixedSampler(u))
+            .then_some((attenuation, scattered))
+    }
+
+    /*
+    白炉测试：单位球放在各方向亮度为1的环境中，从外部射向球面的路径逃逸时的通量即为亮度。
+    不吸收能量的材质每条路径都应得到1，任何材质都不应大于1
+     */
+    fn furnace(material: Rc<dyn Material>) -> Vec<f64> {
+        let sphere = Sphere::new(Point::default(), 1.0, material);
+        let mut sampler = IndependentSampler::new();
+        (0..2000).map(|k| {
+            let offset = (f64::from(k) / 2000.0).sqrt() * 0.99;
+            let mut ray = Ray::new(Point::new(offset, 0.0, 5.0), Vec3::new(0.0, 0.0, -1.0), 0.0);
+            let mut throughput = Color::new(1.0, 1.0, 1.0);
+            for _ in 0..32 {
+                let mut rec = HitRecord::new_default();
+                if !sphere.hit(&ray, 0.001, f64::INFINITY, &mut rec) {
+                    return throughput.luminance();
+                }
+                let mut attenuation = Color::default();
+                let mut scattered = Ray::default();
+                if !rec.get_material().scatter(&ray, &rec, &mut attenuation, &mut scattered, &mut sampler) {
+                    return 0.0;
+                }
+                throughput *= attenuation;
+                ray = scattered;
+            }
+            0.0
+        }).collect()
+    }
+
+    fn white_lambertian() -> Rc<dyn Material> {
+        Rc::new(Lambertian::new(Color::new(1.0, 1.0, 1.0)))
+    }
+
+    #[test]
+    fn mix_passes_white_furnace() {
+        let mirror: Rc<dyn Material> = Rc::new(Metal::new(Color::new(1.0, 1.0, 1.0), 0.0));
+        let mix = Rc::new(MixMaterial::new_constant(white_lambertian(), mirror, 0.3));
+        assert!(furnace(mix).iter().all(|&l| (l - 1.0).abs() < 1e-9));
+    }
+
+    #[test]
+    fn coated_does_not_create_energy() {
+        let coated = Rc::new(CoatedMaterial::new(white_lambertian(), 1.5));
+        let result = furnace(coated);
+        assert!(result.iter().all(|&l| l <= 1.0 + 1e-9));
+        // 只有穿出涂层时的菲涅尔反射损失
+        let mean = result.iter().sum::<f64>() / result.len() as f64;
+        assert!(mean > 0.8 && mean < 1.0, "mean {}", mean);
+    }
+
+    #[test]
+    fn mix_selects_by_sample_and_blends_eval() {
+        let red: Rc<dyn Material> = Rc::new(Lambertian::new(Color::new(1.0, 0.0, 0.0)));
+        let blue: Rc<dyn Material> = Rc::new(Lambertian::new(Color::new(0.0, 0.0, 1.0)));
+        let mix: Rc<dyn Material> = Rc::new(MixMaterial::new_constant(red, blue, 0.25));
+        let r_in = Ray::new(Point::new(0.0, 1.0, 0.0), Vec3::new(0.0, -1.0, 0.0), 0.0);
+        assert_eq!(scatter(&mix, &r_in, 0.1).unwrap().0, Color::new(0.0, 0.0, 1.0));
+        assert_eq!(scatter(&mix, &r_in, 0.5).unwrap().0, Color::new(1.0, 0.0, 0.0));
+
+        let rec = hit_from_above(mix.clone());
+        let wi = Vec3::new(0.0, 1.0, 0.0);
+        let f = mix.eval(&r_in, &rec, wi);
+        assert!((f.x() - 0.75 / PI).abs() < 1e-12 && (f.z() - 0.25 / PI).abs() < 1e-12);
+        assert!((mix.pdf(&r_in, &rec, wi) - 1.0 / PI).abs() < 1e-12);
+    }
+
+    #[test]
+    fn layered_materials_flag_specular_lobes() {
+        let mirror: Rc<dyn Material> = Rc::new(Metal::new(Color::new(1.0, 1.0, 1.0), 0.0));
+        let mix: Rc<dyn Material> = Rc::new(MixMaterial::new_constant(white_lambertian(), mirror, 0.5));
+        let r_in = Ray::new(Point::new(0.0, 1.0, 0.0), Vec3::new(0.3, -1.0, 0.0), 0.0);
+        let rec = hit_from_above(mix.clone());
+        // 混合后的pdf在镜面方向上仍大于0，只能由散射光线区分所选的分支
+        let (_, reflected) = scatter(&mix, &r_in, 0.1).unwrap();
+        assert!(reflected.is_specular());
+        assert!(mix.pdf(&r_in, &rec, reflected.direction()) > 0.0);
+        assert_eq!(mix.ray_type(&r_in, &rec, &reflected), RayType::Specular);
+        let (_, diffuse) = scatter(&mix, &r_in, 0.9).unwrap();
+        assert!(!diffuse.is_specular());
+        assert_eq!(mix.ray_type(&r_in, &rec, &diffuse), RayType::Diffuse);
+
+        let coated: Rc<dyn Material> = Rc::new(CoatedMaterial::new(white_lambertian(), 1.5));
+        let rec = hit_from_above(coated.clone());
+        let (_, coat) = scatter(&coated, &r_in, 0.99).unwrap();
+        assert!(coat.is_specular());
+        assert_eq!(coated.ray_type(&r_in, &rec, &coat), RayType::Specular);
+        assert!(!scatter(&coated, &r_in, 0.5).unwrap().1.is_specular());
+    }
+
+    // 以+x为dpdu的交点记录
+    fn tangent_hit(material: Rc<dyn Material>) -> HitRecord {
+        let mut rec = hit_from_above(material);
+        rec.set_tangents(Vec3::new(1.0, 0.0, 0.0), Vec3::new(0.0, 0.0, -1.0));
+        rec
+    }
+
+    #[test]
+    fn normal_map_tilts_shading_normal() {
+        // 切线空间(0.6, 0, 0.8)：向dpdu方向倾斜
+        let base = Rc::new(Lambertian::new(Color::new(1.0, 1.0, 1.0)));
+        let mapped: Rc<dyn Material> = Rc::new(NormalMappedMaterial::new_normal_map(
+            base, Rc::new(ConstantTexture::new(Color::new(0.8, 0.5, 0.9)))));
+        let rec = tangent_hit(mapped.clone());
+        let r_in = Ray::new(Point::new(0.0, 1.0, 0.0), Vec3::new(0.0, -1.0, 0.0), 0.0);
+        let toward_tilt = mapped.eval(&r_in, &rec, Vec3::new(0.6, 0.8, 0.0));
+        let away = mapped.eval(&r_in, &rec, Vec3::new(-0.6, 0.8, 0.0));
+        assert!((toward_tilt.x() - 1.0 / PI).abs() < 1e-9);
+        assert!(away.x() < toward_tilt.x());
+    }
+
+    #[test]
+    fn bump_map_follows_height_gradient() {
+        // 沿u线性升高的高度场：法向量向-u方向倾斜
+        struct Ramp;
+        impl Texture for Ramp {
+            fn value(&self, u: f64, _v: f64, _p: Vec3) -> Color {
+                Color::new(u, u, u)
+            }
+        }
+        let base = Rc::new(Lambertian::new(Color::new(1.0, 1.0, 1.0)));
+        let bumped = NormalMappedMaterial::new_bump(base, Rc::new(Ramp), 1.0);
+        let rec = tangent_hit(Rc::new(Lambertian::new(Color::default())));
+        let normal = bumped.shading_record(&rec).get_normal();
+        assert!((normal - Vec3::new(-1.0, 1.0, 0.0).unit_vector()).length() < 1e-6);
+    }
+
+    #[test]
+    fn perturbed_reflection_stays_above_surface() {
+        // 几乎沿切线方向的着色法向量把掠射的镜面反射翻到了几何表面之下
+        let mirror = Rc::new(Metal::new(Color::new(1.0, 1.0, 1.0), 0.0));
+        let mapped: Rc<dyn Material> = Rc::new(NormalMappedMaterial::new_normal_map(
+            mirror, Rc::new(ConstantTexture::new(Color::new(1.0, 0.5, 0.55)))));
+        let rec = tangent_hit(mapped.clone());
+        let r_in = Ray::new(Point::new(0.6, 0.8, 0.0), Vec3::new(-0.6, -0.8, 0.0), 0.5);
+        let mut attenuation = Color::default();
+        let mut scattered = Ray::default();
+        assert!(mapped.scatter(&r_in, &rec, &mut attenuation, &mut scattered, &mut FixedSampler(0.5)));
+        assert!(scattered.direction().y() > 0.0);
+        assert_eq!(scattered.get_time(), 0.5);
+    }
+
+    #[test]
+    fn thin_film_uses_path_wavelengths() {
+        let film = ThinFilm::new(300.0, 1.4);
+        let mut metal = Metal::new(Color::new(0.9, 0.9, 0.9), 0.0);
+        metal.set_thin_film(Some(film));
+        let metal: Rc<dyn Material> = Rc::new(metal);
+        let mut r_in = Ray::new(Point::new(0.0, 1.0, 0.0), Vec3::new(0.0, -1.0, 0.0), 0.0);
+
+        let (rgb, scattered) = scatter(&metal, &r_in, 0.5).unwrap();
+        assert!(!scattered.has_spectral_attenuation());
+        assert_eq!(rgb, film.reflectance(1.0, 1.0, Substrate::Reflector(Color::new(0.9, 0.9, 0.9))));
+
+        let wavelengths = SampledWavelengths::sample_visible(0.3);
+        r_in.set_wavelengths(Some(wavelengths));
+        let (spectral, scattered) = scatter(&metal, &r_in, 0.5).unwrap();
+        assert!(scattered.has_spectral_attenuation());
+        let substrate = Substrate::Reflector(wavelengths.upsample(Color::new(0.9, 0.9, 0.9)));
+        assert_eq!(spectral, film.reflectance_at(1.0, 1.0, substrate, wavelengths.get_lambda()));
+    }
+
+    #[test]
+    fn dielectric_chooses_reflection_or_refraction_once() {
+        let glass: Rc<dyn Material> = Rc::new(Dielectric::new(1.5));
+        let r_in = Ray::new(Point::new(0.0, 1.0, 0.0), Vec3::new(0.6, -0.8, 0.0), 0.3);
+        let reflect_prob = Vec3::schlick(0.8, 1.0 / 1.5);
+
+        let (_, reflected) = scatter(&glass, &r_in, reflect_prob * 0.5).unwrap();
+        assert!(reflected.direction().y() > 0.0);
+        assert_eq!(reflected.get_time(), 0.3);
+
+        let (_, refracted) = scatter(&glass, &r_in, (reflect_prob + 1.0) * 0.5).unwrap();
+        assert!(refracted.direction().y() < 0.0);
+        // 折射光线向法线偏折
+        assert!(refracted.direction().unit_vector().x() < 0.6);
+    }
+
+    #[test]
+    fn dielectric_reflects_totally_inside() {
+        let glass: Rc<dyn Material> = Rc::new(Dielectric::new(1.5));
+        let rec = HitRecord::new(Point::default(), Vec3::new(0.0, 1.0, 0.0), 1.0, false, glass.clone());
+        let r_in = Ray::new(Point::new(0.0, 1.0, 0.0), Vec3::new(0.9, -0.3, 0.0), 0.0);
+        let mut attenuation = Color::default();
+        let mut scattered = Ray::default();
+        assert!(glass.scatter(&r_in, &rec, &mut attenuation, &mut scattered, &mut FixedSampler(0.99)));
+        assert!(scattered.direction().y() > 0.0);
+    }
+}
@@ -0,0 +1,355 @@
+use rand::{Rng, SeedableRng};
+use rand::rngs::StdRng;
+use crate::camera::Camera;
+use crate::common::{hash, INFINITY, PI, seed_pixel_sample};
+use crate::distribution::Distribution1D;
+use crate::film::{Aov, Film, SampleAovs};
+use crate::integrator::{Integrator, PathIntegrator, record_first_hit};
+use crate::ray::Ray;
+use crate::sampler::{IndependentSampler, Sampler};
+use crate::scene::Scene;
+use crate::sphere::HitRecord;
+use crate::vec3::Color;
+
+// 自举样本与马尔可夫链的随机数流，与像素样本、场景生成的随机数互不相关
+const BOOTSTRAP_SEED_STREAM: u64 = u64::MAX - 2;
+const CHAIN_SEED_STREAM: u64 = u64::MAX - 3;
+
+/*
+Metropolis光传输参数
+ */
+#[derive(Debug, Copy, Clone)]
+pub struct MltSettings {
+    // 估计图像总亮度并选择马尔可夫链初始状态的独立样本数
+    pub bootstrap_samples: usize,
+    // 马尔可夫链数，总变异次数在各链之间平均分配
+    pub chains: usize,
+    // 大步变异(整个样本向量重新随机生成)的概率
+    pub large_step_probability: f64,
+    // 小步变异的正态扰动标准差
+    pub sigma: f64
+}
+
+impl Default for MltSettings {
+    fn default() -> Self {
+        MltSettings {
+            bootstrap_samples: 100000,
+            chains: 1000,
+            large_step_probability: 0.3,
+            sigma: 0.01
+        }
+    }
+}
+
+/*
+主样本向量的一维，记录最后一次修改所在的迭代，被拒绝时恢复备份
+ */
+#[derive(Default, Copy, Clone)]
+struct PrimarySample {
+    value: f64,
+    last_modification: i64,
+    value_backup: f64,
+    modify_backup: i64
+}
+
+/*
+主样本空间(primary sample space)的采样器：积分器按顺序取的随机数构成一个样本向量，
+每次迭代对其做大步或小步变异。各维度在被用到时才按错过的迭代次数补做变异，
+因此路径长度变化时不需要预先知道样本向量的维数
+ */
+pub struct MltSampler {
+    rng: StdRng,
+    sigma: f64,
+    large_step_probability: f64,
+    x: Vec<PrimarySample>,
+    current_iteration: i64,
+    large_step: bool,
+    last_large_step_iteration: i64,
+    sample_index: usize
+}
+
+impl MltSampler {
+    // 同一种子与stream得到相同的初始样本向量，用于重现自举阶段选中的状态
+    pub fn new(seed: u64, stream: u64, sigma: f64, large_step_probability: f64) -> MltSampler {
+        MltSampler {
+            rng: StdRng::seed_from_u64(hash(seed, stream)),
+            sigma,
+            large_step_probability,
+            x: Vec::new(),
+            current_iteration: 0,
+            // 初始状态相当于一次大步变异，所有维度都是独立的均匀随机数
+            large_step: true,
+            last_large_step_iteration: 0,
+            sample_index: 0
+        }
+    }
+
+    // 开始一次变异
+    pub fn start_iteration(&mut self) {
+        self.current_iteration += 1;
+        self.large_step = self.rng.gen_range(0.0..1.0) < self.large_step_probability;
+        self.sample_index = 0;
+    }
+
+    pub fn accept(&mut self) {
+        if self.large_step {
+            self.last_large_step_iteration = self.current_iteration;
+        }
+    }
+
+    // 恢复本次迭代修改过的维度
+    pub fn reject(&mut self) {
+        for sample in self.x.iter_mut() {
+            if sample.last_modification == self.current_iteration {
+                sample.value = sample.value_backup;
+                sample.last_modification = sample.modify_backup;
+            }
+        }
+        self.current_iteration -= 1;
+    }
+
+    fn ensure_ready(&mut self, index: usize) {
+        if index >= self.x.len() {
+            self.x.resize(index + 1, PrimarySample::default());
+        }
+        let sample = &mut self.x[index];
+        // 上次大步变异之后未被用到的维度先补上那次大步变异
+        if sample.last_modification < self.last_large_step_iteration {
+            sample.value = self.rng.gen_range(0.0..1.0);
+            sample.last_modification = self.last_large_step_iteration;
+        }
+
+        sample.value_backup = sample.value;
+        sample.modify_backup = sample.last_modification;
+        if self.large_step {
+            sample.value = self.rng.gen_range(0.0..1.0);
+        } else {
+            // 错过的n次小步变异合并为一次标准差为sigma*sqrt(n)的正态扰动(Box-Muller)
+            let small_steps = (self.current_iteration - sample.last_modification) as f64;
+            let u1: f64 = 1.0 - self.rng.gen_range(0.0..1.0);
+            let u2: f64 = self.rng.gen_range(0.0..1.0);
+            let normal = (-2.0 * u1.ln()).sqrt() * (2.0 * PI * u2).cos();
+            sample.value += normal * self.sigma * small_steps.sqrt();
+            sample.value -= sample.value.floor();
+        }
+        sample.last_modification = self.current_iteration;
+    }
+}
+
+impl Sampler for MltSampler {
+    // 样本向量由变异决定，与像素无关
+    fn start_sample(&mut self, _i: i32, _j: i32, _index: i32) {}
+
+    fn get_1d(&mut self) -> f64 {
+        let index = self.sample_index;
+        self.sample_index += 1;
+        self.ensure_ready(index);
+        self.x[index].value
+    }
+
+    fn get_2d(&mut self) -> (f64, f64) {
+        (self.get_1d(), self.get_1d())
+    }
+}
+
+// 马尔可夫链的目标函数：路径贡献的亮度，非有限值视为0
+fn contribution(l: Color) -> f64 {
+    let y = l.luminance();
+    if y.is_finite() && y > 0.0 { y } else { 0.0 }
+}
+
+/*
+主样本空间Metropolis光传输(PSSMLT, Kelemen et al. 2002)：样本向量的前两维决定图像位置，
+其余交给路径追踪积分器。马尔可夫链按贡献亮度的比例在样本空间中游走，
+大步变异保证遍历整个空间，小步变异在找到的高贡献路径(例如穿过钥匙孔的光)附近探索。
+自举阶段用独立样本估计图像的平均亮度b，并按贡献选择各链的初始状态；
+每次变异把当前与提议状态按接受概率加权溅射到图像上(期望值技巧)，最后乘以b除以每像素变异数。
+变异总数为相机的每像素样本数乘以像素数
+ */
+pub struct MltIntegrator {
+    path: PathIntegrator,
+    settings: MltSettings
+}
+
+impl MltIntegrator {
+    pub fn new(path: PathIntegrator, settings: MltSettings) -> MltIntegrator {
+        MltIntegrator {
+            path,
+            settings
+        }
+    }
+
+    fn new_sampler(&self, seed: u64, index: usize) -> MltSampler {
+        MltSampler::new(seed, hash(BOOTSTRAP_SEED_STREAM, index as u64), self.settings.sigma, self.settings.large_step_probability)
+    }
+
+    // 按样本向量生成图像位置与路径，返回(辐射亮度, 连续图像坐标)
+    fn radiance(&self, camera: &Camera, scene: &Scene, sampler: &mut MltSampler, (width, height): (i32, i32)) -> (Color, (f64, f64)) {
+        let (u, v) = sampler.get_2d();
+        let (fx, fy) = (u * f64::from(width), v * f64::from(height));
+        let (x, y) = ((fx as i32).min(width - 1), (fy as i32).min(height - 1));
+        let offset = (fx - f64::from(x) - 0.5, fy - f64::from(y) - 0.5);
+        let l = match camera.get_output_ray(x, y, offset, sampler) {
+            Some(r) => self.path.li(&r, camera, scene, sampler, &mut SampleAovs::new()),
+            None => Color::default()
+        };
+        (l, (fx, fy))
+    }
+}
+
+impl Integrator for MltIntegrator {
+    // 单条相机光线没有马尔可夫链可用，退化为路径追踪
+    fn li(&self, r: &Ray, camera: &Camera, scene: &Scene, sampler: &mut dyn Sampler, aovs: &mut SampleAovs) -> Color {
+        self.path.li(r, camera, scene, sampler, aovs)
+    }
+
+    fn render(&self, camera: &Camera, scene: &Scene, aovs: &[Aov]) -> Option<Film> {
+        let mut film = camera.new_film(aovs);
+        let size = (film.get_width(), film.get_height());
+        let exposure = camera.get_exposure();
+
+        // 几何输出变量取自像素中心的相机光线，光照相关的输出变量不记录
+        if !aovs.is_empty() {
+            let mut sampler = IndependentSampler::new();
+            for y in 0..size.1 {
+                for x in 0..size.0 {
+                    seed_pixel_sample(camera.get_seed(), x, y, 0);
+                    let mut sample_aovs = SampleAovs::new();
+                    if let Some(r) = camera.get_output_ray(x, y, (0.0, 0.0), &mut sampler) {
+                        let mut rec = HitRecord::new_default();
+                        if scene.get_world().hit(&r, 0.001, INFINITY, &mut rec) {
+                            record_first_hit(&mut sample_aovs, &r, &rec);
+                        }
+                    }
+                    film.add_aovs(x, y, &sample_aovs);
+                }
+            }
+        }
+
+        println!("Bootstrapping: {} samples", self.settings.bootstrap_samples);
+        let bootstrap_samples = self.settings.bootstrap_samples.max(1);
+        let weights: Vec<f64> = (0..bootstrap_samples).map(|index| {
+            contribution(self.radiance(camera, scene, &mut self.new_sampler(camera.get_seed(), index), size).0)
+        }).collect();
+        let bootstrap = Distribution1D::new(weights);
+        // 图像平均亮度
+        let b = bootstrap.get_integral();
+        if b == 0.0 {
+            return Some(film);
+        }
+
+        let mutations_per_pixel = camera.get_samples_per_pixel().max(1);
+        let total_mutations = i64::from(size.0 * size.1) * i64::from(mutations_per_pixel);
+        let chains = self.settings.chains.max(1) as i64;
+        for chain in 0..chains {
+            let mut rng = StdRng::seed_from_u64(hash(camera.get_seed(), hash(CHAIN_SEED_STREAM, chain as u64)));
+            let (index, _) = bootstrap.sample_discrete(rng.gen_range(0.0..1.0));
+            let mut sampler = self.new_sampler(camera.get_seed(), index);
+            let (mut current, mut current_raster) = self.radiance(camera, scene, &mut sampler, size);
+
+            let mutations = total_mutations / chains + i64::from(chain < total_mutations % chains);
+            for _ in 0..mutations {
+                sampler.start_iteration();
+                let (proposed, proposed_raster) = self.radiance(camera, scene, &mut sampler, size);
+                let (current_y, proposed_y) = (contribution(current), contribution(proposed));
+                let accept = if current_y > 0.0 { (proposed_y / current_y).min(1.0) } else { 1.0 };
+
+                // 期望值技巧：两个状态都按被保留的概率计入图像，拒绝的提议同样有贡献
+                if accept > 0.0 && proposed_y > 0.0 {
+                    film.add_splat(proposed_raster.0, proposed_raster.1, proposed * (exposure * accept / proposed_y));
+                }
+                if accept < 1.0 && current_y > 0.0 {
+                    film.add_splat(current_raster.0, current_raster.1, current * (exposure * (1.0 - accept) / current_y));
+                }
+
+                if rng.gen_range(0.0..1.0) < accept {
+                    current = proposed;
+                    current_raster = proposed_raster;
+                    sampler.accept();
+                } else {
+                    sampler.reject();
+                }
+            }
+        }
+
+        film.set_splat_scale(b / f64::from(mutations_per_pixel));
+        Some(film)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::rc::Rc;
+    use super::*;
+    use crate::depth::DepthLimits;
+    use crate::material::Lambertian;
+    use crate::sphere::{HittableList, Sphere};
+    use crate::vec3::{Point, Vec3};
+
+    // 白色背景下的灰色球，画面中央为球，四周为背景
+    fn grey_sphere() -> Scene {
+        let mut world = HittableList::new();
+        world.add(Box::new(Sphere::new(Point::default(), 1.0, Rc::new(Lambertian::new(Color::new(0.5, 0.5, 0.5))))));
+        let mut scene = Scene::new(Box::new(world));
+        scene.set_background(Some(Color::new(1.0, 1.0, 1.0)));
+        scene
+    }
+
+    fn camera(spp: i32) -> Camera {
+        Camera::new(8, 40.0, 1.0, spp, Point::new(0.0, 0.0, 4.0), Point::default(),
+                    Vec3::new(0.0, 1.0, 0.0), 4.0, 0.0)
+    }
+
+    // (整幅图像的平均亮度, 中央4×4像素的平均亮度)
+    fn means(film: &Film) -> (f64, f64) {
+        let mut total = 0.0;
+        let mut center = 0.0;
+        for y in 0..film.get_height() {
+            for x in 0..film.get_width() {
+                let y_value = film.get_pixel(x, y).luminance();
+                total += y_value;
+                if (2..6).contains(&x) && (2..6).contains(&y) {
+                    center += y_value;
+                }
+            }
+        }
+        (total / f64::from(film.get_width() * film.get_height()), center / 16.0)
+    }
+
+    #[test]
+    fn mlt_matches_path_tracing() {
+        let scene = grey_sphere();
+        let limits = DepthLimits::default();
+        let mut reference = camera(256);
+        reference.set_integrator(Box::new(PathIntegrator::new(limits, false)));
+        let expected = means(&reference.render_film(&scene, &[]));
+
+        let settings = MltSettings {
+            bootstrap_samples: 4096,
+            chains: 64,
+            ..MltSettings::default()
+        };
+        let mut metropolis = camera(256);
+        metropolis.set_integrator(Box::new(MltIntegrator::new(PathIntegrator::new(limits, false), settings)));
+        let actual = means(&metropolis.render_film(&scene, &[]));
+
+        assert!((actual.0 - expected.0).abs() < 0.03 * expected.0, "image mean {} vs {}", actual.0, expected.0);
+        assert!((actual.1 - expected.1).abs() < 0.1 * expected.1, "sphere mean {} vs {}", actual.1, expected.1);
+        // 球比背景暗，马尔可夫链确实按亮度分布到了画面各处
+        assert!(actual.1 < 0.8 * actual.0);
+    }
+
+    #[test]
+    fn rejected_mutation_restores_sample_vector() {
+        let mut sampler = MltSampler::new(0, 7, 0.01, 0.0);
+        let initial: Vec<f64> = (0..4).map(|_| sampler.get_1d()).collect();
+        sampler.start_iteration();
+        let mutated: Vec<f64> = (0..4).map(|_| sampler.get_1d()).collect();
+        assert!(initial.iter().zip(&mutated).all(|(a, b)| a != b));
+        sampler.reject();
+        sampler.start_iteration();
+        sampler.reject();
+        // 拒绝后样本向量回到变异前的状态
+        assert!(sampler.x.iter().zip(&initial).all(|(s, &v)| s.value == v));
+    }
+}
@@ -0,0 +1,529 @@
+use std::ops::{RangeBounds, RangeInclusive};
+use std::str::FromStr;
+use crate::adaptive::AdaptiveSampling;
+use crate::aperture::ApertureShape;
+use crate::common::INFINITY;
+use crate::denoise::Denoiser;
+use crate::depth::DepthLimits;
+use crate::film::Aov;
+use crate::image::{ASPECT_RATIO, SceneType};
+use crate::filter::FilterType;
+use crate::integrator::IntegratorType;
+use crate::mlt::MltSettings;
+use crate::physical_camera::PhysicalCamera;
+use crate::projection::Projection;
+use crate::sampler::SamplerType;
+use crate::stereo::StereoLayout;
+
+// 浮点参数的取值范围，均不接受inf与NaN
+const FINITE: RangeInclusive<f64> = f64::MIN..=f64::MAX;
+const POSITIVE: RangeInclusive<f64> = f64::MIN_POSITIVE..=f64::MAX;
+const NON_NEGATIVE: RangeInclusive<f64> = 0.0..=f64::MAX;
+
+/*
+渲染参数，可由命令行解析得到：
+    rayce_tracing <width> [--scene random|motion|lights|materials] [--ies <file.ies>] [--shutter-interval <open>,<close>]
+                          [--projection perspective|orthographic|fisheye|equirectangular|cylindrical]
+                          [--stereo top-bottom|side-by-side] [--interocular <d>] [--convergence <d>]
+                          [--autofocus <x>,<y>]
+                          [--aperture circle|<blades>[,<rotation>]|<mask.ppm>] [--cat-eye <strength>]
+                          [--lens double-gauss|<lens.dat>]
+                          [--focal-length <mm>] [--f-stop <N>] [--sensor-width <mm>] [--iso <S>] [--shutter <s>]
+                          [--environment <file.hdr|file.pfm>] [--env-rotation <deg>] [--env-intensity <k>]
+                          [--sky <elevation>,<azimuth>] [--turbidity <T>]
+                          [--sampler independent|stratified|halton|sobol] [--seed <n>]
+                          [--adaptive <threshold>] [--min-samples <n>] [--max-samples <n>] [--sample-map <file.ppm>]
+                          [--filter box|tent|gaussian|mitchell|lanczos] [--filter-radius <px>]
+                          [--aov all|<name>,<name>...] [--aov-prefix <prefix>] [--exr <file.exr>]
+                          [--spp <n>] [--denoise] [--denoise-iterations <n>]
+                          [--max-depth <n>] [--diffuse-depth <n>] [--specular-depth <n>]
+                          [--transmission-depth <n>] [--volume-depth <n>] [--roulette-depth <n>]
+                          [--integrator naive|path|bdpt|sppm|mlt|ao|direct|normals|uv|depth] [--ao-distance <d>]
+                          [--photons <n>] [--photon-radius <r>]
+                          [--mlt-bootstrap <n>] [--mlt-chains <n>] [--mlt-large-step <p>] [--mlt-sigma <s>]
+                          [--spectral]
+ */
+pub struct RenderOptions {
+    pub width: i32,
+    pub scene: SceneType,
+    // lights场景中点光源与聚光灯的IES配光文件
+    pub ies: Option<String>,
+    // 相机快门的开启与关闭时刻，None时为[0,1](物理相机为快门时间)
+    pub shutter_interval: Option<(f64, f64)>,
+    pub projection: Projection,
+    // 立体渲染拼接方式，None为单目
+    pub stereo: Option<StereoLayout>,
+    pub interocular: f64,
+    // 会聚距离，None时使用相机焦距
+    pub convergence: Option<f64>,
+    // 自动对焦的像素坐标
+    pub autofocus: Option<(i32, i32)>,
+    // 光圈形状(散景形状)，使用真实镜头时为光阑形状
+    pub aperture: ApertureShape,
+    // 猫眼渐晕强度，0为关闭
+    pub cat_eye: f64,
+    // 真实镜头：内置的double-gauss或镜头数据文件
+    pub lens: Option<String>,
+    // 物理相机参数，任一物理参数出现时启用；给出--iso或--shutter时才按测光曝光
+    pub physical: Option<PhysicalCamera>,
+    // HDRI环境光贴图
+    pub environment: Option<String>,
+    pub env_rotation: f64,
+    pub env_intensity: f64,
+    // 物理天空的太阳高度角与方位角(角度制)
+    pub sky: Option<(f64, f64)>,
+    pub turbidity: f64,
+    pub sampler: SamplerType,
+    // 渲染种子，相同种子与参数的渲染结果逐位一致
+    pub seed: u64,
+    // 自适应采样参数，任一自适应参数出现时启用
+    pub adaptive: Option<AdaptiveSampling>,
+    pub filter: FilterType,
+    // 滤波器半径(像素)，None时使用该滤波器的默认半径
+    pub filter_radius: Option<f64>,
+    // 需要记录的输出变量
+    pub aovs: Vec<Aov>,
+    // 输出变量分别写为<prefix>_<名称>.pfm
+    pub aov_prefix: Option<String>,
+    // 主图像与输出变量写入多层EXR
+    pub exr: Option<String>,
+    pub samples_per_pixel: i32,
+    // 以反照率与法向量输出变量为引导的降噪
+    pub denoise: Option<Denoiser>,
+    pub depth_limits: DepthLimits,
+    pub integrator: IntegratorType,
+    // 环境光遮蔽光线的最大长度
+    pub ao_distance: f64,
+    // 光子映射每次迭代发射的光子数，None时与像素数相同
+    pub photons: Option<usize>,
+    // 光子映射的初始搜索半径
+    pub photon_radius: f64,
+    pub mlt: MltSettings,
+    // 光谱渲染(主波长采样与色散)，只能用于path、naive与mlt积分器
+    pub spectral: bool
+}
+
+impl RenderOptions {
+    pub fn new(width: i32) -> RenderOptions {
+        RenderOptions {
+            width,
+            scene: SceneType::Random,
+            ies: None,
+            shutter_interval: None,
+            projection: Projection::Perspective,
+            stereo: None,
+            interocular: 0.065,
+            convergence: None,
+            autofocus: None,
+            aperture: ApertureShape::Circle,
+            cat_eye: 0.0,
+            lens: None,
+            physical: None,
+            environment: None,
+            env_rotation: 0.0,
+            env_intensity: 1.0,
+            sky: None,
+            turbidity: 3.0,
+            sampler: SamplerType::Independent,
+            seed: 0,
+            adaptive: None,
+            filter: FilterType::Box,
+            filter_radius: None,
+            aovs: Vec::new(),
+            aov_prefix: None,
+            exr: None,
+            samples_per_pixel: 100,
+            denoise: None,
+            depth_limits: DepthLimits::default(),
+            integrator: IntegratorType::Naive,
+            ao_distance: INFINITY,
+            photons: None,
+            photon_radius: 0.1,
+            mlt: MltSettings::default(),
+            spectral: false
+        }
+    }
+
+    pub fn from_args(args: &[String]) -> Result<RenderOptions, String> {
+        let mut options = RenderOptions::new(800);
+        let mut iter = args.iter();
+        while let Some(arg) = iter.next() {
+            match arg.as_str() {
+                "--scene" => {
+                    let name = iter.next().ok_or("--scene requires a value")?;
+                    options.scene = SceneType::from_name(name)
+                        .ok_or(format!("Unknown scene: {}", name))?;
+                }
+                "--ies" => {
+                    let path = iter.next().ok_or("--ies requires a value")?;
+                    options.ies = Some(path.clone());
+                }
+                "--shutter-interval" => {
+                    let (open, close) = parse_pair(arg, iter.next(), FINITE)?;
+                    if open > close {
+                        return Err(format!("--shutter-interval closes before it opens: {},{}", open, close));
+                    }
+                    options.shutter_interval = Some((open, close));
+                }
+                "--projection" => {
+                    let name = iter.next().ok_or("--projection requires a value")?;
+                    options.projection = Projection::from_name(name)
+                        .ok_or(format!("Unknown projection: {}", name))?;
+                }
+                "--stereo" => {
+                    let name = iter.next().ok_or("--stereo requires a value")?;
+                    options.stereo = Some(StereoLayout::from_name(name)
+                        .ok_or(format!("Unknown stereo layout: {}", name))?);
+                }
+                "--interocular" => {
+                    options.interocular = parse_value(arg, iter.next(), NON_NEGATIVE)?;
+                }
+                "--convergence" => {
+                    options.convergence = Some(parse_value(arg, iter.next(), POSITIVE)?);
+                }
+                "--autofocus" => {
+                    let value = iter.next().ok_or("--autofocus requires a value")?;
+                    options.autofocus = Some(parse_pixel(value)?);
+                }
+                "--aperture" => {
+                    let name = iter.next().ok_or("--aperture requires a value")?;
+                    options.aperture = ApertureShape::from_name(name)
+                        .ok_or(format!("Unknown aperture: {}", name))?;
+                }
+                "--cat-eye" => {
+                    options.cat_eye = parse_value(arg, iter.next(), NON_NEGATIVE)?;
+                }
+                "--lens" => {
+                    let name = iter.next().ok_or("--lens requires a value")?;
+                    options.lens = Some(name.clone());
+                }
+                "--focal-length" => {
+                    options.physical_mut().focal_length = parse_value(arg, iter.next(), POSITIVE)?;
+                }
+                "--f-stop" => {
+                    options.physical_mut().f_stop = parse_value(arg, iter.next(), POSITIVE)?;
+                }
+                "--sensor-width" => {
+                    options.physical_mut().sensor_width = parse_value(arg, iter.next(), POSITIVE)?;
+                }
+                "--iso" => {
+                    options.physical_mut().iso = parse_value(arg, iter.next(), POSITIVE)?;
+                    options.physical_mut().metered = true;
+                }
+                "--shutter" => {
+                    options.physical_mut().shutter_time = parse_value(arg, iter.next(), POSITIVE)?;
+                    options.physical_mut().metered = true;
+                }
+                "--environment" => {
+                    let path = iter.next().ok_or("--environment requires a value")?;
+                    options.environment = Some(path.clone());
+                }
+                "--env-rotation" => {
+                    options.env_rotation = parse_value(arg, iter.next(), FINITE)?;
+                }
+                "--env-intensity" => {
+                    options.env_intensity = parse_value(arg, iter.next(), NON_NEGATIVE)?;
+                }
+                "--sky" => {
+                    options.sky = Some(parse_pair(arg, iter.next(), FINITE)?);
+                }
+                "--turbidity" => {
+                    options.turbidity = parse_value(arg, iter.next(), 1.7..=10.0)?;
+                }
+                "--sampler" => {
+                    let name = iter.next().ok_or("--sampler requires a value")?;
+                    options.sampler = SamplerType::from_name(name)
+                        .ok_or(format!("Unknown sampler: {}", name))?;
+                }
+                "--seed" => {
+                    options.seed = parse_value(arg, iter.next(), ..)?;
+                }
+                "--adaptive" => {
+                    options.adaptive_mut().threshold = parse_value(arg, iter.next(), POSITIVE)?;
+                }
+                "--min-samples" => {
+                    options.adaptive_mut().min_samples = parse_value(arg, iter.next(), 1..)?;
+                }
+                "--max-samples" => {
+                    options.adaptive_mut().max_samples = parse_value(arg, iter.next(), 1..)?;
+                }
+                "--sample-map" => {
+                    let path = iter.next().ok_or("--sample-map requires a value")?;
+                    options.adaptive_mut().sample_map = Some(path.clone());
+                }
+                "--filter" => {
+                    let name = iter.next().ok_or("--filter requires a value")?;
+                    options.filter = FilterType::from_name(name)
+                        .ok_or(format!("Unknown filter: {}", name))?;
+                }
+                "--filter-radius" => {
+                    options.filter_radius = Some(parse_value(arg, iter.next(), POSITIVE)?);
+                }
+                "--aov" => {
+                    let value = iter.next().ok_or("--aov requires a value")?;
+                    options.aovs = parse_aovs(value)?;
+                }
+                "--aov-prefix" => {
+                    let prefix = iter.next().ok_or("--aov-prefix requires a value")?;
+                    options.aov_prefix = Some(prefix.clone());
+                }
+                "--exr" => {
+                    let path = iter.next().ok_or("--exr requires a value")?;
+                    options.exr = Some(path.clone());
+                }
+                "--spp" => {
+                    options.samples_per_pixel = parse_value(arg, iter.next(), 1..)?;
+                }
+                "--denoise" => {
+                    options.denoise_mut();
+                }
+                "--denoise-iterations" => {
+                    options.denoise_mut().iterations = parse_value(arg, iter.next(), 1..)?;
+                }
+                "--max-depth" => {
+                    options.depth_limits.max_depth = parse_value(arg, iter.next(), 1..)?;
+                }
+                "--diffuse-depth" => {
+                    options.depth_limits.diffuse = parse_value(arg, iter.next(), 0..)?;
+                }
+                "--specular-depth" => {
+                    options.depth_limits.specular = parse_value(arg, iter.next(), 0..)?;
+                }
+                "--transmission-depth" => {
+                    options.depth_limits.transmission = parse_value(arg, iter.next(), 0..)?;
+                }
+                "--volume-depth" => {
+                    options.depth_limits.volume = parse_value(arg, iter.next(), 0..)?;
+                }
+                "--roulette-depth" => {
+                    options.depth_limits.roulette_depth = parse_value(arg, iter.next(), 0..)?;
+                }
+                "--integrator" => {
+                    let name = iter.next().ok_or("--integrator requires a value")?;
+                    options.integrator = IntegratorType::from_name(name)
+                        .ok_or(format!("Unknown integrator: {}", name))?;
+                }
+                "--ao-distance" => {
+                    options.ao_distance = parse_value(arg, iter.next(), f64::MIN_POSITIVE..)?;
+                }
+                "--photons" => {
+                    options.photons = Some(parse_value(arg, iter.next(), 1..)?);
+                }
+                "--photon-radius" => {
+                    options.photon_radius = parse_value(arg, iter.next(), POSITIVE)?;
+                }
+                "--mlt-bootstrap" => {
+                    options.mlt.bootstrap_samples = parse_value(arg, iter.next(), 1..)?;
+                }
+                "--mlt-chains" => {
+                    options.mlt.chains = parse_value(arg, iter.next(), 1..)?;
+                }
+                "--mlt-large-step" => {
+                    options.mlt.large_step_probability = parse_value(arg, iter.next(), 0.0..=1.0)?;
+                }
+                "--mlt-sigma" => {
+                    options.mlt.sigma = parse_value(arg, iter.next(), POSITIVE)?;
+                }
+                "--spectral" => {
+                    options.spectral = true;
+                }
+                _ if arg.starts_with("--") => {
+                    return Err(format!("Unknown option: {}", arg));
+                }
+                _ => {
+                    options.width = arg.parse().ok().filter(|&width| width > 0)
+                        .ok_or(format!("Invalid image width: {}", arg))?;
+                }
+            }
+        }
+        if let Some(adaptive) = &options.adaptive {
+            if adaptive.min_samples > adaptive.max_samples {
+                return Err("--min-samples must not exceed --max-samples".to_string());
+            }
+        }
+        if options.ies.is_some() && options.scene != SceneType::Lights {
+            return Err("--ies requires --scene lights".to_string());
+        }
+        if let Some((x, y)) = options.autofocus {
+            if x < 0 || y < 0 || x >= options.width || y >= options.image_height() {
+                return Err(format!("--autofocus pixel {},{} is outside the {}x{} image",
+                                   x, y, options.width, options.image_height()));
+            }
+        }
+        if options.spectral && !options.integrator.supports_spectral() {
+            return Err("--spectral requires --integrator path, naive or mlt".to_string());
+        }
+        Ok(options)
+    }
+
+    // 单眼图像的高度，与相机按宽高比取整的结果一致
+    pub fn image_height(&self) -> i32 {
+        ((f64::from(self.width) / ASPECT_RATIO).floor() as i32).max(1)
+    }
+
+    fn physical_mut(&mut self) -> &mut PhysicalCamera {
+        self.physical.get_or_insert_with(PhysicalCamera::default)
+    }
+
+    fn adaptive_mut(&mut self) -> &mut AdaptiveSampling {
+        self.adaptive.get_or_insert_with(AdaptiveSampling::default)
+    }
+
+    fn denoise_mut(&mut self) -> &mut Denoiser {
+        self.denoise.get_or_insert_with(Denoiser::default)
+    }
+}
+
+// 按参数的类型解析(整数参数不接受小数)，并检查取值范围
+fn parse_value<T: FromStr + PartialOrd>(flag: &str, value: Option<&String>, range: impl RangeBounds<T>) -> Result<T, String> {
+    let value = value.ok_or(format!("{} requires a value", flag))?;
+    let parsed = value.parse().map_err(|_| format!("Invalid value for {}: {}", flag, value))?;
+    if !range.contains(&parsed) {
+        return Err(format!("Value for {} out of range: {}", flag, value));
+    }
+    Ok(parsed)
+}
+
+fn parse_pixel(value: &str) -> Result<(i32, i32), String> {
+    let invalid = || format!("Invalid pixel coordinate: {}", value);
+    let (x, y) = value.split_once(',').ok_or_else(invalid)?;
+    Ok((x.trim().parse().map_err(|_| invalid())?, y.trim().parse().map_err(|_| invalid())?))
+}
+
+// 逗号分隔的两个浮点数，分别检查取值范围
+fn parse_pair(flag: &str, value: Option<&String>, range: RangeInclusive<f64>) -> Result<(f64, f64), String> {
+    let value = value.ok_or(format!("{} requires a value", flag))?;
+    let invalid = || format!("Invalid value for {}: {}", flag, value);
+    let (a, b) = value.split_once(',').ok_or_else(invalid)?;
+    let (a, b): (f64, f64) = (a.trim().parse().map_err(|_| invalid())?, b.trim().parse().map_err(|_| invalid())?);
+    if !range.contains(&a) || !range.contains(&b) {
+        return Err(format!("Value for {} out of range: {}", flag, value));
+    }
+    Ok((a, b))
+}
+
+fn parse_aovs(value: &str) -> Result<Vec<Aov>, String> {
+    if value == "all" {
+        return Ok(Aov::ALL.to_vec());
+    }
+    value.split(',')
+        .map(|name| Aov::from_name(name.trim()).ok_or(format!("Unknown AOV: {}", name)))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &[&str]) -> Result<RenderOptions, String> {
+        let args: Vec<String> = args.iter().map(|a| a.to_string()).collect();
+        RenderOptions::from_args(&args)
+    }
+
+    #[test]
+    fn light_sampling_and_roulette_are_opt_in() {
+        let options = parse(&["200"]).unwrap();
+        assert_eq!(options.integrator, IntegratorType::Naive);
+        assert!(options.depth_limits.roulette_depth < 0);
+        let options = parse(&["200", "--integrator", "path", "--roulette-depth", "3"]).unwrap();
+        assert_eq!(options.integrator, IntegratorType::Path);
+        assert_eq!(options.depth_limits.roulette_depth, 3);
+    }
+
+    #[test]
+    fn ies_profile_needs_light_scene() {
+        assert!(parse(&["200", "--ies", "lamp.ies"]).is_err());
+        let options = parse(&["200", "--scene", "lights", "--ies", "lamp.ies"]).unwrap();
+        assert_eq!(options.ies.as_deref(), Some("lamp.ies"));
+    }
+
+    #[test]
+    fn lens_flags_keep_unit_exposure() {
+        let options = parse(&["400", "--focal-length", "85", "--f-stop", "8"]).unwrap();
+        let physical = options.physical.unwrap();
+        assert!(!physical.metered);
+        assert_eq!(physical.exposure(), 1.0);
+    }
+
+    #[test]
+    fn exposure_flags_enable_metering() {
+        let options = parse(&["400", "--f-stop", "8", "--iso", "400"]).unwrap();
+        assert!(options.physical.unwrap().metered);
+        let options = parse(&["400", "--shutter", "0.01"]).unwrap();
+        assert!(options.physical.unwrap().exposure() < 1e-2);
+    }
+    #[test]
+    fn mlt_flags_configure_settings() {
+        let options = parse(&["200", "--integrator", "mlt", "--mlt-bootstrap", "5000", "--mlt-chains", "16",
+                              "--mlt-large-step", "0.5", "--mlt-sigma", "0.02"]).unwrap();
+        assert_eq!(options.integrator, IntegratorType::Metropolis);
+        assert_eq!(options.mlt.bootstrap_samples, 5000);
+        assert_eq!(options.mlt.chains, 16);
+        assert_eq!(options.mlt.large_step_probability, 0.5);
+        assert_eq!(options.mlt.sigma, 0.02);
+    }
+    #[test]
+    fn integer_flags_reject_fractions_and_negatives() {
+        assert_eq!(parse(&["200", "--spp", "64"]).unwrap().samples_per_pixel, 64);
+        assert!(parse(&["200", "--spp", "2.5"]).is_err());
+        assert!(parse(&["200", "--spp", "0"]).is_err());
+        assert!(parse(&["200", "--spp", "-4"]).is_err());
+        assert!(parse(&["200", "--photons", "1e6"]).is_err());
+        assert!(parse(&["200", "--mlt-chains", "-1"]).is_err());
+        assert_eq!(parse(&["200", "--diffuse-depth", "0"]).unwrap().depth_limits.diffuse, 0);
+        assert!(parse(&["200", "--max-depth", "0"]).is_err());
+    }
+
+    #[test]
+    fn float_flags_are_range_checked() {
+        assert!(parse(&["200", "--f-stop", "0"]).is_err());
+        assert!(parse(&["200", "--filter-radius", "-1"]).is_err());
+        assert!(parse(&["200", "--mlt-large-step", "1.5"]).is_err());
+        assert!(parse(&["200", "--env-intensity", "NaN"]).is_err());
+        assert!(parse(&["200", "--shutter", "inf"]).is_err());
+        assert!(parse(&["200", "--min-samples", "64", "--max-samples", "32"]).is_err());
+        assert_eq!(parse(&["200", "--env-rotation", "-90"]).unwrap().env_rotation, -90.0);
+    }
+
+    #[test]
+    fn spectral_needs_path_based_integrator() {
+        assert!(parse(&["200", "--spectral"]).unwrap().spectral);
+        assert!(parse(&["200", "--integrator", "mlt", "--spectral"]).is_ok());
+        for name in ["bdpt", "sppm", "ao", "direct", "normals"] {
+            assert!(parse(&["200", "--spectral", "--integrator", name]).is_err());
+        }
+    }
+
+    #[test]
+    fn pair_flags_are_range_checked() {
+        assert_eq!(parse(&["200", "--shutter-interval", "0.2,0.6"]).unwrap().shutter_interval, Some((0.2, 0.6)));
+        assert!(parse(&["200", "--shutter-interval", "0.5,0.5"]).is_ok());
+        assert!(parse(&["200", "--shutter-interval", "0.6,0.2"]).is_err());
+        assert!(parse(&["200", "--shutter-interval", "NaN,1"]).is_err());
+        assert!(parse(&["200", "--shutter-interval", "0,inf"]).is_err());
+        assert_eq!(parse(&["200", "--sky", "30,-45"]).unwrap().sky, Some((30.0, -45.0)));
+        assert!(parse(&["200", "--sky", "inf,0"]).is_err());
+        assert!(parse(&["200", "--sky", "30,NaN"]).is_err());
+        assert!(parse(&["200", "--sky", "30"]).is_err());
+    }
+
+    #[test]
+    fn autofocus_must_be_inside_the_image() {
+        // 宽400时高为225
+        assert_eq!(parse(&["400", "--autofocus", "399,224"]).unwrap().autofocus, Some((399, 224)));
+        assert!(parse(&["400", "--autofocus", "400,100"]).is_err());
+        assert!(parse(&["400", "--autofocus", "100,225"]).is_err());
+        assert!(parse(&["400", "--autofocus", "-1,0"]).is_err());
+        // 宽度可以出现在--autofocus之后
+        assert!(parse(&["--autofocus", "700,300", "800"]).is_ok());
+        assert!(parse(&["--autofocus", "700,300", "600"]).is_err());
+    }
+
+    #[test]
+    fn unknown_flags_are_reported() {
+        assert_eq!(parse(&["200", "--sps", "64"]).err().unwrap(), "Unknown option: --sps");
+        assert_eq!(parse(&["--spp", "4", "320"]).unwrap().width, 320);
+        assert!(parse(&["wide"]).err().unwrap().starts_with("Invalid image width"));
+        assert!(parse(&["0"]).is_err());
+    }
+}
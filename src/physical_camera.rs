@@ -0,0 +1,104 @@
+use crate::common::PI;
+
+// 镜头参数以毫米为单位，场景单位视为米
+const MM_TO_SCENE: f64 = 0.001;
+
+/*
+物理相机参数，由此推导视角、散焦与曝光。
+测光时曝光按场景辐射亮度单位为cd/m²计算；不测光时曝光系数为1，
+辐射亮度直接作为像素值，焦距与光圈只影响视角和景深
+ */
+#[derive(Debug, Copy, Clone)]
+pub struct PhysicalCamera {
+    // 焦距(mm)
+    pub focal_length: f64,
+    // 光圈值 N = 焦距 / 光圈直径
+    pub f_stop: f64,
+    // 传感器宽度(mm)，高度由图像宽高比得到
+    pub sensor_width: f64,
+    // 感光度
+    pub iso: f64,
+    // 快门时间(s)
+    pub shutter_time: f64,
+    // 是否按光圈、快门与ISO测光
+    pub metered: bool
+}
+
+impl Default for PhysicalCamera {
+    fn default() -> Self {
+        // 全画幅 50mm f/2.8 1/60s ISO100
+        PhysicalCamera {
+            focal_length: 50.0,
+            f_stop: 2.8,
+            sensor_width: 36.0,
+            iso: 100.0,
+            shutter_time: 1.0 / 60.0,
+            metered: false
+        }
+    }
+}
+
+impl PhysicalCamera {
+    // 竖直视角(角度制)
+    pub fn vfov(&self, aspect_ratio: f64) -> f64 {
+        let sensor_height = self.sensor_width / aspect_ratio;
+        (2.0 * (sensor_height / (2.0 * self.focal_length)).atan()) * 180.0 / PI
+    }
+
+    // 入瞳半径(场景单位)
+    pub fn aperture_radius(&self) -> f64 {
+        self.focal_length / self.f_stop / 2.0 * MM_TO_SCENE
+    }
+
+    // 对焦于focus_dist时，入瞳对焦点的张角(角度制)，即Camera的defocus_angle
+    pub fn defocus_angle(&self, focus_dist: f64) -> f64 {
+        (2.0 * (self.aperture_radius() / focus_dist).atan()) * 180.0 / PI
+    }
+
+    /*
+    EV100 = log2(N² / t · 100 / ISO)，
+    传感器饱和时的亮度 L_max = 1.2 · 2^EV100，曝光系数为其倒数
+     */
+    pub fn ev100(&self) -> f64 {
+        (self.f_stop * self.f_stop / self.shutter_time * 100.0 / self.iso).log2()
+    }
+
+    pub fn exposure(&self) -> f64 {
+        if !self.metered {
+            return 1.0;
+        }
+        1.0 / (1.2 * 2f64.powf(self.ev100()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn unmetered_exposure_is_one() {
+        let camera = PhysicalCamera { focal_length: 85.0, f_stop: 8.0, ..PhysicalCamera::default() };
+        assert_eq!(camera.exposure(), 1.0);
+    }
+
+    #[test]
+    fn metered_exposure_follows_ev100() {
+        // f/16 1/100s ISO100 的EV100约为14.6
+        let sunny16 = PhysicalCamera { f_stop: 16.0, shutter_time: 0.01, metered: true, ..PhysicalCamera::default() };
+        assert!((sunny16.ev100() - 14.64).abs() < 0.01);
+        assert!((sunny16.exposure() * 1.2 * 2f64.powf(sunny16.ev100()) - 1.0).abs() < 1e-12);
+        // 光圈缩小一档，曝光减半
+        let stopped_down = PhysicalCamera { f_stop: 16.0 * 2f64.sqrt(), ..sunny16 };
+        assert!((stopped_down.exposure() / sunny16.exposure() - 0.5).abs() < 1e-9);
+    }
+
+    #[test]
+    fn field_of_view_and_defocus() {
+        let camera = PhysicalCamera::default();
+        // 全画幅50mm的水平视角约39.6°，宽高比1时竖直视角相同
+        assert!((camera.vfov(1.0) - 39.6).abs() < 0.1);
+        // 入瞳直径 50/2.8 mm
+        assert!((camera.aperture_radius() - 0.05 / 2.8 / 2.0).abs() < 1e-12);
+        assert!(camera.defocus_angle(10.0) < camera.defocus_angle(1.0));
+    }
+}
@@ -0,0 +1,325 @@
+use crate::common::{degrees_to_radians, PI};
+use crate::vec3::{Point, Vec3};
+
+/*
+相机坐标系：origin为lookfrom点，u向右，v向上，w与视线方向相反
+ */
+#[derive(Debug, Copy, Clone, Default)]
+pub struct CameraBasis {
+    pub origin: Point,
+    pub u: Vec3,
+    pub v: Vec3,
+    pub w: Vec3
+}
+
+impl CameraBasis {
+    pub fn to_world(self, local: Vec3) -> Vec3 {
+        self.u * local.x() + self.v * local.y() - self.w * local.z()
+    }
+}
+
+pub trait CameraModel {
+    /*
+    (s, t)为图像上的归一化坐标，s从左到右、t从上到下取值[0,1]；
+    lens为单位圆盘内的镜头采样点。
+    返回光线起点与方向，None表示该位置不在投影范围内(例如鱼眼圆外)
+     */
+    fn generate_ray(&self, basis: &CameraBasis, s: f64, t: f64, lens: Vec3) -> Option<(Point, Vec3)>;
+
+    // 全景投影(水平方向环绕)，立体渲染时使用全向立体
+    fn is_panoramic(&self) -> bool {
+        false
+    }
+
+    // 重新对焦，不支持对焦的投影忽略该调用
+    fn set_focus(&mut self, _focus_dist: f64, _defocus_angle: f64) {}
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Projection {
+    Perspective,
+    Orthographic,
+    Fisheye,
+    Equirectangular,
+    Cylindrical
+}
+
+impl Projection {
+    pub fn from_name(name: &str) -> Option<Projection> {
+        match name {
+            "perspective" => Some(Projection::Perspective),
+            "orthographic" | "ortho" => Some(Projection::Orthographic),
+            "fisheye" => Some(Projection::Fisheye),
+            "equirectangular" | "latlong" => Some(Projection::Equirectangular),
+            "cylindrical" => Some(Projection::Cylindrical),
+            _ => None
+        }
+    }
+}
+
+/*
+薄透镜透视投影(默认)
+ */
+pub struct Perspective {
+    vfov: f64,
+    aspect_ratio: f64,
+    viewport_width: f64,
+    viewport_height: f64,
+    focus_dist: f64,
+    defocus_radius: f64
+}
+
+impl Perspective {
+    pub fn new(vfov: f64, aspect_ratio: f64, focus_dist: f64, defocus_angle: f64) -> Perspective {
+        let theta = degrees_to_radians(vfov);
+        //视窗高度
+        let viewport_height = 2.0 * (theta / 2.0).tan() * focus_dist;
+        Perspective {
+            vfov,
+            aspect_ratio,
+            viewport_width: viewport_height * aspect_ratio,
+            viewport_height,
+            focus_dist,
+            // 散焦圆盘半径
+            defocus_radius: focus_dist * degrees_to_radians(defocus_angle / 2.0).tan()
+        }
+    }
+}
+
+impl CameraModel for Perspective {
+    fn generate_ray(&self, basis: &CameraBasis, s: f64, t: f64, lens: Vec3) -> Option<(Point, Vec3)> {
+        // 焦平面上的目标点
+        let target = basis.origin + basis.to_world(Vec3::new((s - 0.5) * self.viewport_width,
+                                                              (0.5 - t) * self.viewport_height,
+                                                              self.focus_dist));
+        let origin = if self.defocus_radius <= 0.0 {
+            basis.origin
+        } else {
+            basis.origin + (basis.u * lens.x() + basis.v * lens.y()) * self.defocus_radius
+        };
+        Some((origin, target - origin))
+    }
+
+    fn set_focus(&mut self, focus_dist: f64, defocus_angle: f64) {
+        *self = Perspective::new(self.vfov, self.aspect_ratio, focus_dist, defocus_angle);
+    }
+}
+
+/*
+正交投影，所有光线平行于视线方向
+ */
+pub struct Orthographic {
+    view_width: f64,
+    view_height: f64
+}
+
+impl Orthographic {
+    pub fn new(view_height: f64, aspect_ratio: f64) -> Orthographic {
+        Orthographic {
+            view_width: view_height * aspect_ratio,
+            view_height
+        }
+    }
+}
+
+impl CameraModel for Orthographic {
+    fn generate_ray(&self, basis: &CameraBasis, s: f64, t: f64, _lens: Vec3) -> Option<(Point, Vec3)> {
+        let origin = basis.origin + basis.u * ((s - 0.5) * self.view_width)
+            + basis.v * ((0.5 - t) * self.view_height);
+        Some((origin, -basis.w))
+    }
+}
+
+/*
+等距鱼眼投影，像面半径与入射角成正比(r = f·θ)，成像圆内切于图像短边
+ */
+pub struct Fisheye {
+    fov: f64,
+    aspect_ratio: f64
+}
+
+impl Fisheye {
+    pub fn new(fov: f64, aspect_ratio: f64) -> Fisheye {
+        Fisheye {
+            fov: degrees_to_radians(fov),
+            aspect_ratio
+        }
+    }
+}
+
+impl CameraModel for Fisheye {
+    fn generate_ray(&self, basis: &CameraBasis, s: f64, t: f64, _lens: Vec3) -> Option<(Point, Vec3)> {
+        let (sx, sy) = if self.aspect_ratio >= 1.0 { (self.aspect_ratio, 1.0) } else { (1.0, 1.0 / self.aspect_ratio) };
+        let x = (2.0 * s - 1.0) * sx;
+        let y = (1.0 - 2.0 * t) * sy;
+        let r = (x * x + y * y).sqrt();
+        if r > 1.0 {
+            return None;
+        }
+
+        let theta = r * self.fov / 2.0;
+        let phi = y.atan2(x);
+        let direction = Vec3::new(theta.sin() * phi.cos(), theta.sin() * phi.sin(), theta.cos());
+        Some((basis.origin, basis.to_world(direction)))
+    }
+}
+
+/*
+360°等距柱状投影(经纬度图)，图像宽度对应经度[-π, π]，高度对应纬度[π/2, -π/2]
+ */
+pub struct Equirectangular;
+
+impl Default for Equirectangular {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Equirectangular {
+    pub fn new() -> Equirectangular {
+        Equirectangular
+    }
+}
+
+impl CameraModel for Equirectangular {
+    fn generate_ray(&self, basis: &CameraBasis, s: f64, t: f64, _lens: Vec3) -> Option<(Point, Vec3)> {
+        let phi = (s - 0.5) * 2.0 * PI;
+        let lat = (0.5 - t) * PI;
+        let direction = Vec3::new(lat.cos() * phi.sin(), lat.sin(), lat.cos() * phi.cos());
+        Some((basis.origin, basis.to_world(direction)))
+    }
+
+    fn is_panoramic(&self) -> bool {
+        true
+    }
+}
+
+/*
+柱面全景投影，水平方向按角度展开，竖直方向为柱面上的线性高度
+ */
+pub struct Cylindrical {
+    hfov: f64,
+    height: f64
+}
+
+impl Cylindrical {
+    pub fn new(hfov: f64, aspect_ratio: f64) -> Cylindrical {
+        let hfov = degrees_to_radians(hfov);
+        Cylindrical {
+            hfov,
+            // 保持图像中心附近像素为正方形
+            height: hfov / aspect_ratio
+        }
+    }
+}
+
+impl CameraModel for Cylindrical {
+    fn generate_ray(&self, basis: &CameraBasis, s: f64, t: f64, _lens: Vec3) -> Option<(Point, Vec3)> {
+        let phi = (s - 0.5) * self.hfov;
+        let y = (0.5 - t) * self.height;
+        let direction = Vec3::new(phi.sin(), y, phi.cos());
+        Some((basis.origin, basis.to_world(direction)))
+    }
+
+    fn is_panoramic(&self) -> bool {
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vec3::dot;
+
+    // 看向-z的相机
+    fn basis() -> CameraBasis {
+        CameraBasis {
+            origin: Point::new(1.0, 2.0, 3.0),
+            u: Vec3::new(1.0, 0.0, 0.0),
+            v: Vec3::new(0.0, 1.0, 0.0),
+            w: Vec3::new(0.0, 0.0, 1.0)
+        }
+    }
+
+    fn direction(model: &dyn CameraModel, s: f64, t: f64) -> Vec3 {
+        model.generate_ray(&basis(), s, t, Vec3::default()).unwrap().1.unit_vector()
+    }
+
+    fn assert_close(a: Vec3, b: Vec3) {
+        assert!((a - b).length() < 1e-9, "{:?} != {:?}", a, b);
+    }
+
+    // 两个方向的夹角(角度制)
+    fn angle(a: Vec3, b: Vec3) -> f64 {
+        dot(a.unit_vector(), b.unit_vector()).clamp(-1.0, 1.0).acos().to_degrees()
+    }
+
+    #[test]
+    fn projection_from_name() {
+        assert_eq!(Projection::from_name("ortho"), Some(Projection::Orthographic));
+        assert_eq!(Projection::from_name("latlong"), Some(Projection::Equirectangular));
+        assert_eq!(Projection::from_name("cylindrical"), Some(Projection::Cylindrical));
+        assert_eq!(Projection::from_name("spherical"), None);
+    }
+
+    #[test]
+    fn perspective_edges_span_vertical_fov() {
+        let perspective = Perspective::new(60.0, 2.0, 4.0, 0.0);
+        let forward = Vec3::new(0.0, 0.0, -1.0);
+        assert_close(direction(&perspective, 0.5, 0.5), forward);
+        assert!((angle(direction(&perspective, 0.5, 0.0), forward) - 30.0).abs() < 1e-9);
+        assert!(direction(&perspective, 0.5, 0.0).y() > 0.0);
+        assert!(direction(&perspective, 1.0, 0.5).x() > 0.0);
+        // 散焦时光线仍会聚在焦平面上
+        let blurred = Perspective::new(60.0, 2.0, 4.0, 10.0);
+        let (origin, dir) = blurred.generate_ray(&basis(), 0.5, 0.5, Vec3::new(1.0, 0.0, 0.0)).unwrap();
+        assert!(origin != basis().origin);
+        assert_close(origin + dir, basis().origin + forward * 4.0);
+    }
+
+    #[test]
+    fn orthographic_rays_are_parallel() {
+        let orthographic = Orthographic::new(2.0, 1.5);
+        let (center, dir) = orthographic.generate_ray(&basis(), 0.5, 0.5, Vec3::default()).unwrap();
+        let (corner, corner_dir) = orthographic.generate_ray(&basis(), 0.0, 0.0, Vec3::default()).unwrap();
+        assert_close(dir, Vec3::new(0.0, 0.0, -1.0));
+        assert_close(corner_dir, dir);
+        assert_close(center, basis().origin);
+        assert_close(corner - center, Vec3::new(-1.5, 1.0, 0.0));
+    }
+
+    #[test]
+    fn fisheye_angle_grows_linearly_with_radius() {
+        let fisheye = Fisheye::new(180.0, 2.0);
+        let forward = Vec3::new(0.0, 0.0, -1.0);
+        assert_close(direction(&fisheye, 0.5, 0.5), forward);
+        // 成像圆内切于短边：上边缘为90°，半径一半处为45°
+        assert!((angle(direction(&fisheye, 0.5, 0.0), forward) - 90.0).abs() < 1e-9);
+        assert!((angle(direction(&fisheye, 0.5, 0.25), forward) - 45.0).abs() < 1e-9);
+        assert!((angle(direction(&fisheye, 0.625, 0.5), forward) - 45.0).abs() < 1e-9);
+        assert!(fisheye.generate_ray(&basis(), 0.0, 0.0, Vec3::default()).is_none());
+    }
+
+    #[test]
+    fn equirectangular_covers_the_sphere() {
+        let equirectangular = Equirectangular::new();
+        assert!(equirectangular.is_panoramic());
+        assert_close(direction(&equirectangular, 0.5, 0.5), Vec3::new(0.0, 0.0, -1.0));
+        assert_close(direction(&equirectangular, 0.75, 0.5), Vec3::new(1.0, 0.0, 0.0));
+        assert_close(direction(&equirectangular, 0.5, 0.0), Vec3::new(0.0, 1.0, 0.0));
+        // 左右边缘都指向相机背后
+        assert_close(direction(&equirectangular, 0.0, 0.5), Vec3::new(0.0, 0.0, 1.0));
+        assert_close(direction(&equirectangular, 1.0, 0.5), Vec3::new(0.0, 0.0, 1.0));
+    }
+
+    #[test]
+    fn cylindrical_spans_horizontal_fov() {
+        let cylindrical = Cylindrical::new(120.0, 2.0);
+        let forward = Vec3::new(0.0, 0.0, -1.0);
+        assert_close(direction(&cylindrical, 0.5, 0.5), forward);
+        assert!((angle(direction(&cylindrical, 1.0, 0.5), forward) - 60.0).abs() < 1e-9);
+        // 竖直方向为柱面上的线性高度
+        let up = direction(&cylindrical, 0.5, 0.0);
+        assert!((up.y() / -up.z() - 120f64.to_radians() / 4.0).abs() < 1e-9);
+    }
+}
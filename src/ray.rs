@@ -1,10 +1,17 @@
+use crate::spectrum::SampledWavelengths;
 use crate::vec3::*;
 
 #[derive(Default)]
 pub struct Ray {
     origin: Vec3,
     direction: Vec3,
-    tm:f64
+    tm:f64,
+    // 光谱渲染时路径携带的波长
+    wavelengths: Option<SampledWavelengths>,
+    // 散射得到该光线时，材质给出的衰减已是各波长处的光谱值，无需再由RGB上采样
+    spectral_attenuation: bool,
+    // 由镜面反射/折射分支散射得到，光源采样无法得到该方向
+    specular: bool
 }
 
 impl Ray {
@@ -14,28 +21,59 @@ impl Ray {
         Ray {
             origin,
             direction,
-            tm: time
+            tm: time,
+            wavelengths: None,
+            spectral_attenuation: false,
+            specular: false
         }
     }
 
     pub fn copy(&mut self, other: Ray) {
         self.origin = other.origin;
         self.direction = other.direction;
+        self.tm = other.tm;
+        self.wavelengths = other.wavelengths;
+        self.spectral_attenuation = other.spectral_attenuation;
+        self.specular = other.specular;
     }
 
     pub fn original(&self) -> Vec3 {
-        return self.origin;
+        self.origin
     }
 
     pub fn direction(&self) -> Vec3 {
-        return self.direction;
+        self.direction
     }
 
     pub fn get_time(&self)->f64 {
         self.tm
     }
 
+    pub fn get_wavelengths(&self) -> Option<SampledWavelengths> {
+        self.wavelengths
+    }
+
+    pub fn set_wavelengths(&mut self, wavelengths: Option<SampledWavelengths>) {
+        self.wavelengths = wavelengths;
+    }
+
+    pub fn has_spectral_attenuation(&self) -> bool {
+        self.spectral_attenuation
+    }
+
+    pub fn set_spectral_attenuation(&mut self, spectral: bool) {
+        self.spectral_attenuation = spectral;
+    }
+
+    pub fn is_specular(&self) -> bool {
+        self.specular
+    }
+
+    pub fn set_specular(&mut self, specular: bool) {
+        self.specular = specular;
+    }
+
     pub fn at(&self, t: f64) -> Vec3 {
-        return self.origin + self.direction * t;
+        self.origin + self.direction * t
     }
 }
\ No newline at end of file
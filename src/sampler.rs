@@ -0,0 +1,439 @@
+use crate::common::{get_random_double, hash, pixel_seed};
+
+/*
+样本生成器：渲染每个像素样本前调用start_sample，之后按固定顺序依次取各维度的随机数
+(像素内位置、镜头、时间、光源、BSDF...)。
+同一像素各样本在同一维度上的取值分布均匀，比纯随机数收敛更快
+ */
+pub trait Sampler {
+    // 开始像素(i,j)的第index个样本，维度计数归零
+    fn start_sample(&mut self, i: i32, j: i32, index: i32);
+
+    // 渲染种子，各像素的样本序列由它派生。独立采样器使用线程的随机数发生器，由相机按种子设置
+    fn set_seed(&mut self, _seed: u64) {}
+
+    // [0,1)内的一维样本
+    fn get_1d(&mut self) -> f64;
+
+    // [0,1)^2内的二维样本
+    fn get_2d(&mut self) -> (f64, f64);
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum SamplerType {
+    Independent,
+    Stratified,
+    Halton,
+    Sobol
+}
+
+impl SamplerType {
+    pub fn from_name(name: &str) -> Option<SamplerType> {
+        match name {
+            "independent" | "random" => Some(SamplerType::Independent),
+            "stratified" | "jittered" => Some(SamplerType::Stratified),
+            "halton" => Some(SamplerType::Halton),
+            "sobol" => Some(SamplerType::Sobol),
+            _ => None
+        }
+    }
+
+    // samples_per_pixel为每个像素最多取的样本数，自适应采样时为最大样本数
+    pub fn create(self, samples_per_pixel: i32, seed: u64) -> Box<dyn Sampler> {
+        let mut sampler: Box<dyn Sampler> = match self {
+            SamplerType::Independent => Box::new(IndependentSampler::new()),
+            SamplerType::Stratified => Box::new(StratifiedSampler::new(samples_per_pixel)),
+            SamplerType::Halton => Box::new(HaltonSampler::new()),
+            SamplerType::Sobol => Box::new(SobolSampler::new())
+        };
+        sampler.set_seed(seed);
+        sampler
+    }
+}
+
+// 32位整数映射到[0,1)
+fn to_unit(x: u32) -> f64 {
+    f64::from(x) / 4294967296.0
+}
+
+/*
+纯随机样本，与原先直接调用get_random_double一致
+ */
+pub struct IndependentSampler;
+
+impl Default for IndependentSampler {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl IndependentSampler {
+    pub fn new() -> IndependentSampler {
+        IndependentSampler
+    }
+}
+
+impl Sampler for IndependentSampler {
+    fn start_sample(&mut self, _i: i32, _j: i32, _index: i32) {}
+
+    fn get_1d(&mut self) -> f64 {
+        get_random_double()
+    }
+
+    fn get_2d(&mut self) -> (f64, f64) {
+        (get_random_double(), get_random_double())
+    }
+}
+
+/*
+Kensler哈希置换(Correlated Multi-Jittered Sampling, 2013)：
+不需要存储即可得到[0,l)上由p决定的随机排列中第i个元素
+ */
+fn permute(mut i: u32, l: u32, p: u32) -> u32 {
+    let mut w = l - 1;
+    w |= w >> 1;
+    w |= w >> 2;
+    w |= w >> 4;
+    w |= w >> 8;
+    w |= w >> 16;
+    loop {
+        i ^= p;
+        i = i.wrapping_mul(0xe170893d);
+        i ^= p >> 16;
+        i ^= (i & w) >> 4;
+        i ^= p >> 8;
+        i = i.wrapping_mul(0x0929eb3f);
+        i ^= p >> 23;
+        i ^= (i & w) >> 1;
+        i = i.wrapping_mul(1 | p >> 27);
+        i = i.wrapping_mul(0x6935fa69);
+        i ^= (i & w) >> 11;
+        i = i.wrapping_mul(0x74dcb303);
+        i ^= (i & w) >> 2;
+        i = i.wrapping_mul(0x9e501cc3);
+        i ^= (i & w) >> 2;
+        i = i.wrapping_mul(0xc860a3df);
+        i &= w;
+        i ^= i >> 5;
+        if i < l {
+            break;
+        }
+    }
+    i.wrapping_add(p) % l
+}
+
+/*
+分层抖动采样：一维把[0,1)按每像素样本数分层，二维取不小于样本数的近似正方形网格，
+每个样本占一层(格)并在层内随机抖动。
+样本数不是完全平方数时网格格数多于样本数，各样本在全部格子的随机排列中依次取格，
+每个样本在整个正方形上仍是均匀分布的。各维度的排列用不同的哈希置换打乱，避免维度之间相关
+ */
+pub struct StratifiedSampler {
+    samples_per_pixel: u32,
+    // 二维网格的列数与行数
+    x_strata: u32,
+    y_strata: u32,
+    // 渲染种子与当前像素的种子
+    render_seed: u64,
+    seed: u64,
+    index: u32,
+    dimension: u64
+}
+
+impl StratifiedSampler {
+    // samples_per_pixel为每个像素最多取的样本数
+    pub fn new(samples_per_pixel: i32) -> StratifiedSampler {
+        let samples_per_pixel = samples_per_pixel.max(1) as u32;
+        let x_strata = f64::from(samples_per_pixel).sqrt().ceil() as u32;
+        StratifiedSampler {
+            samples_per_pixel,
+            x_strata,
+            y_strata: samples_per_pixel.div_ceil(x_strata),
+            render_seed: 0,
+            seed: 0,
+            index: 0,
+            dimension: 0
+        }
+    }
+
+    // 当前维度下本样本在count个层的随机排列中所在的层
+    fn next_stratum(&mut self, count: u32) -> u32 {
+        let p = hash(self.seed, self.dimension) as u32;
+        self.dimension += 1;
+        permute(self.index % count, count, p)
+    }
+}
+
+impl Sampler for StratifiedSampler {
+    fn start_sample(&mut self, i: i32, j: i32, index: i32) {
+        self.seed = pixel_seed(self.render_seed, i, j);
+        self.index = index as u32;
+        self.dimension = 0;
+    }
+
+    fn set_seed(&mut self, seed: u64) {
+        self.render_seed = seed;
+    }
+
+    fn get_1d(&mut self) -> f64 {
+        let stratum = self.next_stratum(self.samples_per_pixel);
+        (f64::from(stratum) + get_random_double()) / f64::from(self.samples_per_pixel)
+    }
+
+    fn get_2d(&mut self) -> (f64, f64) {
+        let stratum = self.next_stratum(self.x_strata * self.y_strata);
+        let x = stratum % self.x_strata;
+        let y = stratum / self.x_strata;
+        ((f64::from(x) + get_random_double()) / f64::from(self.x_strata),
+         (f64::from(y) + get_random_double()) / f64::from(self.y_strata))
+    }
+}
+
+// Halton序列使用的维数，超出后退化为随机数
+const HALTON_DIMENSIONS: usize = 128;
+
+fn radical_inverse(base: u64, mut index: u64) -> f64 {
+    let inv_base = 1.0 / base as f64;
+    let mut inv = inv_base;
+    let mut result = 0.0;
+    while index > 0 {
+        result += (index % base) as f64 * inv;
+        index /= base;
+        inv *= inv_base;
+    }
+    result
+}
+
+/*
+Halton序列：第d维取第d个素数为底的根式逆。
+每个像素按哈希做Cranley-Patterson随机平移，相邻像素的样本互不相关
+ */
+pub struct HaltonSampler {
+    primes: Vec<u64>,
+    render_seed: u64,
+    seed: u64,
+    index: u64,
+    dimension: usize
+}
+
+impl Default for HaltonSampler {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl HaltonSampler {
+    pub fn new() -> HaltonSampler {
+        let mut primes: Vec<u64> = Vec::with_capacity(HALTON_DIMENSIONS);
+        let mut candidate = 2;
+        while primes.len() < HALTON_DIMENSIONS {
+            if primes.iter().all(|p| candidate % p != 0) {
+                primes.push(candidate);
+            }
+            candidate += 1;
+        }
+        HaltonSampler {
+            primes,
+            render_seed: 0,
+            seed: 0,
+            index: 0,
+            dimension: 0
+        }
+    }
+}
+
+impl Sampler for HaltonSampler {
+    fn start_sample(&mut self, i: i32, j: i32, index: i32) {
+        self.seed = pixel_seed(self.render_seed, i, j);
+        self.index = index as u64;
+        self.dimension = 0;
+    }
+
+    fn set_seed(&mut self, seed: u64) {
+        self.render_seed = seed;
+    }
+
+    fn get_1d(&mut self) -> f64 {
+        let dimension = self.dimension;
+        self.dimension += 1;
+        if dimension >= self.primes.len() {
+            return get_random_double();
+        }
+        let offset = to_unit(hash(self.seed, dimension as u64) as u32);
+        (radical_inverse(self.primes[dimension], self.index) + offset).fract()
+    }
+
+    fn get_2d(&mut self) -> (f64, f64) {
+        (self.get_1d(), self.get_1d())
+    }
+}
+
+/*
+Sobol序列前两维的生成矩阵(第二维本原多项式为x+1)
+ */
+fn sobol_matrices() -> [[u32; 32]; 2] {
+    let mut first = [0u32; 32];
+    let mut second = [0u32; 32];
+    let mut v = 1u32 << 31;
+    for (k, (a, b)) in first.iter_mut().zip(second.iter_mut()).enumerate() {
+        *a = 1 << (31 - k);
+        *b = v;
+        v ^= v >> 1;
+    }
+    [first, second]
+}
+
+fn sobol(matrix: &[u32; 32], mut index: u32) -> u32 {
+    let mut result = 0;
+    let mut k = 0;
+    while index != 0 {
+        if index & 1 != 0 {
+            result ^= matrix[k];
+        }
+        index >>= 1;
+        k += 1;
+    }
+    result
+}
+
+// Laine-Karras置换，配合位反转实现嵌套均匀(Owen)扰乱
+fn laine_karras_permutation(mut x: u32, seed: u32) -> u32 {
+    x = x.wrapping_add(seed);
+    x ^= x.wrapping_mul(0x6c50b47c);
+    x ^= x.wrapping_mul(0xb82f1e52);
+    x ^= x.wrapping_mul(0xc7afe638);
+    x ^= x.wrapping_mul(0x8d22f6e6);
+    x
+}
+
+fn nested_uniform_scramble(x: u32, seed: u32) -> u32 {
+    laine_karras_permutation(x.reverse_bits(), seed).reverse_bits()
+}
+
+/*
+Owen扰乱的Sobol序列(Burley, Practical Hash-based Owen Scrambling, 2020)。
+每次取二维样本都使用Sobol前两维，并按(像素, 维度)哈希打乱样本顺序与数值，
+高维之间因此不相关，同时保留每对维度上的(0,2)序列分层性质
+ */
+pub struct SobolSampler {
+    matrices: [[u32; 32]; 2],
+    render_seed: u64,
+    seed: u64,
+    index: u32,
+    dimension: u64
+}
+
+impl Default for SobolSampler {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl SobolSampler {
+    pub fn new() -> SobolSampler {
+        SobolSampler {
+            matrices: sobol_matrices(),
+            render_seed: 0,
+            seed: 0,
+            index: 0,
+            dimension: 0
+        }
+    }
+
+    fn next_point(&mut self) -> (u32, u32) {
+        let dimension_seed = hash(self.seed, self.dimension);
+        self.dimension += 1;
+        let index = nested_uniform_scramble(self.index, dimension_seed as u32);
+        let x = sobol(&self.matrices[0], index);
+        let y = sobol(&self.matrices[1], index);
+        (nested_uniform_scramble(x, hash(dimension_seed, 0) as u32),
+         nested_uniform_scramble(y, hash(dimension_seed, 1) as u32))
+    }
+}
+
+impl Sampler for SobolSampler {
+    fn start_sample(&mut self, i: i32, j: i32, index: i32) {
+        self.seed = pixel_seed(self.render_seed, i, j);
+        self.index = index as u32;
+        self.dimension = 0;
+    }
+
+    fn set_seed(&mut self, seed: u64) {
+        self.render_seed = seed;
+    }
+
+    fn get_1d(&mut self) -> f64 {
+        to_unit(self.next_point().0)
+    }
+
+    fn get_2d(&mut self) -> (f64, f64) {
+        let (x, y) = self.next_point();
+        (to_unit(x), to_unit(y))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // 像素(i, j)前count个样本的第一个二维样本
+    fn first_2d(sampler: &mut dyn Sampler, i: i32, j: i32, count: i32) -> Vec<(f64, f64)> {
+        (0..count).map(|index| {
+            sampler.start_sample(i, j, index);
+            sampler.get_2d()
+        }).collect()
+    }
+
+    // 每个样本落在n×m网格中的格子序号
+    fn cells(points: &[(f64, f64)], n: f64, m: f64) -> Vec<usize> {
+        points.iter().map(|&(x, y)| (y * m) as usize * n as usize + (x * n) as usize).collect()
+    }
+
+    #[test]
+    fn stratified_1d_hits_every_stratum_once() {
+        let mut sampler = StratifiedSampler::new(10);
+        let mut hits = [0; 10];
+        for index in 0..10 {
+            sampler.start_sample(3, 4, index);
+            hits[(sampler.get_1d() * 10.0) as usize] += 1;
+        }
+        assert!(hits.iter().all(|&h| h == 1));
+    }
+
+    #[test]
+    fn stratified_2d_covers_every_cell() {
+        // 10个样本使用4×3网格：同一像素内不重复，多个像素合起来覆盖全部12格
+        let mut sampler = StratifiedSampler::new(10);
+        let mut covered = [false; 12];
+        for i in 0..16 {
+            let mut hit = cells(&first_2d(&mut sampler, i, 0, 10), 4.0, 3.0);
+            for &cell in hit.iter() {
+                covered[cell] = true;
+            }
+            hit.sort();
+            hit.dedup();
+            assert_eq!(hit.len(), 10);
+        }
+        assert!(covered.iter().all(|&c| c));
+    }
+
+    #[test]
+    fn sobol_samples_form_a_net() {
+        let mut sampler = SobolSampler::new();
+        let mut hit = cells(&first_2d(&mut sampler, 1, 2, 16), 4.0, 4.0);
+        hit.sort();
+        assert_eq!(hit, (0..16).collect::<Vec<usize>>());
+    }
+
+    #[test]
+    fn halton_first_dimension_is_stratified() {
+        // 以2为底的根式逆前8个值各占[0,1)的一个1/8区间，随机平移不改变这一点
+        let mut sampler = HaltonSampler::new();
+        let mut hit: Vec<usize> = (0..8).map(|index| {
+            sampler.start_sample(5, 6, index);
+            (sampler.get_1d() * 8.0) as usize
+        }).collect();
+        hit.sort();
+        assert_eq!(hit, (0..8).collect::<Vec<usize>>());
+    }
+}
@@ -0,0 +1,61 @@
+use crate::light::Light;
+use crate::sphere::Hittable;
+use crate::vec3::{Color, Point};
+
+/*
+场景：可求交的几何体与需要直接采样的光源。
+逃逸光线的颜色由各光源的le给出(环境光、太阳)；没有环境光时再加上纯色背景或默认的白-蓝渐变背景
+ */
+pub struct Scene {
+    world: Box<dyn Hittable>,
+    lights: Vec<Box<dyn Light>>,
+    has_environment: bool,
+    background: Option<Color>
+}
+
+impl Scene {
+    pub fn new(world: Box<dyn Hittable>) -> Scene {
+        Scene {
+            world,
+            lights: Vec::new(),
+            has_environment: false,
+            background: None
+        }
+    }
+
+    pub fn add_light(&mut self, light: Box<dyn Light>) {
+        self.lights.push(light)
+    }
+
+    // 环境光同时作为背景与光源
+    pub fn set_environment(&mut self, environment: Box<dyn Light>) {
+        self.lights.push(environment);
+        self.has_environment = true;
+    }
+
+    pub fn has_environment(&self) -> bool {
+        self.has_environment
+    }
+
+    // 没有环境光时逃逸光线的颜色，None为白-蓝渐变
+    pub fn set_background(&mut self, background: Option<Color>) {
+        self.background = background;
+    }
+
+    pub fn get_background(&self) -> Option<Color> {
+        self.background
+    }
+
+    // 场景包围球，远处光源在覆盖它的圆盘上发射光线
+    pub fn bounding_sphere(&self) -> (Point, f64) {
+        self.world.bounding_box().bounding_sphere()
+    }
+
+    pub fn get_world(&self) -> &dyn Hittable {
+        self.world.as_ref()
+    }
+
+    pub fn get_lights(&self) -> &Vec<Box<dyn Light>> {
+        &self.lights
+    }
+}
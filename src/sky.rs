@@ -0,0 +1,208 @@
+use crate::common::{degrees_to_radians, PI};
+use crate::environment::EnvironmentLight;
+use crate::imageio::Image;
+use crate::light::SunLight;
+use crate::vec3::{Color, dot, Vec3};
+
+// 太阳圆盘亮度(cd/m²)，再按大气透过率衰减
+const SUN_LUMINANCE: f64 = 1.6e9;
+// 太阳角直径(角度制)
+const SUN_ANGULAR_DIAMETER: f64 = 0.53;
+
+/*
+Preetham晴天天空模型(A Practical Analytic Model for Daylight, 1999)。
+由太阳高度角、方位角与大气浑浊度得到天空亮度分布，单位为cd/m²；
+同一组参数同时给出经大气衰减后的太阳光，保证天空与太阳一致。
+方位角从-z方向起绕+y轴逆时针度量，与EnvironmentLight的经纬度图一致
+ */
+pub struct PhysicalSky {
+    to_sun: Vec3,
+    // 太阳天顶角
+    theta_sun: f64,
+    turbidity: f64,
+    // Perez分布系数 A..E，分别对应 Y、x、y
+    perez_y: [f64; 5],
+    perez_cx: [f64; 5],
+    perez_cy: [f64; 5],
+    // 天顶处的 Y(cd/m²)、x、y
+    zenith: (f64, f64, f64),
+    intensity: f64
+}
+
+impl PhysicalSky {
+    // 角度制，turbidity取值约为2(非常晴朗)到10(雾霾)
+    pub fn new(sun_elevation: f64, sun_azimuth: f64, turbidity: f64) -> PhysicalSky {
+        let elevation = degrees_to_radians(sun_elevation.clamp(0.0, 90.0));
+        let azimuth = degrees_to_radians(sun_azimuth);
+        let t = turbidity.clamp(1.7, 10.0);
+        let theta_sun = PI / 2.0 - elevation;
+
+        let perez_y = [0.1787 * t - 1.4630, -0.3554 * t + 0.4275, -0.0227 * t + 5.3251,
+                       0.1206 * t - 2.5771, -0.0670 * t + 0.3703];
+        let perez_cx = [-0.0193 * t - 0.2592, -0.0665 * t + 0.0008, -0.0004 * t + 0.2125,
+                        -0.0641 * t - 0.8989, -0.0033 * t + 0.0452];
+        let perez_cy = [-0.0167 * t - 0.2608, -0.0950 * t + 0.0092, -0.0079 * t + 0.2102,
+                        -0.0441 * t - 1.6537, -0.0109 * t + 0.0529];
+
+        let chi = (4.0 / 9.0 - t / 120.0) * (PI - 2.0 * theta_sun);
+        // 论文中天顶亮度单位为kcd/m²
+        let zenith_y = ((4.0453 * t - 4.9710) * chi.tan() - 0.2155 * t + 2.4192) * 1000.0;
+        let theta = [theta_sun.powi(3), theta_sun.powi(2), theta_sun, 1.0];
+        let zenith_poly = |m: [[f64; 4]; 3]| {
+            let row = |r: [f64; 4]| r[0] * theta[0] + r[1] * theta[1] + r[2] * theta[2] + r[3] * theta[3];
+            t * t * row(m[0]) + t * row(m[1]) + row(m[2])
+        };
+        let zenith_x = zenith_poly([[0.00166, -0.00375, 0.00209, 0.0],
+                                    [-0.02903, 0.06377, -0.03202, 0.00394],
+                                    [0.11693, -0.21196, 0.06052, 0.25886]]);
+        let zenith_cy = zenith_poly([[0.00275, -0.00610, 0.00317, 0.0],
+                                     [-0.04214, 0.08970, -0.04153, 0.00516],
+                                     [0.15346, -0.26756, 0.06670, 0.26688]]);
+
+        PhysicalSky {
+            to_sun: Vec3::new(elevation.cos() * azimuth.sin(), elevation.sin(), -elevation.cos() * azimuth.cos()),
+            theta_sun,
+            turbidity: t,
+            perez_y,
+            perez_cx,
+            perez_cy,
+            zenith: (zenith_y.max(0.0), zenith_x, zenith_cy),
+            intensity: 1.0
+        }
+    }
+
+    // 整体亮度缩放，天空与太阳同时生效
+    pub fn set_intensity(&mut self, intensity: f64) {
+        self.intensity = intensity;
+    }
+
+    fn perez(coefficients: &[f64; 5], cos_theta: f64, gamma: f64) -> f64 {
+        let [a, b, c, d, e] = *coefficients;
+        (1.0 + a * (b / cos_theta).exp()) * (1.0 + c * (d * gamma).exp() + e * gamma.cos() * gamma.cos())
+    }
+
+    /*
+    direction方向的天空辐射亮度(线性sRGB)，地平线以下取地平线处的值
+     */
+    pub fn radiance(&self, direction: Vec3) -> Color {
+        let d = direction.unit_vector();
+        let cos_theta = d.y().max(0.001);
+        let gamma = dot(d, self.to_sun).clamp(-1.0, 1.0).acos();
+
+        let relative = |coefficients: &[f64; 5]| {
+            Self::perez(coefficients, cos_theta, gamma) / Self::perez(coefficients, 1.0, self.theta_sun)
+        };
+        let luminance = self.zenith.0 * relative(&self.perez_y);
+        let x = self.zenith.1 * relative(&self.perez_cx);
+        let y = self.zenith.2 * relative(&self.perez_cy);
+        xyy_to_rgb(x, y, luminance) * self.intensity
+    }
+
+    /*
+    将天空烘焙为经纬度图并作为可重要性采样的环境光
+     */
+    pub fn environment(&self, width: usize, height: usize) -> EnvironmentLight {
+        let mut image = Image::new(width, height);
+        for y in 0..height {
+            let theta = PI * (y as f64 + 0.5) / height as f64;
+            for x in 0..width {
+                let phi = 2.0 * PI * (x as f64 + 0.5) / width as f64;
+                let direction = Vec3::new(theta.sin() * phi.sin(), theta.cos(), -theta.sin() * phi.cos());
+                image.set_pixel(x, y, self.radiance(direction));
+            }
+        }
+        EnvironmentLight::new(image, 0.0, 1.0)
+    }
+
+    /*
+    与天空匹配的太阳光：大气层外的太阳亮度经瑞利散射与气溶胶衰减(Preetham附录A.2)，
+    按R/G/B代表波长分别计算透过率
+     */
+    pub fn sun(&self) -> SunLight {
+        // 相对光学质量
+        let theta_deg = self.theta_sun.to_degrees();
+        let m = 1.0 / (self.theta_sun.cos() + 0.15 * (93.885 - theta_deg).powf(-1.253));
+        let beta = 0.04608 * self.turbidity - 0.04586;
+        let transmittance = |lambda_um: f64| {
+            let rayleigh = (-0.008735 * lambda_um.powf(-4.08) * m).exp();
+            let aerosol = (-beta * lambda_um.powf(-1.3) * m).exp();
+            rayleigh * aerosol
+        };
+        let radiance = Color::new(transmittance(0.680), transmittance(0.550), transmittance(0.440))
+            * (SUN_LUMINANCE * self.intensity);
+
+        let cos_theta_max = degrees_to_radians(SUN_ANGULAR_DIAMETER / 2.0).cos();
+        let solid_angle = 2.0 * PI * (1.0 - cos_theta_max);
+        SunLight::new(self.to_sun, radiance * solid_angle, SUN_ANGULAR_DIAMETER)
+    }
+}
+
+fn xyy_to_rgb(x: f64, y: f64, luminance: f64) -> Color {
+    if y <= 0.0 {
+        return Color::default();
+    }
+    let cap_x = x / y * luminance;
+    let cap_z = (1.0 - x - y) / y * luminance;
+    let cap_y = luminance;
+    Color::new(
+        (3.2406 * cap_x - 1.5372 * cap_y - 0.4986 * cap_z).max(0.0),
+        (-0.9689 * cap_x + 1.8758 * cap_y + 0.0415 * cap_z).max(0.0),
+        (0.0557 * cap_x - 0.2040 * cap_y + 1.0570 * cap_z).max(0.0)
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::light::Light;
+
+    fn direction(elevation: f64, azimuth: f64) -> Vec3 {
+        let (e, a) = (degrees_to_radians(elevation), degrees_to_radians(azimuth));
+        Vec3::new(e.cos() * a.sin(), e.sin(), -e.cos() * a.cos())
+    }
+
+    #[test]
+    fn zenith_matches_zenith_luminance() {
+        let sky = PhysicalSky::new(45.0, 0.0, 3.0);
+        let zenith = sky.radiance(Vec3::new(0.0, 1.0, 0.0));
+        assert!((zenith.luminance() - sky.zenith.0).abs() < 0.02 * sky.zenith.0, "{:?} vs {}", zenith, sky.zenith.0);
+        // 晴天天顶偏蓝
+        assert!(zenith.z() > zenith.x());
+    }
+
+    #[test]
+    fn sky_is_brightest_around_the_sun() {
+        let mut sky = PhysicalSky::new(30.0, 90.0, 3.0);
+        let near_sun = sky.radiance(direction(35.0, 90.0)).luminance();
+        let opposite = sky.radiance(direction(35.0, 270.0)).luminance();
+        assert!(near_sun > 2.0 * opposite, "{} vs {}", near_sun, opposite);
+        // 地平线以下按地平线的天顶角计算，不产生无穷大
+        let below = sky.radiance(direction(-60.0, 0.0));
+        assert!(below.luminance().is_finite() && below.luminance() >= 0.0);
+        sky.set_intensity(0.5);
+        assert!((sky.radiance(direction(35.0, 90.0)).luminance() - 0.5 * near_sun).abs() < 1e-9 * near_sun);
+    }
+
+    #[test]
+    fn sun_follows_elevation_and_reddens_near_horizon() {
+        let high = PhysicalSky::new(60.0, 90.0, 3.0).sun();
+        let low = PhysicalSky::new(5.0, 90.0, 3.0).sun();
+        let high_radiance = high.le(direction(60.0, 90.0));
+        let low_radiance = low.le(direction(5.0, 90.0));
+        assert!(high_radiance.luminance() > 0.0 && high_radiance.luminance() < SUN_LUMINANCE);
+        assert_eq!(high.le(direction(5.0, 90.0)), Color::default());
+        // 低太阳穿过更厚的大气：更暗、更红
+        assert!(low_radiance.luminance() < high_radiance.luminance());
+        assert!(low_radiance.x() / low_radiance.z() > high_radiance.x() / high_radiance.z());
+    }
+
+    #[test]
+    fn baked_environment_matches_sky() {
+        let sky = PhysicalSky::new(40.0, 120.0, 4.0);
+        let environment = sky.environment(64, 32);
+        // 像素中心方向上两者一致
+        let (theta, phi) = (PI * 10.5 / 32.0, 2.0 * PI * 20.5 / 64.0);
+        let d = Vec3::new(theta.sin() * phi.sin(), theta.cos(), -theta.sin() * phi.cos());
+        assert!((environment.le(d) - sky.radiance(d)).length() < 1e-9 * sky.radiance(d).length());
+    }
+}
@@ -0,0 +1,248 @@
+use crate::common::PI;
+use crate::vec3::{Color, Vec3};
+
+// 可见光波长范围(纳米)
+pub const LAMBDA_MIN: f64 = 360.0;
+pub const LAMBDA_MAX: f64 = 830.0;
+// 每条路径同时携带的波长数，与Color的分量数相同
+const WAVELENGTH_SAMPLES: usize = 3;
+
+/*
+Smits(1999)的RGB到光谱转换表：380-720nm等分为10段的分段常数基函数
+ */
+const SMITS_LAMBDA_START: f64 = 380.0;
+const SMITS_BIN_WIDTH: f64 = 34.0;
+const SMITS_WHITE: [f64; 10] = [1.0000, 1.0000, 0.9999, 0.9993, 0.9992, 0.9998, 1.0000, 1.0000, 1.0000, 1.0000];
+const SMITS_CYAN: [f64; 10] = [0.9710, 0.9426, 1.0007, 1.0007, 1.0007, 1.0007, 0.1564, 0.0000, 0.0000, 0.0000];
+const SMITS_MAGENTA: [f64; 10] = [1.0000, 1.0000, 0.9685, 0.2229, 0.0000, 0.0458, 0.8369, 1.0000, 1.0000, 0.9959];
+const SMITS_YELLOW: [f64; 10] = [0.0001, 0.0000, 0.1088, 0.6651, 1.0000, 1.0000, 0.9996, 0.9586, 0.9685, 0.9840];
+const SMITS_RED: [f64; 10] = [0.1012, 0.0515, 0.0000, 0.0000, 0.0000, 0.0000, 0.8325, 1.0149, 1.0149, 1.0149];
+const SMITS_GREEN: [f64; 10] = [0.0000, 0.0000, 0.0273, 0.7937, 1.0000, 0.9418, 0.1719, 0.0000, 0.0000, 0.0025];
+const SMITS_BLUE: [f64; 10] = [1.0000, 1.0000, 0.8916, 0.3323, 0.0000, 0.0000, 0.0003, 0.0369, 0.0483, 0.0496];
+
+/*
+RGB上采样为光谱后在波长lambda处的值：以最小分量乘白色，
+其余部分依次由两种"颜色"基函数补足(Smits, An RGB-to-Spectrum Conversion for Reflectances)
+ */
+pub fn rgb_to_spectrum(rgb: Color, lambda: f64) -> f64 {
+    let bin = (((lambda - SMITS_LAMBDA_START) / SMITS_BIN_WIDTH).floor().max(0.0) as usize).min(9);
+    let (r, g, b) = (rgb.x(), rgb.y(), rgb.z());
+    if r <= g && r <= b {
+        r * SMITS_WHITE[bin] + if g <= b {
+            (g - r) * SMITS_CYAN[bin] + (b - g) * SMITS_BLUE[bin]
+        } else {
+            (b - r) * SMITS_CYAN[bin] + (g - b) * SMITS_GREEN[bin]
+        }
+    } else if g <= r && g <= b {
+        g * SMITS_WHITE[bin] + if r <= b {
+            (r - g) * SMITS_MAGENTA[bin] + (b - r) * SMITS_BLUE[bin]
+        } else {
+            (b - g) * SMITS_MAGENTA[bin] + (r - b) * SMITS_RED[bin]
+        }
+    } else {
+        b * SMITS_WHITE[bin] + if r <= g {
+            (r - b) * SMITS_YELLOW[bin] + (g - r) * SMITS_GREEN[bin]
+        } else {
+            (g - b) * SMITS_YELLOW[bin] + (r - g) * SMITS_RED[bin]
+        }
+    }
+}
+
+// 两侧宽度不同的高斯函数
+fn piecewise_gaussian(lambda: f64, mu: f64, sigma_left: f64, sigma_right: f64) -> f64 {
+    let t = (lambda - mu) / if lambda < mu { sigma_left } else { sigma_right };
+    (-0.5 * t * t).exp()
+}
+
+/*
+CIE 1931标准观察者颜色匹配函数的解析拟合
+(Wyman, Sloan, Shirley 2013, Simple Analytic Approximations to the CIE XYZ Color Matching Functions)
+ */
+pub fn cie_xyz(lambda: f64) -> Vec3 {
+    Vec3::new(
+        1.056 * piecewise_gaussian(lambda, 599.8, 37.9, 31.0) + 0.362 * piecewise_gaussian(lambda, 442.0, 16.0, 26.7)
+            - 0.065 * piecewise_gaussian(lambda, 501.1, 20.4, 26.2),
+        0.821 * piecewise_gaussian(lambda, 568.8, 46.9, 40.5) + 0.286 * piecewise_gaussian(lambda, 530.9, 16.3, 31.1),
+        1.217 * piecewise_gaussian(lambda, 437.0, 11.8, 36.0) + 0.681 * piecewise_gaussian(lambda, 459.0, 26.0, 13.8)
+    )
+}
+
+/*
+拟合函数在全波长上的积分，每个高斯瓣的积分为 a·sqrt(π/2)·(σ1+σ2)。
+等能光谱(常数1)的XYZ除以y的积分后约为(1, 1, 1)
+ */
+fn cie_integrals() -> Vec3 {
+    let lobe = |a: f64, sigma_left: f64, sigma_right: f64| a * (PI / 2.0).sqrt() * (sigma_left + sigma_right);
+    Vec3::new(
+        lobe(1.056, 37.9, 31.0) + lobe(0.362, 16.0, 26.7) - lobe(0.065, 20.4, 26.2),
+        lobe(0.821, 46.9, 40.5) + lobe(0.286, 16.3, 31.1),
+        lobe(1.217, 11.8, 36.0) + lobe(0.681, 26.0, 13.8)
+    )
+}
+
+// XYZ到线性sRGB(D65白点)
+pub fn xyz_to_srgb(xyz: Vec3) -> Color {
+    Color::new(
+        3.2404542 * xyz.x() - 1.5371385 * xyz.y() - 0.4985314 * xyz.z(),
+        -0.9692660 * xyz.x() + 1.8760108 * xyz.y() + 0.0415560 * xyz.z(),
+        0.0556434 * xyz.x() - 0.2040259 * xyz.y() + 1.0572252 * xyz.z()
+    )
+}
+
+/*
+按人眼敏感度集中在可见光中部的分布采样波长(pbrt-v4 SampleVisibleWavelengths)
+ */
+pub fn sample_visible_wavelength(u: f64) -> f64 {
+    538.0 - 138.888889 * (0.85691062 - 1.82750197 * u).atanh()
+}
+
+pub fn visible_wavelength_pdf(lambda: f64) -> f64 {
+    if !(LAMBDA_MIN..=LAMBDA_MAX).contains(&lambda) {
+        return 0.0;
+    }
+    let c = (0.0072 * (lambda - 538.0)).cosh();
+    0.0039398042 / (c * c)
+}
+
+/*
+一条路径携带的波长：主波长(hero wavelength)与在采样空间中等距旋转得到的另外两个波长，
+光谱值依次存放在Color的三个分量中。
+折射率随波长变化时各波长的路径不再相同，只保留主波长(终止次波长)
+ */
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct SampledWavelengths {
+    lambda: [f64; WAVELENGTH_SAMPLES],
+    pdf: [f64; WAVELENGTH_SAMPLES]
+}
+
+impl SampledWavelengths {
+    // u为[0,1)的随机数
+    pub fn sample_visible(u: f64) -> SampledWavelengths {
+        let mut lambda = [0.0; WAVELENGTH_SAMPLES];
+        let mut pdf = [0.0; WAVELENGTH_SAMPLES];
+        for i in 0..WAVELENGTH_SAMPLES {
+            let up = (u + i as f64 / WAVELENGTH_SAMPLES as f64).fract();
+            lambda[i] = sample_visible_wavelength(up);
+            pdf[i] = visible_wavelength_pdf(lambda[i]);
+        }
+        SampledWavelengths {
+            lambda,
+            pdf
+        }
+    }
+
+    pub fn hero(&self) -> f64 {
+        self.lambda[0]
+    }
+
+    pub fn get_lambda(&self) -> [f64; WAVELENGTH_SAMPLES] {
+        self.lambda
+    }
+
+    pub fn is_secondary_terminated(&self) -> bool {
+        self.pdf[1..].iter().all(|&pdf| pdf == 0.0)
+    }
+
+    // 只保留主波长，其概率密度除以波长数以代表全部样本
+    pub fn terminate_secondary(&mut self) {
+        if self.is_secondary_terminated() {
+            return;
+        }
+        for pdf in self.pdf[1..].iter_mut() {
+            *pdf = 0.0;
+        }
+        self.pdf[0] /= WAVELENGTH_SAMPLES as f64;
+    }
+
+    // RGB反射率或辐射亮度上采样为光谱后在各波长处的值
+    pub fn upsample(&self, rgb: Color) -> Color {
+        Color::new(rgb_to_spectrum(rgb, self.lambda[0]),
+                   rgb_to_spectrum(rgb, self.lambda[1]),
+                   rgb_to_spectrum(rgb, self.lambda[2]))
+    }
+
+    // 各波长处光谱辐射亮度的蒙特卡洛估计转换为XYZ，Y按等能白光为1归一化
+    pub fn to_xyz(self, l: Color) -> Vec3 {
+        let mut xyz = Vec3::default();
+        for i in 0..WAVELENGTH_SAMPLES {
+            if self.pdf[i] > 0.0 {
+                xyz += cie_xyz(self.lambda[i]) * (l.get(i).unwrap_or(0.0) / self.pdf[i]);
+            }
+        }
+        xyz / (WAVELENGTH_SAMPLES as f64 * cie_integrals().y())
+    }
+
+    /*
+    转换为线性sRGB。上采样不区分光源光谱，等能白光按sRGB的D65白点换算会偏红，
+    因此按等能白光的sRGB值做白平衡，使RGB白色往返后仍为白色
+     */
+    pub fn to_rgb(self, l: Color) -> Color {
+        let white = xyz_to_srgb(cie_integrals() / cie_integrals().y());
+        let rgb = xyz_to_srgb(self.to_xyz(l));
+        Color::new(rgb.x() / white.x(), rgb.y() / white.y(), rgb.z() / white.z())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // 分层的波长样本上取平均
+    fn average(n: usize, estimate: impl Fn(SampledWavelengths) -> Color) -> Color {
+        (0..n).map(|k| estimate(SampledWavelengths::sample_visible((k as f64 + 0.5) / n as f64)))
+            .fold(Color::default(), |sum, c| sum + c) / n as f64
+    }
+
+    #[test]
+    fn white_and_grey_upsample_flat() {
+        for lambda in [380.0, 450.0, 555.0, 640.0, 700.0] {
+            assert!((rgb_to_spectrum(Color::new(1.0, 1.0, 1.0), lambda) - 1.0).abs() < 1e-3);
+            assert!((rgb_to_spectrum(Color::new(0.3, 0.3, 0.3), lambda) - 0.3).abs() < 1e-3);
+        }
+        // 红色反射率集中在长波段
+        assert!(rgb_to_spectrum(Color::new(1.0, 0.0, 0.0), 650.0) > 0.9);
+        assert!(rgb_to_spectrum(Color::new(1.0, 0.0, 0.0), 500.0) < 0.1);
+    }
+
+    #[test]
+    fn wavelength_pdf_integrates_to_one() {
+        let steps = 4700;
+        let h = (LAMBDA_MAX - LAMBDA_MIN) / steps as f64;
+        let integral: f64 = (0..steps).map(|k| visible_wavelength_pdf(LAMBDA_MIN + (k as f64 + 0.5) * h) * h).sum();
+        assert!((integral - 1.0).abs() < 1e-3, "{}", integral);
+        // 解析积分与数值积分一致
+        let numeric = (0..steps).fold(Vec3::default(), |sum, k| sum + cie_xyz(LAMBDA_MIN + (k as f64 + 0.5) * h) * h);
+        assert!((numeric - cie_integrals()).length() < 0.01 * cie_integrals().length());
+    }
+
+    #[test]
+    fn hero_wavelengths_rotate_through_sample_space() {
+        let wavelengths = SampledWavelengths::sample_visible(0.9);
+        let lambda = wavelengths.get_lambda();
+        assert_eq!(wavelengths.hero(), lambda[0]);
+        assert_eq!(lambda[1], sample_visible_wavelength((0.9 + 1.0 / 3.0) - 1.0));
+        assert!(lambda.iter().all(|l| (LAMBDA_MIN..=LAMBDA_MAX).contains(l)));
+
+        let mut terminated = wavelengths;
+        assert!(!terminated.is_secondary_terminated());
+        terminated.terminate_secondary();
+        terminated.terminate_secondary();
+        assert!(terminated.is_secondary_terminated());
+        assert_eq!(terminated.pdf[0], wavelengths.pdf[0] / 3.0);
+    }
+
+    #[test]
+    fn rgb_round_trips_through_spectra() {
+        let white = average(3000, |w| w.to_rgb(w.upsample(Color::new(1.0, 1.0, 1.0))));
+        assert!((white - Color::new(1.0, 1.0, 1.0)).length() < 0.01, "{:?}", white);
+        // 只保留主波长时估计仍然无偏
+        let hero_only = average(3000, |mut w| {
+            w.terminate_secondary();
+            w.to_rgb(w.upsample(Color::new(1.0, 1.0, 1.0)))
+        });
+        assert!((hero_only - Color::new(1.0, 1.0, 1.0)).length() < 0.02, "{:?}", hero_only);
+        let orange = average(3000, |w| w.to_rgb(w.upsample(Color::new(0.9, 0.5, 0.1))));
+        assert!((orange - Color::new(0.9, 0.5, 0.1)).length() < 0.1, "{:?}", orange);
+        assert!(orange.x() > orange.y() && orange.y() > orange.z());
+    }
+}
@@ -1,5 +1,6 @@
-use std::cell::RefCell;
 use std::rc::Rc;
+use crate::aabb::AABB;
+use crate::common::PI;
 use crate::material::{Lambertian, Material};
 use crate::ray::Ray;
 use crate::vec3::*;
@@ -18,7 +19,15 @@ pub struct HitRecord {
     // 相交解(此处为圆和Ray二元一次 x0、x1)
     t: f64,
     // 入射角方向判断
-    front_face: bool
+    front_face: bool,
+    // 击中物体在场景列表中的序号
+    object_id: i32,
+    // 表面参数坐标
+    u: f64,
+    v: f64,
+    // 交点位置对参数坐标u、v的偏导数，即表面的切线方向，用于法线贴图与凹凸贴图
+    dpdu: Vec3,
+    dpdv: Vec3
 }
 
 impl HitRecord {
@@ -29,6 +38,11 @@ impl HitRecord {
             t,
             front_face,
             material,
+            object_id: -1,
+            u: 0.0,
+            v: 0.0,
+            dpdu: Vec3::default(),
+            dpdv: Vec3::default()
         }
     }
 
@@ -53,6 +67,29 @@ impl HitRecord {
         self.set_t(other.t);
         self.set_p(other.p);
         self.front_face = other.front_face;
+        self.object_id = other.object_id;
+        self.u = other.u;
+        self.v = other.v;
+        self.dpdu = other.dpdu;
+        self.dpdv = other.dpdv;
+    }
+
+    /*
+    复制一份只替换法向量的记录，法线贴图等用它把着色法向量交给材质，不修改几何法向量
+     */
+    pub fn with_normal(&self, normal: Vec3) -> HitRecord {
+        HitRecord {
+            p: self.p,
+            normal,
+            material: self.material.clone(),
+            t: self.t,
+            front_face: self.front_face,
+            object_id: self.object_id,
+            u: self.u,
+            v: self.v,
+            dpdu: self.dpdu,
+            dpdv: self.dpdv
+        }
     }
 
     pub fn set_t(&mut self, value: f64) {
@@ -86,6 +123,36 @@ impl HitRecord {
     pub fn get_front_face(&self)->bool {
         self.front_face
     }
+
+    pub fn set_object_id(&mut self, value: i32) {
+        self.object_id = value;
+    }
+
+    pub fn get_object_id(&self) -> i32 {
+        self.object_id
+    }
+
+    pub fn set_uv(&mut self, u: f64, v: f64) {
+        self.u = u;
+        self.v = v;
+    }
+
+    pub fn get_uv(&self) -> (f64, f64) {
+        (self.u, self.v)
+    }
+
+    pub fn set_tangents(&mut self, dpdu: Vec3, dpdv: Vec3) {
+        self.dpdu = dpdu;
+        self.dpdv = dpdv;
+    }
+
+    pub fn get_dpdu(&self) -> Vec3 {
+        self.dpdu
+    }
+
+    pub fn get_dpdv(&self) -> Vec3 {
+        self.dpdv
+    }
 }
 
 pub trait Hittable {
@@ -93,6 +160,11 @@ pub trait Hittable {
     Sphere是否有交集
      */
     fn hit(&self,r: &Ray, t_min: f64, t_max: f64, rec: &mut HitRecord) -> bool;
+
+    /*
+    包围盒，运动物体需覆盖整个运动过程
+     */
+    fn bounding_box(&self) -> AABB;
 }
 
 pub struct Sphere {
@@ -101,6 +173,8 @@ pub struct Sphere {
     is_moving: bool,
     r: f64,
     material: Rc<dyn Material>,
+    // 包围盒需要覆盖的快门区间，运动球体的球心在区间两端之间线性移动
+    shutter: (f64, f64)
 }
 
 impl Sphere{
@@ -110,7 +184,8 @@ impl Sphere{
             cen_vec: center2 - center,
             is_moving: true,
             r: radius,
-            material
+            material,
+            shutter: (0.0, 1.0)
         }
     }
 
@@ -120,10 +195,16 @@ impl Sphere{
             cen_vec: Point::default(),
             is_moving: false,
             r: radius,
-            material
+            material,
+            shutter: (0.0, 1.0)
         }
     }
 
+    // 相机的快门区间，须在加入HittableList之前设置
+    pub fn set_shutter(&mut self, open: f64, close: f64) {
+        self.shutter = (open, close);
+    }
+
     pub fn get_center(&self) ->Point {
         self.cen
     }
@@ -136,12 +217,42 @@ impl Sphere{
         self.r
     }
 
+    /*
+    单位球面上的点p对应的参数坐标：u为绕Y轴的角度(从X=-1开始)，v为从Y=-1到Y=1的角度，均归一化到[0,1]
+     */
+    pub fn get_sphere_uv(p: Point) -> (f64, f64) {
+        let theta = (-p.y()).acos();
+        let phi = (-p.z()).atan2(p.x()) + PI;
+        (phi / (2.0 * PI), theta / PI)
+    }
+
+    /*
+    半径为radius的球面上法向量为n的点对参数坐标(u, v)的偏导数。
+    两极处dpdu退化为0，dpdv处处非零
+     */
+    pub fn get_sphere_tangents(n: Vec3, radius: f64) -> (Vec3, Vec3) {
+        let phi = (-n.z()).atan2(n.x()) + PI;
+        let sin_theta = (1.0 - n.y() * n.y()).max(0.0).sqrt();
+        let dpdu = Vec3::new(n.z(), 0.0, -n.x()) * (2.0 * PI * radius);
+        let dpdv = Vec3::new(n.y() * phi.cos(), sin_theta, -n.y() * phi.sin()) * (PI * radius);
+        (dpdu, dpdv)
+    }
+
     fn set_record(&self, r: &Ray, t_min: f64, t_max: f64, rec: &mut HitRecord, temp: f64,center:Point) -> Option<bool> {
         if temp < t_max && temp > t_min {
-            rec.set_t(temp);
-            rec.set_p(r.at(temp));
-            rec.set_face_normal(r, (rec.get_p() - center) / self.get_radius());
-            rec.material = self.material.clone();
+            // 先写入临时记录，被透明遮罩跳过的交点不能改动rec中已有的最近交点
+            let mut candidate = HitRecord::new(r.at(temp), Vec3::default(), temp, false, self.material.clone());
+            let outward_normal = (candidate.get_p() - center) / self.get_radius();
+            candidate.set_face_normal(r, outward_normal);
+            let (u, v) = Sphere::get_sphere_uv(outward_normal);
+            candidate.set_uv(u, v);
+            let (dpdu, dpdv) = Sphere::get_sphere_tangents(outward_normal, self.get_radius());
+            candidate.set_tangents(dpdu, dpdv);
+            if !self.material.alpha_test(r, &candidate) {
+                return None;
+            }
+            candidate.set_object_id(rec.get_object_id());
+            *rec = candidate;
             return Some(true);
         }
         None
@@ -171,26 +282,53 @@ impl Hittable for Sphere {
             }
         }
 
-        return false;
+        false
+    }
+
+    fn bounding_box(&self) -> AABB {
+        let rvec = Vec3::new(self.r, self.r, self.r);
+        if !self.is_moving {
+            return AABB::new_with_bounding_box(self.cen - rvec, self.cen + rvec);
+        }
+        let (open, close) = self.shutter;
+        let center1 = self.get_moving_center(open);
+        let box0 = AABB::new_with_bounding_box(center1 - rvec, center1 + rvec);
+        let center2 = self.get_moving_center(close);
+        let box1 = AABB::new_with_bounding_box(center2 - rvec, center2 + rvec);
+        AABB::new_with_box(&box0, &box1)
     }
 }
 
 pub struct HittableList {
-    objects: Vec<Box<dyn Hittable>>
+    objects: Vec<Box<dyn Hittable>>,
+    bbox: AABB
+}
+
+impl Default for HittableList {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl HittableList {
     pub fn new()->HittableList {
         HittableList {
-            objects: Vec::new()
+            objects: Vec::new(),
+            bbox: AABB::default()
         }
     }
 
     pub fn clear(&mut self) {
-        self.objects.clear()
+        self.objects.clear();
+        self.bbox = AABB::default();
     }
 
     pub fn add(&mut self,object:Box<dyn Hittable>) {
+        self.bbox = if self.objects.is_empty() {
+            object.bounding_box()
+        } else {
+            AABB::new_with_box(&self.bbox, &object.bounding_box())
+        };
         self.objects.push(object)
     }
 
@@ -204,13 +342,112 @@ impl Hittable for HittableList {
         let mut hit_anything = false;
         let mut closest_so_far = t_max;
 
-        for object in self.get_objects().into_iter() {
+        for (index, object) in self.get_objects().iter().enumerate() {
             if object.hit(r,t_min,closest_so_far,rec) {
                 hit_anything = true;
                 closest_so_far = rec.get_t();
+                rec.set_object_id(index as i32);
             }
         }
 
-        return hit_anything;
+        hit_anything
+    }
+
+    fn bounding_box(&self) -> AABB {
+        self.bbox
+    }
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::common::INFINITY;
+    use crate::material::{AlphaMaskedMaterial, AlphaMode, Metal};
+    use crate::sampler::IndependentSampler;
+    use crate::texture::{ConstantTexture, Texture};
+
+    fn black() -> Rc<dyn Material> {
+        Rc::new(Lambertian::new(Color::default()))
+    }
+
+    #[test]
+    fn reflected_ray_hits_moving_sphere_at_its_time() {
+        let mirror = Metal::new(Color::new(1.0, 1.0, 1.0), 0.0);
+        let r_in = Ray::new(Point::new(0.0, 0.0, 1.0), Vec3::new(0.0, 0.0, -1.0), 0.5);
+        let rec = HitRecord::new(Point::default(), Vec3::new(0.0, 0.0, 1.0), 1.0, true, black());
+        let mut attenuation = Color::default();
+        let mut scattered = Ray::default();
+        assert!(mirror.scatter(&r_in, &rec, &mut attenuation, &mut scattered, &mut IndependentSampler::new()));
+        assert_eq!(scattered.get_time(), 0.5);
+
+        // 球心t=0时在(-4,0,5)，t=0.5时正好移动到反射光线上
+        let sphere = Sphere::new_moving_sphere(Point::new(-4.0, 0.0, 5.0), Point::new(4.0, 0.0, 5.0), 1.0, black());
+        let mut hit = HitRecord::new_default();
+        assert!(sphere.hit(&scattered, 0.001, INFINITY, &mut hit));
+        assert!((hit.get_p() - Point::new(0.0, 0.0, 4.0)).length() < 1e-9);
+        let early = Ray::new(Point::default(), Vec3::new(0.0, 0.0, 1.0), 0.0);
+        assert!(!sphere.hit(&early, 0.001, INFINITY, &mut hit));
+    }
+
+    #[test]
+    fn moving_sphere_bounds_cover_shutter() {
+        let mut sphere = Sphere::new_moving_sphere(Point::default(), Point::new(1.0, 0.0, 0.0), 0.5, black());
+        sphere.set_shutter(-1.0, 2.0);
+        let bbox = sphere.bounding_box();
+        assert!(bbox.axis(0).min <= -1.5 && bbox.axis(0).max >= 2.5);
+    }
+
+    // z<0的半边不透明，其余镂空
+    struct BackHalf;
+
+    impl Texture for BackHalf {
+        fn value(&self, _u: f64, _v: f64, p: Point) -> Color {
+            if p.z() < 0.0 { Color::new(1.0, 1.0, 1.0) } else { Color::default() }
+        }
+    }
+
+    fn masked(alpha: Rc<dyn Texture>, mode: AlphaMode) -> Rc<dyn Material> {
+        Rc::new(AlphaMaskedMaterial::new(black(), alpha, mode))
+    }
+
+    #[test]
+    fn masked_texel_lets_ray_pass_to_next_object() {
+        let mut world = HittableList::new();
+        world.add(Box::new(Sphere::new(Point::new(0.0, 0.0, 0.0), 1.0,
+                                       masked(Rc::new(ConstantTexture::new(Color::default())), AlphaMode::Threshold(0.5)))));
+        world.add(Box::new(Sphere::new(Point::new(0.0, 0.0, -4.0), 1.0, black())));
+        let ray = Ray::new(Point::new(0.0, 0.0, 5.0), Vec3::new(0.0, 0.0, -1.0), 0.0);
+        let mut rec = HitRecord::new_default();
+        assert!(world.hit(&ray, 0.001, INFINITY, &mut rec));
+        assert!((rec.get_t() - 8.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn masked_front_face_reveals_back_face() {
+        let sphere = Sphere::new(Point::default(), 1.0, masked(Rc::new(BackHalf), AlphaMode::Threshold(0.5)));
+        let mut rec = HitRecord::new_default();
+        let ray = Ray::new(Point::new(0.0, 0.0, 5.0), Vec3::new(0.0, 0.0, -1.0), 0.0);
+        assert!(sphere.hit(&ray, 0.001, INFINITY, &mut rec));
+        assert!((rec.get_t() - 6.0).abs() < 1e-9);
+        assert!(!rec.get_front_face());
+        // 从另一侧射入时先击中不透明的半边
+        let ray = Ray::new(Point::new(0.0, 0.0, -5.0), Vec3::new(0.0, 0.0, 1.0), 0.0);
+        assert!(sphere.hit(&ray, 0.001, INFINITY, &mut rec));
+        assert!((rec.get_t() - 4.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn stochastic_alpha_keeps_matching_fraction() {
+        let sphere = Sphere::new(Point::default(), 1.0,
+                                 masked(Rc::new(ConstantTexture::new(Color::new(0.3, 0.3, 0.3))), AlphaMode::Stochastic));
+        let mut rec = HitRecord::new_default();
+        // 两个交点各以0.3的概率保留
+        let hits = (0..4000).filter(|&k| {
+            let x = f64::from(k % 63) / 100.0 - 0.31;
+            let y = f64::from(k / 63) / 100.0 - 0.31;
+            let ray = Ray::new(Point::new(x, y, 5.0), Vec3::new(0.0, 0.0, -1.0), 0.0);
+            sphere.hit(&ray, 0.001, INFINITY, &mut rec)
+        }).count();
+        let expected = 4000.0 * (1.0 - 0.7 * 0.7);
+        assert!((hits as f64 - expected).abs() < 0.1 * expected, "{} hits", hits);
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,384 @@
+use std::collections::HashMap;
+use crate::camera::Camera;
+use crate::common::{hash, INFINITY, PI, seed_pixel_sample, seed_random};
+use crate::film::{Aov, Film, SampleAovs};
+use crate::integrator::{background, Integrator, PathIntegrator, record_first_hit, sample_lights};
+use crate::ray::Ray;
+use crate::sampler::{IndependentSampler, Sampler};
+use crate::scene::Scene;
+use crate::sphere::HitRecord;
+use crate::vec3::{Color, dot, Point};
+
+// 光子随机数流，与像素样本、场景生成的随机数互不相关
+const PHOTON_SEED_STREAM: u64 = u64::MAX - 1;
+// 每次迭代保留的新光子比例(论文中的alpha)
+const ALPHA: f64 = 2.0 / 3.0;
+
+/*
+相机路径上第一个非镜面交点，收集附近的光子
+ */
+struct VisiblePoint {
+    rec: HitRecord,
+    r_in: Ray,
+    // 相机到该点的路径通量
+    beta: Color
+}
+
+/*
+像素的渐进统计量：搜索半径随迭代缩小，tau为半径内累计的光子通量
+ */
+struct SppmPixel {
+    radius: f64,
+    // 累计的光子数(按alpha衰减)
+    n: f64,
+    tau: Color,
+    visible: Option<VisiblePoint>,
+    // 本次迭代收集到的通量与光子数
+    phi: Color,
+    m: i32
+}
+
+/*
+可见点的均匀哈希网格，每个可见点登记到其搜索球覆盖的所有单元
+ */
+struct VisiblePointGrid {
+    cell_size: f64,
+    cells: HashMap<(i64, i64, i64), Vec<usize>>
+}
+
+impl VisiblePointGrid {
+    fn new(pixels: &[SppmPixel]) -> VisiblePointGrid {
+        let max_radius = pixels.iter()
+            .filter(|pixel| pixel.visible.is_some())
+            .fold(0.0, |max: f64, pixel| max.max(pixel.radius));
+        let mut grid = VisiblePointGrid {
+            cell_size: (max_radius * 2.0).max(1e-6),
+            cells: HashMap::new()
+        };
+        for (index, pixel) in pixels.iter().enumerate() {
+            let p = match &pixel.visible {
+                Some(vp) => vp.rec.get_p(),
+                None => continue
+            };
+            let offset = Point::new(pixel.radius, pixel.radius, pixel.radius);
+            let (lo, hi) = (grid.cell(p - offset), grid.cell(p + offset));
+            for x in lo.0..=hi.0 {
+                for y in lo.1..=hi.1 {
+                    for z in lo.2..=hi.2 {
+                        grid.cells.entry((x, y, z)).or_default().push(index);
+                    }
+                }
+            }
+        }
+        grid
+    }
+
+    fn cell(&self, p: Point) -> (i64, i64, i64) {
+        ((p.x() / self.cell_size).floor() as i64,
+         (p.y() / self.cell_size).floor() as i64,
+         (p.z() / self.cell_size).floor() as i64)
+    }
+
+    fn get(&self, p: Point) -> &[usize] {
+        self.cells.get(&self.cell(p)).map_or(&[], |indices| indices.as_slice())
+    }
+}
+
+/*
+随机渐进式光子映射(SPPM)：每次迭代先从每个像素追踪一条相机路径，穿过镜面反射与折射直到第一个非镜面交点(可见点)，
+在那里计算直接光照；再从光源发射光子，光子在非镜面表面处把通量交给半径内的可见点。
+每次迭代后按alpha缩小各像素的搜索半径，结果随迭代次数收敛，能处理镜面-漫反射-镜面(SDS)路径产生的焦散。
+迭代次数为相机的每像素样本数；光子只从场景光源发出，自发光物体与默认渐变背景只贡献直接光照。
+场景没有光源时无法发射光子，改用路径追踪渲染
+ */
+pub struct SppmIntegrator {
+    max_depth: i32,
+    // 每次迭代发射的光子数，None时与像素数相同
+    photons_per_iteration: Option<usize>,
+    initial_radius: f64,
+    // 没有光源时使用的路径追踪
+    fallback: PathIntegrator
+}
+
+impl SppmIntegrator {
+    pub fn new(max_depth: i32, photons_per_iteration: Option<usize>, initial_radius: f64, fallback: PathIntegrator) -> SppmIntegrator {
+        SppmIntegrator {
+            max_depth,
+            photons_per_iteration,
+            initial_radius,
+            fallback
+        }
+    }
+
+    /*
+    追踪相机路径到第一个非镜面交点，返回沿途看到的自发光与该点的直接光照，以及该点处的可见点
+     */
+    fn trace_camera_path(&self, r: &Ray, scene: &Scene, sampler: &mut dyn Sampler, aovs: &mut SampleAovs) -> (Color, Option<VisiblePoint>) {
+        let mut ray = Ray::new(r.original(), r.direction(), r.get_time());
+        let mut beta = Color::new(1.0, 1.0, 1.0);
+        let mut ld = Color::default();
+        for depth in 0..self.max_depth {
+            let mut rec = HitRecord::new_default();
+            if !scene.get_world().hit(&ray, 0.001, INFINITY, &mut rec) {
+                // 只有相机光线与镜面散射光线会走到这里
+                let le = beta * background(&ray, scene, 0.0);
+                if depth == 0 { aovs.emission += le } else { aovs.direct += le }
+                ld += le;
+                break;
+            }
+            if depth == 0 {
+                record_first_hit(aovs, &ray, &rec);
+            }
+            let material = rec.get_material().clone();
+            let emitted = beta * material.emitted(&rec);
+            if depth == 0 { aovs.emission += emitted } else { aovs.direct += emitted }
+            ld += emitted;
+
+            let mut scattered = Ray::default();
+            let mut attenuation = Color::default();
+            if !material.scatter(&ray, &rec, &mut attenuation, &mut scattered, sampler) {
+                break;
+            }
+            let bsdf_pdf = material.pdf(&ray, &rec, scattered.direction());
+            if bsdf_pdf <= 0.0 {
+                beta *= attenuation;
+                ray = scattered;
+                continue;
+            }
+
+            // 非镜面表面：光源采样与材质采样按MIS结合的直接光照
+            let mut direct = beta * sample_lights(&ray, &rec, scene, sampler);
+            let mut light_rec = HitRecord::new_default();
+            direct += beta * attenuation * if scene.get_world().hit(&scattered, 0.001, INFINITY, &mut light_rec) {
+                light_rec.get_material().emitted(&light_rec)
+            } else {
+                // 组合材质可能采样到镜面分支，该方向无法被光源采样，不做MIS加权
+                background(&scattered, scene, if scattered.is_specular() { 0.0 } else { bsdf_pdf })
+            };
+            aovs.direct += direct;
+            ld += direct;
+            return (ld, Some(VisiblePoint { rec, r_in: ray, beta }));
+        }
+        (ld, None)
+    }
+
+    /*
+    发射一个光子并沿路径随机游走，第一次击中之后的每个交点把通量交给网格中半径内的可见点。
+    第一次击中对应直接光照，已由可见点的光源采样计算
+     */
+    fn trace_photon(&self, camera: &Camera, scene: &Scene, bounds: (Point, f64), sampler: &mut dyn Sampler,
+                    grid: &VisiblePointGrid, pixels: &mut [SppmPixel]) {
+        let lights = scene.get_lights();
+        let light_pdf = 1.0 / lights.len() as f64;
+        let index = ((sampler.get_1d() * lights.len() as f64) as usize).min(lights.len() - 1);
+        let (u1, u2) = (sampler.get_2d(), sampler.get_2d());
+        let emission = match lights[index].sample_le(u1, u2, bounds) {
+            Some(emission) if emission.pdf_pos > 0.0 && emission.pdf_dir > 0.0 && emission.le != Color::default() => emission,
+            _ => return
+        };
+        let mut beta = emission.le / (light_pdf * emission.pdf_pos * emission.pdf_dir);
+        let mut ray = Ray::new(emission.origin, emission.direction, camera.sample_time(sampler.get_1d()));
+
+        for depth in 0..self.max_depth {
+            let mut rec = HitRecord::new_default();
+            if !scene.get_world().hit(&ray, 0.001, INFINITY, &mut rec) {
+                break;
+            }
+            if depth > 0 {
+                let p = rec.get_p();
+                let wi = -ray.direction().unit_vector();
+                for &pixel_index in grid.get(p) {
+                    let pixel = &mut pixels[pixel_index];
+                    let vp = match &pixel.visible {
+                        Some(vp) => vp,
+                        None => continue
+                    };
+                    if (vp.rec.get_p() - p).length_squared() > pixel.radius * pixel.radius {
+                        continue;
+                    }
+                    // eval包含cos项，光子通量已是单位面积上的入射量，需除去
+                    let cosine = dot(vp.rec.get_normal(), wi);
+                    if cosine <= 0.0 {
+                        continue;
+                    }
+                    pixel.phi += beta * vp.rec.get_material().eval(&vp.r_in, &vp.rec, wi) / cosine;
+                    pixel.m += 1;
+                }
+            }
+
+            let material = rec.get_material().clone();
+            let mut scattered = Ray::default();
+            let mut attenuation = Color::default();
+            if !material.scatter(&ray, &rec, &mut attenuation, &mut scattered, sampler) {
+                break;
+            }
+            // 俄罗斯轮盘赌：按散射后通量的衰减比例终止，存活的光子保持通量大致不变
+            let new_beta = beta * attenuation;
+            let survival = if beta.luminance() > 0.0 { (new_beta.luminance() / beta.luminance()).min(1.0) } else { 0.0 };
+            if survival <= 0.0 || sampler.get_1d() >= survival {
+                break;
+            }
+            beta = new_beta / survival;
+            ray = scattered;
+        }
+    }
+}
+
+impl Integrator for SppmIntegrator {
+    // 单条相机光线只能得到可见点的直接光照，完整结果需通过render迭代光子
+    fn li(&self, r: &Ray, camera: &Camera, scene: &Scene, sampler: &mut dyn Sampler, aovs: &mut SampleAovs) -> Color {
+        if scene.get_lights().is_empty() {
+            return self.fallback.li(r, camera, scene, sampler, aovs);
+        }
+        self.trace_camera_path(r, scene, sampler, aovs).0
+    }
+
+    fn render(&self, camera: &Camera, scene: &Scene, aovs: &[Aov]) -> Option<Film> {
+        // 交给相机逐像素调用li，即路径追踪
+        if scene.get_lights().is_empty() {
+            return None;
+        }
+        let mut film = camera.new_film(aovs);
+        let (width, height) = (film.get_width(), film.get_height());
+        let iterations = camera.get_samples_per_pixel().max(1);
+        let photons = self.photons_per_iteration.unwrap_or((width * height) as usize);
+        let exposure = camera.get_exposure();
+        let bounds = scene.bounding_sphere();
+
+        let mut pixels: Vec<SppmPixel> = (0..width * height).map(|_| SppmPixel {
+            radius: self.initial_radius,
+            n: 0.0,
+            tau: Color::default(),
+            visible: None,
+            phi: Color::default(),
+            m: 0
+        }).collect();
+        let mut sampler = camera.new_sampler();
+        let mut photon_sampler = IndependentSampler::new();
+
+        for iteration in 0..iterations {
+            println!("Iterations remaining: {}", iterations - iteration);
+            for y in 0..height {
+                for x in 0..width {
+                    seed_pixel_sample(camera.get_seed(), x, y, iteration);
+                    sampler.start_sample(x, y, iteration);
+                    let (px, py) = camera.pixel_sample_square(sampler.as_mut());
+                    let mut sample_aovs = SampleAovs::new();
+                    let (ld, visible) = match camera.get_output_ray(x, y, (px, py), sampler.as_mut()) {
+                        Some(r) => self.trace_camera_path(&r, scene, sampler.as_mut(), &mut sample_aovs),
+                        None => (Color::default(), None)
+                    };
+                    sample_aovs.direct *= exposure;
+                    sample_aovs.emission *= exposure;
+                    film.add_sample(f64::from(x) + 0.5 + px, f64::from(y) + 0.5 + py, ld * exposure, camera.get_window(x, y));
+                    film.add_aovs(x, y, &sample_aovs);
+                    pixels[(y * width + x) as usize].visible = visible;
+                }
+            }
+
+            let grid = VisiblePointGrid::new(&pixels);
+            seed_random(camera.get_seed(), hash(PHOTON_SEED_STREAM, iteration as u64));
+            for _ in 0..photons {
+                self.trace_photon(camera, scene, bounds, &mut photon_sampler, &grid, &mut pixels);
+            }
+
+            // 只保留alpha比例的新光子，半径按光子数的增长比例缩小，累计通量随面积缩放
+            for pixel in pixels.iter_mut() {
+                if pixel.m > 0 {
+                    let n = pixel.n + ALPHA * f64::from(pixel.m);
+                    let radius = pixel.radius * (n / (pixel.n + f64::from(pixel.m))).sqrt();
+                    let beta = pixel.visible.as_ref().map_or(Color::default(), |vp| vp.beta);
+                    pixel.tau = (pixel.tau + beta * pixel.phi) * (radius * radius / (pixel.radius * pixel.radius));
+                    pixel.n = n;
+                    pixel.radius = radius;
+                }
+                pixel.visible = None;
+                pixel.phi = Color::default();
+                pixel.m = 0;
+            }
+        }
+
+        // 光子估计为每像素的密度估计，不经过重建滤波器
+        let total_photons = f64::from(iterations) * photons as f64;
+        for y in 0..height {
+            for x in 0..width {
+                let pixel = &pixels[(y * width + x) as usize];
+                let indirect = pixel.tau / (total_photons * PI * pixel.radius * pixel.radius);
+                film.add_splat(f64::from(x) + 0.5, f64::from(y) + 0.5, indirect * exposure);
+            }
+        }
+        Some(film)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::rc::Rc;
+    use super::*;
+    use crate::depth::DepthLimits;
+    use crate::integrator::DirectLightingIntegrator;
+    use crate::light::PointLight;
+    use crate::material::Lambertian;
+    use crate::sphere::{HittableList, Sphere};
+    use crate::vec3::Vec3;
+
+    // 白色背景下放在地面上的灰色球，没有光源
+    fn unlit_scene() -> Scene {
+        let mut world = HittableList::new();
+        let grey = Rc::new(Lambertian::new(Color::new(0.5, 0.5, 0.5)));
+        world.add(Box::new(Sphere::new(Point::new(0.0, -1000.0, 0.0), 1000.0, grey.clone())));
+        world.add(Box::new(Sphere::new(Point::new(0.0, 1.0, 0.0), 1.0, grey)));
+        let mut scene = Scene::new(Box::new(world));
+        scene.set_background(Some(Color::new(1.0, 1.0, 1.0)));
+        scene
+    }
+
+    fn camera(spp: i32, integrator: Box<dyn Integrator>) -> Camera {
+        let mut camera = Camera::new(8, 40.0, 1.0, spp, Point::new(0.0, 2.0, 5.0), Point::new(0.0, 1.0, 0.0),
+                                     Vec3::new(0.0, 1.0, 0.0), 5.0, 0.0);
+        camera.set_integrator(integrator);
+        camera
+    }
+
+    fn sppm() -> SppmIntegrator {
+        SppmIntegrator::new(8, Some(1024), 0.1, PathIntegrator::new(DepthLimits::default(), true))
+    }
+
+    #[test]
+    fn scene_without_lights_falls_back_to_path_tracing() {
+        let scene = unlit_scene();
+        assert!(sppm().render(&camera(4, Box::new(sppm())), &scene, &[]).is_none());
+
+        // 相同的像素种子下与路径追踪逐像素一致，包括地面与球之间的间接光照
+        let film = camera(4, Box::new(sppm())).render_film(&scene, &[]);
+        let expected = camera(4, Box::new(PathIntegrator::new(DepthLimits::default(), true))).render_film(&scene, &[]);
+        for y in 0..film.get_height() {
+            for x in 0..film.get_width() {
+                assert_eq!(film.get_pixel(x, y), expected.get_pixel(x, y));
+            }
+        }
+    }
+
+    #[test]
+    fn scene_with_lights_traces_photons() {
+        let mut scene = unlit_scene();
+        scene.set_background(Some(Color::default()));
+        scene.add_light(Box::new(PointLight::new(Point::new(0.0, 4.0, 2.0), Color::new(8.0, 8.0, 8.0))));
+        let mean = |film: Film| {
+            let mut sum = 0.0;
+            for y in 0..film.get_height() {
+                for x in 0..film.get_width() {
+                    sum += film.get_pixel(x, y).luminance();
+                }
+            }
+            sum / f64::from(film.get_width() * film.get_height())
+        };
+        let photons = mean(sppm().render(&camera(64, Box::new(sppm())), &scene, &[]).unwrap());
+        let path = mean(camera(256, Box::new(PathIntegrator::new(DepthLimits::default(), true))).render_film(&scene, &[]));
+        // 只有光源直接光照的估计
+        let direct = mean(camera(256, Box::new(DirectLightingIntegrator::new(1))).render_film(&scene, &[]));
+        // 光子补上了地面与球之间的间接光照
+        assert!((photons - path).abs() < 0.03 * path, "{} vs {}", photons, path);
+        assert!(photons - direct > 0.5 * (path - direct), "{} vs direct {}", photons, direct);
+    }
+}
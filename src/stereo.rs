@@ -0,0 +1,159 @@
+use crate::projection::CameraBasis;
+use crate::vec3::{cross, dot, Point, Vec3};
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Eye {
+    Left,
+    Right
+}
+
+/*
+左右眼图像的拼接方式
+ */
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum StereoLayout {
+    // 左眼在上，右眼在下
+    TopBottom,
+    // 左眼在左，右眼在右
+    SideBySide
+}
+
+impl StereoLayout {
+    pub fn from_name(name: &str) -> Option<StereoLayout> {
+        match name {
+            "top-bottom" | "tb" => Some(StereoLayout::TopBottom),
+            "side-by-side" | "sbs" => Some(StereoLayout::SideBySide),
+            _ => None
+        }
+    }
+}
+
+pub struct StereoRig {
+    // 瞳距，两眼之间的距离
+    interocular: f64,
+    // 会聚距离，此距离上左右眼视差为0；非有限值表示两眼视线平行
+    convergence: f64,
+    layout: StereoLayout
+}
+
+impl StereoRig {
+    pub fn new(interocular: f64, convergence: f64, layout: StereoLayout) -> StereoRig {
+        StereoRig {
+            interocular,
+            convergence,
+            layout
+        }
+    }
+
+    // 拼接后的输出图像尺寸
+    pub fn output_size(&self, width: i32, height: i32) -> (i32, i32) {
+        match self.layout {
+            StereoLayout::TopBottom => (width, height * 2),
+            StereoLayout::SideBySide => (width * 2, height)
+        }
+    }
+
+    // 输出图像中的像素 -> (眼睛, 单眼图像中的像素)
+    pub fn locate(&self, x: i32, y: i32, width: i32, height: i32) -> (Eye, i32, i32) {
+        match self.layout {
+            StereoLayout::TopBottom if y >= height => (Eye::Right, x, y - height),
+            StereoLayout::SideBySide if x >= width => (Eye::Right, x - width, y),
+            _ => (Eye::Left, x, y)
+        }
+    }
+
+    /*
+    将单眼相机光线偏移到指定眼睛。
+    普通投影沿相机u轴平移；全景投影使用全向立体(ODS)，偏移方向垂直于光线的水平分量。
+    平移后剪切光线方向，使其仍经过会聚距离处的同一点(离轴投影，不产生梯形畸变)
+     */
+    pub fn eye_ray(&self, eye: Eye, basis: &CameraBasis, panoramic: bool,
+                   origin: Point, direction: Vec3) -> (Point, Vec3) {
+        let half = if eye == Eye::Left { -self.interocular / 2.0 } else { self.interocular / 2.0 };
+
+        let (right, distance_scale) = if panoramic {
+            let horizontal = direction - basis.v * dot(direction, basis.v);
+            if horizontal.length_squared() < 1e-12 {
+                // 正上方/正下方没有确定的水平方向，两眼重合
+                return (origin, direction);
+            }
+            (cross(horizontal.unit_vector(), basis.v), direction.length())
+        } else {
+            (basis.u, dot(direction, -basis.w))
+        };
+
+        let offset = right * half;
+        if !self.convergence.is_finite() || self.convergence <= 0.0 || distance_scale <= 0.0 {
+            return (origin + offset, direction);
+        }
+
+        // 光线参数tc处到达会聚距离
+        let tc = self.convergence / distance_scale;
+        (origin + offset, direction - offset / tc)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::common::INFINITY;
+
+    // 看向-z的相机
+    fn basis() -> CameraBasis {
+        CameraBasis {
+            origin: Point::default(),
+            u: Vec3::new(1.0, 0.0, 0.0),
+            v: Vec3::new(0.0, 1.0, 0.0),
+            w: Vec3::new(0.0, 0.0, 1.0)
+        }
+    }
+
+    fn assert_close(a: Vec3, b: Vec3) {
+        assert!((a - b).length() < 1e-9, "{:?} != {:?}", a, b);
+    }
+
+    #[test]
+    fn layouts_split_the_output_image() {
+        assert_eq!(StereoLayout::from_name("sbs"), Some(StereoLayout::SideBySide));
+        assert_eq!(StereoLayout::from_name("anaglyph"), None);
+        let top_bottom = StereoRig::new(0.065, 2.0, StereoLayout::TopBottom);
+        assert_eq!(top_bottom.output_size(40, 30), (40, 60));
+        assert_eq!(top_bottom.locate(5, 29, 40, 30), (Eye::Left, 5, 29));
+        assert_eq!(top_bottom.locate(5, 30, 40, 30), (Eye::Right, 5, 0));
+        let side_by_side = StereoRig::new(0.065, 2.0, StereoLayout::SideBySide);
+        assert_eq!(side_by_side.output_size(40, 30), (80, 30));
+        assert_eq!(side_by_side.locate(45, 7, 40, 30), (Eye::Right, 5, 7));
+    }
+
+    #[test]
+    fn eyes_converge_at_convergence_distance() {
+        let rig = StereoRig::new(0.2, 3.0, StereoLayout::SideBySide);
+        let direction = Vec3::new(0.5, 0.25, -1.0);
+        let target = direction * 3.0;
+        let (left, left_dir) = rig.eye_ray(Eye::Left, &basis(), false, Point::default(), direction);
+        let (right, right_dir) = rig.eye_ray(Eye::Right, &basis(), false, Point::default(), direction);
+        assert_close(left, Point::new(-0.1, 0.0, 0.0));
+        assert_close(right, Point::new(0.1, 0.0, 0.0));
+        // 离轴投影：两眼光线在会聚平面(z = -3)上交于单眼光线的同一点
+        assert_close(left + left_dir * 3.0, target);
+        assert_close(right + right_dir * 3.0, target);
+
+        // 无限远会聚时两眼视线平行
+        let parallel = StereoRig::new(0.2, INFINITY, StereoLayout::SideBySide);
+        assert_close(parallel.eye_ray(Eye::Right, &basis(), false, Point::default(), direction).1, direction);
+    }
+
+    #[test]
+    fn panoramic_eyes_offset_perpendicular_to_view() {
+        let rig = StereoRig::new(0.2, 3.0, StereoLayout::TopBottom);
+        // 向前看时右眼在+x，向+x看时右眼在+z
+        let (forward, _) = rig.eye_ray(Eye::Right, &basis(), true, Point::default(), Vec3::new(0.0, 0.0, -1.0));
+        assert_close(forward, Point::new(0.1, 0.0, 0.0));
+        let (sideways, dir) = rig.eye_ray(Eye::Right, &basis(), true, Point::default(), Vec3::new(1.0, 0.0, 0.0));
+        assert_close(sideways, Point::new(0.0, 0.0, 0.1));
+        assert_close(sideways + dir * 3.0, Point::new(3.0, 0.0, 0.0));
+        // 正上方两眼重合
+        let (up, _) = rig.eye_ray(Eye::Left, &basis(), true, Point::default(), Vec3::new(0.0, 1.0, 0.0));
+        assert_close(up, Point::default());
+    }
+}
@@ -0,0 +1,178 @@
+use std::io;
+use std::rc::Rc;
+use crate::imageio::{Image, load_hdr_image, load_ppm};
+use crate::vec3::{Color, Point};
+
+/*
+纹理：按表面参数坐标(u, v)与交点位置p给出的颜色或标量(取亮度)
+ */
+pub trait Texture {
+    fn value(&self, u: f64, v: f64, p: Point) -> Color;
+}
+
+pub struct ConstantTexture {
+    color: Color
+}
+
+impl ConstantTexture {
+    pub fn new(color: Color) -> ConstantTexture {
+        ConstantTexture {
+            color
+        }
+    }
+}
+
+impl Texture for ConstantTexture {
+    fn value(&self, _u: f64, _v: f64, _p: Point) -> Color {
+        self.color
+    }
+}
+
+/*
+三维棋盘格：按交点位置在边长为scale的立方体格子间交替两种纹理，与参数化方式无关
+ */
+pub struct CheckerTexture {
+    scale: f64,
+    even: Rc<dyn Texture>,
+    odd: Rc<dyn Texture>
+}
+
+impl CheckerTexture {
+    pub fn new(scale: f64, even: Rc<dyn Texture>, odd: Rc<dyn Texture>) -> CheckerTexture {
+        CheckerTexture {
+            scale,
+            even,
+            odd
+        }
+    }
+}
+
+impl Texture for CheckerTexture {
+    fn value(&self, u: f64, v: f64, p: Point) -> Color {
+        let cell = (p.x() / self.scale).floor() + (p.y() / self.scale).floor() + (p.z() / self.scale).floor();
+        if (cell as i64).rem_euclid(2) == 0 {
+            self.even.value(u, v, p)
+        } else {
+            self.odd.value(u, v, p)
+        }
+    }
+}
+
+/*
+参数坐标上的棋盘格：u、v方向分别划分为u_count、v_count格，随物体一起移动与旋转
+ */
+pub struct UvCheckerTexture {
+    u_count: f64,
+    v_count: f64,
+    even: Rc<dyn Texture>,
+    odd: Rc<dyn Texture>
+}
+
+impl UvCheckerTexture {
+    pub fn new(u_count: f64, v_count: f64, even: Rc<dyn Texture>, odd: Rc<dyn Texture>) -> UvCheckerTexture {
+        UvCheckerTexture {
+            u_count,
+            v_count,
+            even,
+            odd
+        }
+    }
+}
+
+impl Texture for UvCheckerTexture {
+    fn value(&self, u: f64, v: f64, p: Point) -> Color {
+        let cell = (u * self.u_count).floor() + (v * self.v_count).floor();
+        if (cell as i64).rem_euclid(2) == 0 {
+            self.even.value(u, v, p)
+        } else {
+            self.odd.value(u, v, p)
+        }
+    }
+}
+
+/*
+图像纹理：u向右、v向上，超出[0,1]时重复平铺，双线性插值。
+像素值按文件中的数值使用(PPM不做gamma解码)，适合遮罩、高度等线性数据
+ */
+pub struct ImageTexture {
+    image: Image
+}
+
+impl ImageTexture {
+    pub fn new(image: Image) -> ImageTexture {
+        ImageTexture {
+            image
+        }
+    }
+
+    // 读取.ppm/.pgm或.pfm/.hdr图像
+    pub fn load(path: &str) -> io::Result<ImageTexture> {
+        let lower = path.to_lowercase();
+        let image = if lower.ends_with(".ppm") || lower.ends_with(".pgm") {
+            load_ppm(path)?
+        } else {
+            load_hdr_image(path)?
+        };
+        Ok(ImageTexture::new(image))
+    }
+
+    fn texel(&self, x: i64, y: i64) -> Color {
+        let (width, height) = (self.image.get_width() as i64, self.image.get_height() as i64);
+        self.image.get_pixel(x.rem_euclid(width) as usize, y.rem_euclid(height) as usize)
+    }
+}
+
+impl Texture for ImageTexture {
+    fn value(&self, u: f64, v: f64, _p: Point) -> Color {
+        if self.image.get_width() == 0 || self.image.get_height() == 0 {
+            return Color::default();
+        }
+        // 像素中心位于(x+0.5, y+0.5)，图像第0行在上方
+        let x = u * self.image.get_width() as f64 - 0.5;
+        let y = (1.0 - v) * self.image.get_height() as f64 - 0.5;
+        let (x0, y0) = (x.floor(), y.floor());
+        let (tx, ty) = (x - x0, y - y0);
+        let (x0, y0) = (x0 as i64, y0 as i64);
+        self.texel(x0, y0) * ((1.0 - tx) * (1.0 - ty)) + self.texel(x0 + 1, y0) * (tx * (1.0 - ty))
+            + self.texel(x0, y0 + 1) * ((1.0 - tx) * ty) + self.texel(x0 + 1, y0 + 1) * (tx * ty)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn constant(v: f64) -> Rc<dyn Texture> {
+        Rc::new(ConstantTexture::new(Color::new(v, v, v)))
+    }
+
+    #[test]
+    fn uv_checker_alternates_in_parameter_space() {
+        let checker = UvCheckerTexture::new(4.0, 2.0, constant(1.0), constant(0.0));
+        let p = Point::new(7.0, -3.0, 2.0);
+        assert_eq!(checker.value(0.1, 0.1, p).x(), 1.0);
+        assert_eq!(checker.value(0.3, 0.1, p).x(), 0.0);
+        assert_eq!(checker.value(0.3, 0.6, p).x(), 1.0);
+        // 与交点位置无关
+        assert_eq!(checker.value(0.3, 0.6, Point::default()), checker.value(0.3, 0.6, p));
+    }
+
+    #[test]
+    fn image_texture_interpolates_and_wraps() {
+        // 2×2图像：上一行(v=1附近)为0与1，下一行为2与3
+        let mut image = Image::new(2, 2);
+        for (x, y, v) in [(0, 0, 0.0), (1, 0, 1.0), (0, 1, 2.0), (1, 1, 3.0)] {
+            image.set_pixel(x, y, Color::new(v, v, v));
+        }
+        let texture = ImageTexture::new(image);
+        let p = Point::default();
+        // 像素中心取原值，v向上
+        assert!((texture.value(0.25, 0.75, p).x() - 0.0).abs() < 1e-12);
+        assert!((texture.value(0.75, 0.25, p).x() - 3.0).abs() < 1e-12);
+        // 四个像素中心之间为双线性平均
+        assert!((texture.value(0.5, 0.5, p).x() - 1.5).abs() < 1e-12);
+        // 超出[0,1]时平铺：u=0处在最右列与最左列之间插值
+        assert!((texture.value(0.0, 0.75, p).x() - 0.5).abs() < 1e-12);
+        assert_eq!(texture.value(1.25, 0.75, p), texture.value(0.25, 0.75, p));
+    }
+}
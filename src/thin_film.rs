@@ -0,0 +1,125 @@
+use crate::common::PI;
+use crate::vec3::Color;
+
+// RGB各通道的代表波长(纳米)
+const CHANNEL_WAVELENGTHS: [f64; 3] = [650.0, 532.0, 450.0];
+
+/*
+薄膜下方的基底
+ */
+#[derive(Debug, Copy, Clone)]
+pub enum Substrate {
+    // 电介质基底的折射率
+    Dielectric(f64),
+    // 金属基底：近似为各通道振幅为sqrt(反照率)、相位反转的反射
+    Reflector(Color)
+}
+
+// 界面i->j的s、p偏振振幅反射系数
+fn fresnel_amplitudes(ni: f64, nj: f64, cos_i: f64, cos_j: f64) -> (f64, f64) {
+    ((ni * cos_i - nj * cos_j) / (ni * cos_i + nj * cos_j),
+     (nj * cos_i - ni * cos_j) / (nj * cos_i + ni * cos_j))
+}
+
+/*
+两个界面之间多次反射的相干叠加(Airy公式)，delta为往返一次的相位差
+ */
+fn airy(r12: f64, r23: f64, delta: f64) -> f64 {
+    let c = 2.0 * r12 * r23 * delta.cos();
+    (r12 * r12 + r23 * r23 + c) / (1.0 + r12 * r12 * r23 * r23 + c)
+}
+
+/*
+薄膜干涉涂层：厚度与光波长相当的透明膜，上下表面的反射光相互干涉，
+反射率随波长与入射角变化，呈现肥皂泡、镀膜镜头上的彩虹色。
+RGB各通道按代表波长计算；光谱渲染时直接在路径的各波长处计算
+ */
+#[derive(Debug, Copy, Clone)]
+pub struct ThinFilm {
+    // 膜厚(纳米)
+    thickness: f64,
+    ior: f64
+}
+
+impl ThinFilm {
+    pub fn new(thickness: f64, ior: f64) -> ThinFilm {
+        ThinFilm {
+            thickness,
+            ior
+        }
+    }
+
+    /*
+    从折射率为n1的介质以入射角余弦cos_theta照射时，膜与基底整体在RGB代表波长处的反射率(s、p偏振平均)。
+    膜内或基底处发生全反射时反射率为1
+     */
+    pub fn reflectance(&self, cos_theta: f64, n1: f64, substrate: Substrate) -> Color {
+        self.reflectance_at(cos_theta, n1, substrate, CHANNEL_WAVELENGTHS)
+    }
+
+    // 在lambda给出的三个波长(纳米)处的反射率，金属基底的反照率按相同顺序给出
+    pub fn reflectance_at(&self, cos_theta: f64, n1: f64, substrate: Substrate, lambda: [f64; 3]) -> Color {
+        let sin_sq = 1.0 - cos_theta * cos_theta;
+        let film_sin_sq = (n1 / self.ior) * (n1 / self.ior) * sin_sq;
+        if film_sin_sq >= 1.0 {
+            return Color::new(1.0, 1.0, 1.0);
+        }
+        let film_cos = (1.0 - film_sin_sq).sqrt();
+        let (r12_s, r12_p) = fresnel_amplitudes(n1, self.ior, cos_theta, film_cos);
+
+        let mut result = [0.0; 3];
+        for (channel, value) in result.iter_mut().enumerate() {
+            let (r23_s, r23_p) = match substrate {
+                Substrate::Dielectric(n3) => {
+                    let sin3_sq = (n1 / n3) * (n1 / n3) * sin_sq;
+                    if sin3_sq >= 1.0 {
+                        (1.0, 1.0)
+                    } else {
+                        fresnel_amplitudes(self.ior, n3, film_cos, (1.0 - sin3_sq).sqrt())
+                    }
+                }
+                Substrate::Reflector(albedo) => {
+                    let r = -albedo.get(channel).unwrap_or(0.0).max(0.0).sqrt();
+                    (r, r)
+                }
+            };
+            // 光在膜内往返一次的光程差对应的相位
+            let delta = 4.0 * PI * self.ior * self.thickness * film_cos / lambda[channel];
+            *value = 0.5 * (airy(r12_s, r23_s, delta) + airy(r12_p, r23_p, delta));
+        }
+        Color::new(result[0], result[1], result[2])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rgb_reflectance_uses_channel_wavelengths() {
+        let film = ThinFilm::new(380.0, 1.33);
+        let rgb = film.reflectance(0.7, 1.0, Substrate::Dielectric(1.5));
+        assert_eq!(rgb, film.reflectance_at(0.7, 1.0, Substrate::Dielectric(1.5), CHANNEL_WAVELENGTHS));
+    }
+
+    #[test]
+    fn zero_thickness_matches_bare_fresnel() {
+        // 膜厚为0时等价于直接从空气照射基底：正入射 ((n-1)/(n+1))²
+        let film = ThinFilm::new(0.0, 1.8);
+        let r = film.reflectance_at(1.0, 1.0, Substrate::Dielectric(1.5), [400.0, 550.0, 700.0]);
+        for channel in 0..3 {
+            assert!((r.get(channel).unwrap() - 0.04).abs() < 1e-9);
+        }
+    }
+
+    #[test]
+    fn quarter_wave_coating_cancels_reflection() {
+        // 折射率为sqrt(1.5)、光学厚度为四分之一波长的增透膜在该波长处正入射反射率为0
+        let ior = 1.5f64.sqrt();
+        let film = ThinFilm::new(550.0 / (4.0 * ior), ior);
+        let r = film.reflectance_at(1.0, 1.0, Substrate::Dielectric(1.5), [550.0, 450.0, 700.0]);
+        assert!(r.x() < 1e-12);
+        assert!(r.y() > 1e-3 && r.z() > 1e-3);
+        assert!((0..3).all(|c| r.get(c).unwrap() < 0.04));
+    }
+}
@@ -0,0 +1,343 @@
+use crate::aabb::AABB;
+use crate::common::{clamp, degrees_to_radians};
+use crate::ray::Ray;
+use crate::sphere::{HitRecord, Hittable};
+use crate::vec3::{cross, dot, Point, Vec3};
+
+/*
+单位四元数，用于表示旋转并在关键帧之间做球面插值(slerp)
+ */
+#[derive(Debug, Copy, Clone)]
+pub struct Quaternion {
+    w: f64,
+    v: Vec3
+}
+
+impl Quaternion {
+    pub fn new(w: f64, x: f64, y: f64, z: f64) -> Quaternion {
+        Quaternion {
+            w,
+            v: Vec3::new(x, y, z)
+        }
+    }
+
+    pub fn identity() -> Quaternion {
+        Quaternion::new(1.0, 0.0, 0.0, 0.0)
+    }
+
+    pub fn from_axis_angle(axis: Vec3, degrees: f64) -> Quaternion {
+        let half = degrees_to_radians(degrees) / 2.0;
+        Quaternion {
+            w: half.cos(),
+            v: axis.unit_vector() * half.sin()
+        }
+    }
+
+    pub fn dot(&self, other: &Quaternion) -> f64 {
+        self.w * other.w + dot(self.v, other.v)
+    }
+
+    pub fn normalize(&self) -> Quaternion {
+        let len = self.dot(self).sqrt();
+        Quaternion {
+            w: self.w / len,
+            v: self.v / len
+        }
+    }
+
+    pub fn conjugate(&self) -> Quaternion {
+        Quaternion {
+            w: self.w,
+            v: -self.v
+        }
+    }
+
+    // 两个旋转之间的夹角(弧度)
+    pub fn angle_between(&self, other: &Quaternion) -> f64 {
+        2.0 * clamp(self.dot(other).abs(), 0.0, 1.0).acos()
+    }
+
+    pub fn rotate(&self, p: Vec3) -> Vec3 {
+        // p' = q p q*，展开后的向量形式
+        let t = cross(self.v, p) * 2.0;
+        p + t * self.w + cross(self.v, t)
+    }
+
+    pub fn slerp(a: &Quaternion, b: &Quaternion, t: f64) -> Quaternion {
+        let mut cos_theta = a.dot(b);
+        let mut end = *b;
+        // q 与 -q 表示同一旋转，取较短的弧
+        if cos_theta < 0.0 {
+            cos_theta = -cos_theta;
+            end = Quaternion { w: -b.w, v: -b.v };
+        }
+
+        if cos_theta > 0.9995 {
+            // 夹角很小时退化为线性插值，避免除以sin(θ)≈0
+            return Quaternion {
+                w: a.w + (end.w - a.w) * t,
+                v: a.v + (end.v - a.v) * t
+            }.normalize();
+        }
+
+        let theta = cos_theta.acos();
+        let sin_theta = theta.sin();
+        let wa = ((1.0 - t) * theta).sin() / sin_theta;
+        let wb = (t * theta).sin() / sin_theta;
+        Quaternion {
+            w: a.w * wa + end.w * wb,
+            v: a.v * wa + end.v * wb
+        }
+    }
+}
+
+/*
+平移、旋转、缩放组成的变换，作用顺序为 缩放 -> 旋转 -> 平移
+ */
+#[derive(Debug, Copy, Clone)]
+pub struct Transform {
+    translation: Vec3,
+    rotation: Quaternion,
+    scale: Vec3
+}
+
+impl Transform {
+    pub fn new(translation: Vec3, rotation: Quaternion, scale: Vec3) -> Transform {
+        Transform {
+            translation,
+            rotation: rotation.normalize(),
+            scale
+        }
+    }
+
+    pub fn identity() -> Transform {
+        Transform::new(Vec3::default(), Quaternion::identity(), Vec3::new(1.0, 1.0, 1.0))
+    }
+
+    pub fn translation(offset: Vec3) -> Transform {
+        Transform::new(offset, Quaternion::identity(), Vec3::new(1.0, 1.0, 1.0))
+    }
+
+    pub fn apply_point(&self, p: Point) -> Point {
+        self.rotation.rotate(p * self.scale) + self.translation
+    }
+
+    pub fn apply_vector(&self, v: Vec3) -> Vec3 {
+        self.rotation.rotate(v * self.scale)
+    }
+
+    pub fn apply_normal(&self, n: Vec3) -> Vec3 {
+        // 法向量需要乘以逆转置矩阵，对于 R*S 即为 R*S^-1
+        self.rotation.rotate(n / self.scale)
+    }
+
+    pub fn inverse_point(&self, p: Point) -> Point {
+        self.rotation.conjugate().rotate(p - self.translation) / self.scale
+    }
+
+    pub fn inverse_vector(&self, v: Vec3) -> Vec3 {
+        self.rotation.conjugate().rotate(v) / self.scale
+    }
+
+    pub fn lerp(a: &Transform, b: &Transform, t: f64) -> Transform {
+        Transform {
+            translation: a.translation + (b.translation - a.translation) * t,
+            rotation: Quaternion::slerp(&a.rotation, &b.rotation, t),
+            scale: a.scale + (b.scale - a.scale) * t
+        }
+    }
+}
+
+pub struct Keyframe {
+    time: f64,
+    transform: Transform
+}
+
+/*
+按时间排序的关键帧，时间超出范围时取首/尾关键帧
+ */
+pub struct AnimatedTransform {
+    keyframes: Vec<Keyframe>
+}
+
+impl Default for AnimatedTransform {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl AnimatedTransform {
+    pub fn new() -> AnimatedTransform {
+        AnimatedTransform {
+            keyframes: Vec::new()
+        }
+    }
+
+    pub fn new_static(transform: Transform) -> AnimatedTransform {
+        let mut animation = AnimatedTransform::new();
+        animation.add_keyframe(0.0, transform);
+        animation
+    }
+
+    pub fn add_keyframe(&mut self, time: f64, transform: Transform) {
+        let index = self.keyframes.partition_point(|k| k.time <= time);
+        self.keyframes.insert(index, Keyframe { time, transform });
+    }
+
+    pub fn interpolate(&self, time: f64) -> Transform {
+        let (first, last) = match (self.keyframes.first(), self.keyframes.last()) {
+            (Some(first), Some(last)) => (first, last),
+            _ => return Transform::identity()
+        };
+        if time <= first.time {
+            return first.transform;
+        }
+        if time >= last.time {
+            return last.transform;
+        }
+
+        let index = self.keyframes.partition_point(|k| k.time <= time);
+        let k0 = &self.keyframes[index - 1];
+        let k1 = &self.keyframes[index];
+        let t = (time - k0.time) / (k1.time - k0.time);
+        Transform::lerp(&k0.transform, &k1.transform, t)
+    }
+
+    pub fn motion_bounds(&self, bbox: &AABB) -> AABB {
+        // 对每段关键帧按旋转角度细分采样，把包围盒8个角点变换后合并
+        let corners = bbox.corners();
+        let mut result: Option<AABB> = None;
+        let mut add = |transform: &Transform| {
+            for corner in corners.iter() {
+                let p = transform.apply_point(*corner);
+                let b = AABB::new_with_bounding_box(p, p);
+                result = Some(match result {
+                    Some(r) => AABB::new_with_box(&r, &b),
+                    None => b
+                });
+            }
+        };
+
+        for keyframe in self.keyframes.iter() {
+            add(&keyframe.transform);
+        }
+        for pair in self.keyframes.windows(2) {
+            let angle = pair[0].transform.rotation.angle_between(&pair[1].transform.rotation);
+            let steps = ((angle / degrees_to_radians(5.0)).ceil() as i32).max(1);
+            for step in 1..steps {
+                let t = f64::from(step) / f64::from(steps);
+                add(&Transform::lerp(&pair[0].transform, &pair[1].transform, t));
+            }
+        }
+
+        result.unwrap_or(*bbox)
+    }
+}
+
+/*
+按关键帧运动的物体，任意Hittable都可以包装成运动物体产生运动模糊
+ */
+pub struct AnimatedInstance {
+    object: Box<dyn Hittable>,
+    animation: AnimatedTransform,
+    bbox: AABB
+}
+
+impl AnimatedInstance {
+    pub fn new(object: Box<dyn Hittable>, animation: AnimatedTransform) -> AnimatedInstance {
+        let bbox = animation.motion_bounds(&object.bounding_box());
+        AnimatedInstance {
+            object,
+            animation,
+            bbox
+        }
+    }
+}
+
+impl Hittable for AnimatedInstance {
+    fn hit(&self, r: &Ray, t_min: f64, t_max: f64, rec: &mut HitRecord) -> bool {
+        // 将光线变换到物体空间求交，方向不归一化因此t值保持不变
+        let transform = self.animation.interpolate(r.get_time());
+        let local_ray = Ray::new(transform.inverse_point(r.original()),
+                                 transform.inverse_vector(r.direction()),
+                                 r.get_time());
+        if !self.object.hit(&local_ray, t_min, t_max, rec) {
+            return false;
+        }
+
+        rec.set_p(transform.apply_point(rec.get_p()));
+        rec.set_normal(transform.apply_normal(rec.get_normal()).unit_vector());
+        rec.set_tangents(transform.apply_vector(rec.get_dpdu()), transform.apply_vector(rec.get_dpdv()));
+        true
+    }
+
+    fn bounding_box(&self) -> AABB {
+        self.bbox
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::rc::Rc;
+    use super::*;
+    use crate::camera::Camera;
+    use crate::material::Lambertian;
+    use crate::scene::Scene;
+    use crate::sphere::{HittableList, Sphere};
+    use crate::vec3::Color;
+
+    // 黑色小球t=0时在画面外，t=1时移动到画面中心，返回中心像素的亮度
+    fn render_center(open: f64, close: f64) -> f64 {
+        let mut animation = AnimatedTransform::new();
+        animation.add_keyframe(0.0, Transform::translation(Vec3::new(5.0, 0.0, 0.0)));
+        animation.add_keyframe(1.0, Transform::identity());
+        let sphere = Sphere::new(Point::default(), 0.5, Rc::new(Lambertian::new(Color::default())));
+        let mut world = HittableList::new();
+        world.add(Box::new(AnimatedInstance::new(Box::new(sphere), animation)));
+        let scene = Scene::new(Box::new(world));
+
+        let mut camera = Camera::new(9, 20.0, 1.0, 64, Point::new(0.0, 0.0, 5.0), Point::default(),
+                                     Vec3::new(0.0, 1.0, 0.0), 5.0, 0.0);
+        camera.set_shutter(open, close);
+        camera.render_film(&scene, &[]).get_pixel(4, 4).luminance()
+    }
+
+    #[test]
+    fn animated_transform_covers_motion() {
+        let mut animation = AnimatedTransform::new();
+        animation.add_keyframe(0.0, Transform::identity());
+        animation.add_keyframe(1.0, Transform::new(Vec3::new(2.0, 0.0, 0.0),
+                                                   Quaternion::from_axis_angle(Vec3::new(0.0, 1.0, 0.0), 90.0),
+                                                   Vec3::new(1.0, 1.0, 1.0)));
+        let mid = animation.interpolate(0.5).apply_point(Vec3::new(1.0, 0.0, 0.0));
+        let expected = Vec3::new(1.0 + (45f64).to_radians().cos(), 0.0, -(45f64).to_radians().sin());
+        assert!((mid - expected).length() < 1e-9);
+
+        let bbox = AABB::new_with_bounding_box(Vec3::new(-1.0, -1.0, -1.0), Vec3::new(1.0, 1.0, 1.0));
+        let bounds = animation.motion_bounds(&bbox);
+        assert!(bounds.axis(0).min <= -1.0 && bounds.axis(0).max >= 3.0);
+    }
+
+    #[test]
+    fn animated_instance_transforms_tangents() {
+        let animation = AnimatedTransform::new_static(Transform::new(Vec3::default(),
+            Quaternion::from_axis_angle(Vec3::new(0.0, 0.0, 1.0), 90.0), Vec3::new(1.0, 1.0, 1.0)));
+        let instance = AnimatedInstance::new(Box::new(Sphere::new(Point::default(), 1.0, Rc::new(Lambertian::new(Color::default())))), animation);
+        // 旋转后物体空间的(0,0,1)点仍在+z方向，其dpdu由+x转到+y
+        let ray = Ray::new(Point::new(0.0, 0.0, 5.0), Vec3::new(0.0, 0.0, -1.0), 0.0);
+        let mut rec = HitRecord::new_default();
+        assert!(instance.hit(&ray, 0.001, f64::INFINITY, &mut rec));
+        let dpdu = rec.get_dpdu().unit_vector();
+        assert!((dpdu - Vec3::new(0.0, 1.0, 0.0)).length() < 1e-9);
+    }
+
+    #[test]
+    fn animated_instance_renders_at_shutter_time() {
+        let background = render_center(0.0, 0.0);
+        assert!(background > 0.1);
+        assert!(render_center(1.0, 1.0) < 1e-6);
+        // 球只在快门末尾约10%的时间内挡住中心像素
+        let blurred = render_center(0.0, 1.0);
+        assert!(blurred > 0.5 * background && blurred < background);
+    }
+}
@@ -71,18 +71,42 @@ impl Vec3 {
         self.length_squared().sqrt()
     }
 
+    // 作为线性RGB颜色时的亮度(Rec.709系数)
+    pub fn luminance(&self) -> f64 {
+        0.2126 * self.e[0] + 0.7152 * self.e[1] + 0.0722 * self.e[2]
+    }
+
     pub fn unit_vector(&self)->Vec3 {
-        return *self / self.length();
+        *self / self.length()
     }
 
     pub fn random_in_unit_sphere() -> Vec3 {
+        Vec3::sample_unit_sphere((get_random_double(), get_random_double()))
+    }
+
+    // 由[0,1)^2样本得到单位球面上均匀分布的方向
+    pub fn sample_unit_sphere(u: (f64, f64)) -> Vec3 {
         // lambertian分布率系数应该为cosφ
-        let a = random_double(0.0, PI * 2.0);
-        let z = random_double(-1.0, 1.0);
-        let r = (1.0 - z * z).sqrt();
+        let a = PI * 2.0 * u.0;
+        let z = 1.0 - 2.0 * u.1;
+        let r = (1.0 - z * z).max(0.0).sqrt();
         Vec3::new(r * a.cos(), r * a.sin(), z)
     }
 
+    // 由[0,1)^2样本得到单位圆盘内均匀分布的点(Shirley同心圆映射，保持采样的分层性)
+    pub fn sample_unit_disk(u: (f64, f64)) -> Vec3 {
+        let (a, b) = (2.0 * u.0 - 1.0, 2.0 * u.1 - 1.0);
+        if a == 0.0 && b == 0.0 {
+            return Vec3::default();
+        }
+        let (r, theta) = if a.abs() > b.abs() {
+            (a, PI / 4.0 * (b / a))
+        } else {
+            (b, PI / 2.0 - PI / 4.0 * (a / b))
+        };
+        Vec3::new(r * theta.cos(), r * theta.sin(), 0.0)
+    }
+
     /*
     半球面反射
     */
@@ -91,7 +115,7 @@ impl Vec3 {
         if dot(in_unit_sphere, *self) > 0.0 {
             return in_unit_sphere;
         }
-        return -in_unit_sphere;
+        -in_unit_sphere
     }
 
     pub fn write_color<W: Write>(&self, stream: &mut W, samples_per_pixel: i32) -> io::Result<()> {
@@ -113,11 +137,11 @@ impl Vec3 {
         let cos_theta = dot(-uv, n);
         let r_out_parallel = (uv + n * cos_theta) * etai_over_etat;
         let r_out_perp = n * (-(1.0 - r_out_parallel.length_squared()).sqrt());
-        return r_out_parallel + r_out_perp;
+        r_out_parallel + r_out_perp
     }
 
     pub fn reflect(v: Vec3, n: Vec3) -> Vec3 {
-        return v - n * (dot(v, n) * 2.0);
+        v - n * (dot(v, n) * 2.0)
     }
 
     pub fn schlick(cosine:f64,ref_idx:f64)->f64 {
@@ -326,7 +350,7 @@ impl PartialEq for Vec3 {
         if self.x() == other.x() && self.y() == other.y() && self.z() == other.z() {
             return true
         }
-        return  false
+        false
     }
 }
 
@@ -334,15 +358,15 @@ pub fn dot(u:Vec3,v:Vec3)->f64 {
     let Vec3 { e: [x, y, z] } = u;
     let Vec3 { e: [x1, y1, z1] } = v;
 
-    return x * x1 + y * y1 + z * z1;
+    x * x1 + y * y1 + z * z1
 }
 
 pub fn cross(u:Vec3,v:Vec3) -> Vec3 {
     let Vec3 { e: [x, y, z] } = u;
     let Vec3 { e: [x1, y1, z1] } = v;
 
-    return Vec3::new(
+    Vec3::new(
         y * z1 - z * y1,
         z * x1 - x * z1,
-        x * y1 - y * x1);
+        x * y1 - y * x1)
 }
\ No newline at end of file